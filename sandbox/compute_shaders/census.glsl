@@ -0,0 +1,9 @@
+#version 450
+
+#include "includes.glsl"
+
+// Histogram over matter ids, the census buffer is cleared before
+void main() {
+  ivec2 pos = get_current_sim_pos();
+  atomicAdd(census[read_matter(pos).matter], uint(1));
+}
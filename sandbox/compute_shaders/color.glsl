@@ -2,6 +2,8 @@
 
 #include "includes.glsl"
 
+const vec3 AWAKE_TILE_TINT = vec3(1.0, 0.0, 1.0);
+
 // 0-1 linear  from  0-255 sRGB
 vec3 linear_from_srgb(vec3 srgb) {
   bvec3 cutoff = lessThan(srgb, vec3(10.31475));
@@ -21,7 +23,12 @@ void write_color_to_image(ivec2 pos) {
   // (only way to ImageStore), thus we need to convert the colors to linear
   // space. We are assuming that images Are already in SRGB color space. When we
   // render, the linear gets interpreted as SRGB.
-  write_image_color(pos, linear_from_srgba(matter_color_to_vec4(matter.color)));
+  vec4 color = matter_color(matter);
+  // Debug overlay, tint tiles the movement kernels ran on
+  if(push_constants.show_awake_tiles && tile_awake[get_tile_index(pos)] != uint(0)) {
+    color.rgb = mix(color.rgb, AWAKE_TILE_TINT, 0.3);
+  }
+  write_image_color(pos, linear_from_srgba(color));
 }
 
 void main() { write_color_to_image(get_current_sim_pos()); }
\ No newline at end of file
@@ -2,25 +2,6 @@
 
 #include "includes.glsl"
 
-vec4 vary_color_rgb(vec4 color, ivec2 seed_pos) {
-  // Just use the same seed (means same color for individual xy position)
-  float seed = 0.1;
-  float p = rand(seed_pos, seed);
-  float variation = -0.1 + 0.15 * p;
-  color.rgb += vec3(variation);
-  return color;
-}
-
-// 3. Convert uint to vec4, randomize rgb a bit, convert back
-uint variate_color(ivec2 pos, uint color) {
-  vec4 color_f32 = matter_color_to_vec4(color);
-  vec4 variated_color_f32 = vary_color_rgb(color_f32, pos);
-  uint rgb = ((uint(variated_color_f32.r * 255.0) & uint(255)) << uint(16)) |
-             ((uint(variated_color_f32.g * 255.0) & uint(255)) << uint(8)) |
-             (uint(variated_color_f32.b * 255.0) & uint(255));
-  return rgb;
-}
-
 // 2. Check if current pixel is within radius from draw position (closest point
 // on line)
 void draw_matter_circle(ivec2 pos, ivec2 draw_pos, float radius, Matter matter) {
@@ -32,8 +13,8 @@ void draw_matter_circle(ivec2 pos, ivec2 draw_pos, float radius, Matter matter)
     vec2 diff = vec2(pos) - vec2(draw_pos);
     float dist = length(diff);
     if(round(dist) <= radius) {
-      // 3. Vary color only if not empty
-      if(!is_empty(matter)) { matter.color = variate_color(pos, matter.color); }
+      // 3. Vary color per cell
+      matter.variation = variation_at(pos);
 
       // 4. write matter to input buffer
       write_matter_input(pos, matter);
@@ -47,8 +28,8 @@ void draw_matter_square(ivec2 pos, ivec2 draw_pos, int size, Matter matter) {
   int x_start = draw_pos.x - size / 2;
   int x_end = draw_pos.x + size / 2;
   if(pos.x >= x_start && pos.x <= x_end && pos.y >= y_start && pos.y <= y_end) {
-    // 3. Vary color only if not empty
-    if(!is_empty(matter)) { matter.color = variate_color(pos, matter.color); }
+    // 3. Vary color per cell
+    matter.variation = variation_at(pos);
 
     // 4. write matter to input buffer
     write_matter_input(pos, matter);
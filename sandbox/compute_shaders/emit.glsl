@@ -0,0 +1,49 @@
+#version 450
+
+#include "includes.glsl"
+
+// Emitters (see `Emitter::to_kernel_data`), EMITTER_STRIDE values each: canvas position, radius,
+// rate, matter, flags
+#define EMITTER_STRIDE 6
+#define EMITTER_SINK 1
+#define EMITTER_SQUARE 2
+
+// Same shapes as draw_matter.glsl
+bool is_inside_emitter(ivec2 pos, ivec2 center, float radius, bool is_square) {
+  ivec2 diff = abs(pos - center);
+  if(is_square) {
+    int half_size = int(radius * 2.0) / 2;
+    return diff.x <= half_size && diff.y <= half_size;
+  }
+  return round(length(vec2(diff))) <= radius;
+}
+
+// Every source & sink over this cell, in order. Sources fill empty cells, sinks delete their
+// matter (any matter if theirs is empty). Each with a chance of `rate` per step.
+void emit(ivec2 pos) {
+  Matter current = read_matter(pos);
+  for(uint i = uint(0); i < push_constants.num_emitters; i++) {
+    uint base = i * uint(EMITTER_STRIDE);
+    ivec2 center = ivec2(int(emitters[base]), int(emitters[base + uint(1)]));
+    float radius = uintBitsToFloat(emitters[base + uint(2)]);
+    float rate = uintBitsToFloat(emitters[base + uint(3)]);
+    uint matter = emitters[base + uint(4)];
+    uint flags = emitters[base + uint(5)];
+    if(!is_inside_emitter(pos, center, radius, (flags & uint(EMITTER_SQUARE)) != uint(0))) { continue; }
+    // Offset from the seeds of react.glsl
+    if(rand(pos, push_constants.seed + float(i) + 0.5) >= rate) { continue; }
+
+    bool is_sink = (flags & uint(EMITTER_SINK)) != uint(0);
+    if(is_sink && !is_empty(current) && (matter == empty_matter || current.matter == matter)) {
+      current = new_matter(empty_matter);
+    } else if(!is_sink && is_empty(current)) {
+      current = new_matter(matter);
+    } else {
+      continue;
+    }
+    current.variation = variation_at(pos);
+    write_matter_input(pos, current);
+  }
+}
+
+void main() { emit(get_current_sim_pos()); }
@@ -13,4 +13,4 @@ void fall_empty(ivec2 pos)
   write_matter(pos, m);
 }
 
-void main() { fall_empty(get_current_sim_pos()); }
\ No newline at end of file
+void main() { fall_empty(get_awake_sim_pos()); }
\ No newline at end of file
@@ -58,4 +58,4 @@ void cellular_automata_move_horizontal_empty(ivec2 pos) {
   }
 }
 
-void main() { cellular_automata_move_horizontal_empty(get_current_sim_pos()); }
\ No newline at end of file
+void main() { cellular_automata_move_horizontal_empty(get_awake_sim_pos()); }
\ No newline at end of file
@@ -18,4 +18,4 @@ void cellular_automata_rise_empty(ivec2 pos) {
   write_matter(pos, m);
 }
 
-void main() { cellular_automata_rise_empty(get_current_sim_pos()); }
\ No newline at end of file
+void main() { cellular_automata_rise_empty(get_awake_sim_pos()); }
\ No newline at end of file
@@ -46,4 +46,4 @@ void cellular_automata_slide_down_empty(ivec2 pos) {
   }
 }
 
-void main() { cellular_automata_slide_down_empty(get_current_sim_pos()); }
\ No newline at end of file
+void main() { cellular_automata_slide_down_empty(get_awake_sim_pos()); }
\ No newline at end of file
@@ -0,0 +1,43 @@
+#version 450
+
+#include "includes.glsl"
+
+// Heat flows from & to the direct neighbors. Between two cells the lower conductivity limits the
+// flow, heat capacity slows down how fast the temperature changes. Stable for conductivities up to
+// 1 and heat capacities from 1.
+float diffuse_heat(ivec2 pos, Matter current) {
+  float flow = 0.0;
+  for(int dir = UP; dir <= LEFT; dir += 2) {
+    Matter neighbor = get_neighbor(pos, dir);
+    float conductivity = min(matter_conductivity[current.matter], matter_conductivity[neighbor.matter]);
+    flow += conductivity * (neighbor.temperature - current.temperature);
+  }
+  return current.temperature + 0.25 * flow / matter_heat_capacity[current.matter];
+}
+
+// Matter melts & boils at or above, freezes at or below its transition temperatures. The first
+// transition that happens wins.
+Matter transition(ivec2 pos, Matter current) {
+  for(int i = 0; i < NUM_TEMPERATURE_TRANSITIONS; i++) {
+    uint index = current.matter * uint(NUM_TEMPERATURE_TRANSITIONS) + uint(i);
+    float temperature = matter_transition_point[index];
+    bool crossed =
+        i == TRANSITION_FREEZES ? current.temperature <= temperature : current.temperature >= temperature;
+    if(crossed) {
+      Matter m = new_matter(matter_transition_becomes[index]);
+      m.variation = variation_at(pos);
+      // Keeps its heat
+      m.temperature = current.temperature;
+      return m;
+    }
+  }
+  return current;
+}
+
+void heat(ivec2 pos) {
+  Matter current = read_matter(pos);
+  current.temperature = diffuse_heat(pos, current);
+  write_matter(pos, transition(pos, current));
+}
+
+void main() { heat(get_current_sim_pos()); }
@@ -2,7 +2,7 @@
 Specialization constants
 */
 layout(constant_id = 0) const uint empty_matter = 1;
-layout(constant_id = 1) const int sim_canvas_size = 1;
+layout(constant_id = 1) const int sim_canvas_width = 1;
 layout(constant_id = 2) const uint state_empty = 1;
 layout(constant_id = 3) const uint state_powder = 1;
 layout(constant_id = 4) const uint state_liquid = 1;
@@ -10,6 +10,7 @@ layout(constant_id = 5) const uint state_solid = 1;
 layout(constant_id = 6) const uint state_solid_gravity = 1;
 layout(constant_id = 7) const uint state_gas = 1;
 layout(local_size_x_id = 8, local_size_y_id = 9, local_size_z = 1) in;
+layout(constant_id = 10) const int sim_canvas_height = 1;
 
 /*
 Buffers
@@ -17,13 +18,55 @@ Buffers
 layout(set = 0, binding = 0) restrict buffer MatterStateBuffer { uint matter_state[]; };
 layout(set = 0, binding = 1) restrict buffer MatterWeightsBuffer { float matter_weights[]; };
 layout(set = 0, binding = 2) restrict buffer MatterDispersionBuffer { uint matter_dispersion[]; };
+layout(set = 0, binding = 7) restrict buffer MatterCharacteristicsBuffer { uint matter_characteristics[]; };
+layout(set = 0, binding = 12) restrict buffer MatterColorBuffer { uint matter_colors[]; };
+/*
+Reaction tables, MAX_TRANSITIONS entries per matter (index: matter * MAX_TRANSITIONS + i)
+*/
+#define MAX_TRANSITIONS 5
+layout(set = 0, binding = 8) restrict buffer MatterReactionReactsBuffer { uint matter_reaction_reacts[]; };
+layout(set = 0, binding = 9) restrict buffer MatterReactionDirectionBuffer { uint matter_reaction_direction[]; };
+layout(set = 0, binding = 10) restrict buffer MatterReactionProbabilityBuffer { float matter_reaction_probability[]; };
+layout(set = 0, binding = 11) restrict buffer MatterReactionBecomesBuffer { uint matter_reaction_becomes[]; };
+/*
+Heat tables, and NUM_TEMPERATURE_TRANSITIONS transitions per matter: melts, boils, freezes
+(index: matter * NUM_TEMPERATURE_TRANSITIONS + i)
+*/
+#define NUM_TEMPERATURE_TRANSITIONS 3
+#define TRANSITION_FREEZES 2
+layout(set = 0, binding = 15) restrict buffer MatterConductivityBuffer { float matter_conductivity[]; };
+layout(set = 0, binding = 16) restrict buffer MatterHeatCapacityBuffer { float matter_heat_capacity[]; };
+layout(set = 0, binding = 17) restrict buffer MatterTemperatureBuffer { float matter_temperature[]; };
+layout(set = 0, binding = 18) restrict buffer MatterTransitionPointBuffer { float matter_transition_point[]; };
+layout(set = 0, binding = 19) restrict buffer MatterTransitionBecomesBuffer { uint matter_transition_becomes[]; };
+layout(set = 0, binding = 22) restrict buffer MatterLifetimeBuffer { uint matter_lifetime[]; };
 /*
 Matter data chunks
 */
 layout(set = 0, binding = 3) restrict buffer MatterInBuffer { uint matter_in[]; };
 layout(set = 0, binding = 4) restrict writeonly buffer MatterOutBuffer { uint matter_out[]; };
 layout(set = 0, binding = 5) restrict writeonly buffer QueryMatterBuffer { uint query_matter[]; };
+layout(set = 0, binding = 13) restrict buffer TemperatureInBuffer { float temperature_in[]; };
+layout(set = 0, binding = 14) restrict writeonly buffer TemperatureOutBuffer { float temperature_out[]; };
+layout(set = 0, binding = 20) restrict buffer CellDataInBuffer { uint cell_data_in[]; };
+layout(set = 0, binding = 21) restrict writeonly buffer CellDataOutBuffer { uint cell_data_out[]; };
 layout(set = 0, binding = 6, rgba8) restrict uniform writeonly image2D canvas_img;
+/*
+Tiles, one per work group (index: tile.y * num_tiles_x + tile.x)
+*/
+layout(set = 0, binding = 23) restrict buffer TileActivityBuffer { uint tile_activity[]; };
+layout(set = 0, binding = 24) restrict buffer TileAwakeBuffer { uint tile_awake[]; };
+layout(set = 0, binding = 25) restrict buffer AwakeTilesBuffer { uint awake_tiles[]; };
+// Indirect dispatch size of the movement kernels, one work group per awake tile
+layout(set = 0, binding = 26) restrict buffer AwakeTileDispatchBuffer {
+  uint awake_tile_count;
+  uint awake_tile_dispatch_y;
+  uint awake_tile_dispatch_z;
+};
+// Persistent sources & sinks, see emit.glsl
+layout(set = 0, binding = 27) restrict readonly buffer EmittersBuffer { uint emitters[]; };
+// Cells per matter id, see census.glsl
+layout(set = 0, binding = 28) restrict buffer CensusBuffer { uint census[]; };
 
 layout(push_constant) uniform PushConstants {
   float seed;
@@ -37,5 +80,9 @@ layout(push_constant) uniform PushConstants {
   uint move_step;
   uint dispersion_step;
   bool is_square;
+  bool show_awake_tiles;
+  // Per edge, indexed by EDGE_*: bits 0-7 BOUNDARY_* mode, bits 16-31 matter of source edges
+  uint boundaries[4];
+  uint num_emitters;
 }
 push_constants;
\ No newline at end of file
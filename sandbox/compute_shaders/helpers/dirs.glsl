@@ -10,6 +10,14 @@ Grid Directions
 #define DOWN_LEFT 6
 #define LEFT 7
 
+/*
+Canvas edges
+*/
+#define EDGE_TOP 0
+#define EDGE_RIGHT 1
+#define EDGE_BOTTOM 2
+#define EDGE_LEFT 3
+
 /*
 Neighbor Directions
 */
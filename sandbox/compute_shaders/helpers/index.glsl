@@ -1,5 +1,5 @@
 #include "definition.glsl"
 #include "dirs.glsl"
+#include "rand.glsl"
 #include "matter.glsl"
-#include "query.glsl"
-#include "rand.glsl"
\ No newline at end of file
+#include "query.glsl"
\ No newline at end of file
@@ -1,21 +1,49 @@
 struct Matter
 {
-  uint color;
+  uint variation;
   uint state;
   uint matter;
   float weight;
   uint dispersion;
+  uint characteristics;
+  float temperature;
+  uint data;
 };
 
+/*
+Cell data, moves along with its matter (same layout as `CellData`):
+- bits 0-7: lifetime, steps left until the matter becomes empty (0 lives forever)
+- bits 8-15: velocity x, signed
+- bits 16-23: velocity y, signed
+- bits 24-31: flags, free for kernels to use
+*/
+uint cell_lifetime(uint data) { return data & uint(255); }
+ivec2 cell_velocity(uint data) { return ivec2(bitfieldExtract(int(data), 8, 8), bitfieldExtract(int(data), 16, 8)); }
+uint cell_flags(uint data) { return data >> uint(24); }
+uint with_lifetime(uint data, uint lifetime) { return (data & ~uint(255)) | (lifetime & uint(255)); }
+
+/*
+Matter cell (same layout as `MatterCell`):
+- bits 0-15: matter id
+- bits 16-23: color variation of the cell, 0-255
+*/
 Matter new_matter(uint matter)
 {
   Matter m;
-  m.matter = (matter & uint(255));
-  m.color = matter >> uint(8);
+  m.matter = (matter & uint(65535));
+  m.variation = (matter >> uint(16)) & uint(255);
   m.state = matter_state[m.matter];
   m.weight = matter_weights[m.matter];
   m.dispersion = matter_dispersion[m.matter];
+  m.characteristics = matter_characteristics[m.matter];
+  // New matter, read_matter replaces these with the temperature & data of the cell
+  m.temperature = matter_temperature[m.matter];
+  m.data = with_lifetime(uint(0), matter_lifetime[m.matter]);
   return m;
 }
 
-uint matter_to_uint(Matter matter) { return ((matter.color << uint(8)) | matter.matter); }
\ No newline at end of file
+// Color variation for a cell. Just use the same seed (means same variation for individual xy
+// position)
+uint variation_at(ivec2 pos) { return uint(rand(pos, 0.1) * 255.0) & uint(255); }
+
+uint matter_to_uint(Matter matter) { return ((matter.variation << uint(16)) | matter.matter); }
\ No newline at end of file
@@ -1,36 +1,102 @@
 // Get the index of the matter in the matter_in array.
-int get_index(ivec2 pos) { return pos.y * sim_canvas_size + pos.x; }
+int get_index(ivec2 pos) { return pos.y * sim_canvas_width + pos.x; }
 
 // Get the current position of the thread.
 ivec2 get_current_sim_pos() { return ivec2(gl_GlobalInvocationID.xy); }
 
+/*
+TILES
+Kernels mark tiles where matter changes as active. Active tiles & their neighbors are awake for the
+next step, movement kernels are dispatched over awake tiles only (see tiles.glsl).
+*/
+int num_tiles_x() { return sim_canvas_width / int(gl_WorkGroupSize.x); }
+int num_tiles_y() { return sim_canvas_height / int(gl_WorkGroupSize.y); }
+int get_tile_index(ivec2 pos) {
+  ivec2 tile = pos / ivec2(gl_WorkGroupSize.xy);
+  return tile.y * num_tiles_x() + tile.x;
+}
+void mark_tile_active(ivec2 pos) { tile_activity[get_tile_index(pos)] = uint(1); }
+
+// Get the current position of the thread, for kernels dispatched over the awake tiles.
+ivec2 get_awake_sim_pos() {
+  int tile = int(awake_tiles[gl_WorkGroupID.x]);
+  ivec2 tile_pos = ivec2(tile % num_tiles_x(), tile / num_tiles_x());
+  return tile_pos * ivec2(gl_WorkGroupSize.xy) + ivec2(gl_LocalInvocationID.xy);
+}
+
 // Is the current thread inside the simulation canvas?
 bool is_inside_sim_canvas(ivec2 pos) {
-  return pos.x >= 0 && pos.x < sim_canvas_size && pos.y >= 0 && pos.y < sim_canvas_size;
+  return pos.x >= 0 && pos.x < sim_canvas_width && pos.y >= 0 && pos.y < sim_canvas_height;
 }
 
-// Get the position of the neighbor in the given direction.
-ivec2 get_pos_at_dir(ivec2 pos, int dir) { return pos + OFFSETS[dir]; }
+/*
+BOUNDARIES
+What lies beyond each canvas edge. Only walls stop matter. Wrapping edges come in pairs
+(left & right, top & bottom), off-canvas cells read as the cells across the canvas. Void reads as
+empty, so matter moving out is deleted. Source reads as its matter, which keeps moving in.
+*/
+#define BOUNDARY_WALL 0
+#define BOUNDARY_WRAP 1
+#define BOUNDARY_VOID 2
+#define BOUNDARY_SOURCE 3
+uint boundary_mode(int edge) { return push_constants.boundaries[edge] & uint(255); }
+uint boundary_source(int edge) { return push_constants.boundaries[edge] >> uint(16); }
+
+// Edge an off-canvas position lies beyond. Beyond a corner counts as beyond the top or bottom.
+int edge_beyond(ivec2 pos) {
+  if(pos.y >= sim_canvas_height) { return EDGE_TOP; }
+  if(pos.y < 0) { return EDGE_BOTTOM; }
+  if(pos.x >= sim_canvas_width) { return EDGE_RIGHT; }
+  return EDGE_LEFT;
+}
+
+// Bring positions beyond wrapping edges back onto the canvas
+ivec2 wrap_pos(ivec2 pos) {
+  if((pos.x < 0 || pos.x >= sim_canvas_width) && boundary_mode(EDGE_LEFT) == BOUNDARY_WRAP) {
+    pos.x = (pos.x % sim_canvas_width + sim_canvas_width) % sim_canvas_width;
+  }
+  if((pos.y < 0 || pos.y >= sim_canvas_height) && boundary_mode(EDGE_BOTTOM) == BOUNDARY_WRAP) {
+    pos.y = (pos.y % sim_canvas_height + sim_canvas_height) % sim_canvas_height;
+  }
+  return pos;
+}
+
+// Get the position of the neighbor in the given direction, across wrapping edges.
+ivec2 get_pos_at_dir(ivec2 pos, int dir) { return wrap_pos(pos + OFFSETS[dir]); }
 
 /*
 MATTER POSITION QUERIES
 */
-Matter read_matter(ivec2 pos) { return new_matter(matter_in[get_index(pos)]); }
-bool is_at_border_top(ivec2 pos) { return pos.y == sim_canvas_size - 1; }
-bool is_at_border_bottom(ivec2 pos) { return pos.y == 0; }
-bool is_at_border_right(ivec2 pos) { return pos.x == sim_canvas_size - 1; }
-bool is_at_border_left(ivec2 pos) { return pos.x == 0; }
+Matter read_matter(ivec2 pos) {
+  Matter m = new_matter(matter_in[get_index(pos)]);
+  m.temperature = temperature_in[get_index(pos)];
+  m.data = cell_data_in[get_index(pos)];
+  return m;
+}
+// At a wall, which matter can't move across
+bool is_at_border_top(ivec2 pos) {
+  return pos.y == sim_canvas_height - 1 && boundary_mode(EDGE_TOP) == BOUNDARY_WALL;
+}
+bool is_at_border_bottom(ivec2 pos) { return pos.y == 0 && boundary_mode(EDGE_BOTTOM) == BOUNDARY_WALL; }
+bool is_at_border_right(ivec2 pos) {
+  return pos.x == sim_canvas_width - 1 && boundary_mode(EDGE_RIGHT) == BOUNDARY_WALL;
+}
+bool is_at_border_left(ivec2 pos) { return pos.x == 0 && boundary_mode(EDGE_LEFT) == BOUNDARY_WALL; }
 
 // | 0 1 2 |
 // | 7 x 3 |
 // | 6 5 4 |
 Matter get_neighbor(ivec2 pos, int dir) {
   ivec2 neighbor_pos = get_pos_at_dir(pos, dir);
-  if(is_inside_sim_canvas(neighbor_pos)) {
-    return read_matter(neighbor_pos);
-  } else {
-    return new_matter(empty_matter);
+  if(is_inside_sim_canvas(neighbor_pos)) { return read_matter(neighbor_pos); }
+
+  int edge = edge_beyond(neighbor_pos);
+  if(boundary_mode(edge) == BOUNDARY_SOURCE) {
+    Matter m = new_matter(boundary_source(edge));
+    m.variation = variation_at(neighbor_pos);
+    return m;
   }
+  return new_matter(empty_matter);
 }
 
 /*
@@ -111,4 +177,21 @@ bool slides_on_swap(Matter from_diagonal, Matter to_diagonal, Matter from_down)
 
 bool rises_on_swap(Matter from, Matter to) {
   return is_gas(from) && (is_liquid(to) || is_powder(to)) && to.weight > from.weight;
-}
\ No newline at end of file
+}
+
+/*
+MATTER REACTION QUERIES
+*/
+
+/// Does a neighbor in one of the direction bits have any of the characteristics? No
+/// characteristics means the reaction needs no neighbor at all (e.g. matter that dies over time)
+bool reacts_with_neighbor(ivec2 pos, uint characteristics, uint direction) {
+  if(characteristics == uint(0)) { return true; }
+  for(int dir = 0; dir < 8; dir++) {
+    if((direction & (uint(1) << uint(dir))) != uint(0) &&
+       (get_neighbor(pos, dir).characteristics & characteristics) != uint(0)) {
+      return true;
+    }
+  }
+  return false;
+}
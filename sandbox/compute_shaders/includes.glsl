@@ -5,10 +5,38 @@ Utility functions to be used in the various kernels:
 */
 
 void write_query_matter(Matter matter) { query_matter[0] = matter_to_uint(matter); }
-void write_matter(ivec2 pos, Matter matter) { matter_out[get_index(pos)] = matter_to_uint(matter); }
-void write_matter_input(ivec2 pos, Matter matter) { matter_in[get_index(pos)] = matter_to_uint(matter); }
+// Temperature & cell data are written along with matter, so moving matter carries them. Changed
+// matter wakes up its tile.
+void write_matter(ivec2 pos, Matter matter) {
+  uint value = matter_to_uint(matter);
+  if(value != matter_in[get_index(pos)]) { mark_tile_active(pos); }
+  matter_out[get_index(pos)] = value;
+  temperature_out[get_index(pos)] = matter.temperature;
+  cell_data_out[get_index(pos)] = matter.data;
+}
+void write_matter_input(ivec2 pos, Matter matter) {
+  uint value = matter_to_uint(matter);
+  if(value != matter_in[get_index(pos)]) { mark_tile_active(pos); }
+  matter_in[get_index(pos)] = value;
+  temperature_in[get_index(pos)] = matter.temperature;
+  cell_data_in[get_index(pos)] = matter.data;
+}
 void write_image_color(ivec2 pos, vec4 color) { imageStore(canvas_img, pos, color); }
 vec4 matter_color_to_vec4(uint color) {
   return vec4(float((color >> uint(16)) & uint(255)) / 255.0, float((color >> uint(8)) & uint(255)) / 255.0,
               float(color & uint(255)) / 255.0, 1.0);
 }
+
+vec4 vary_color_rgb(vec4 color, uint variation) {
+  float p = float(variation) / 255.0;
+  color.rgb += vec3(-0.1 + 0.15 * p);
+  return color;
+}
+
+// Color of the matter from its definition, randomized a bit by the cell's variation. Empty is not
+// varied
+vec4 matter_color(Matter matter) {
+  vec4 color = matter_color_to_vec4(matter_colors[matter.matter]);
+  if(is_empty(matter)) { return color; }
+  return vary_color_rgb(color, matter.variation);
+}
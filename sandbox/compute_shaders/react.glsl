@@ -0,0 +1,42 @@
+#version 450
+
+#include "includes.glsl"
+
+// Count down lifetime, matter that runs out of it becomes empty
+Matter age(Matter current) {
+  uint lifetime = cell_lifetime(current.data);
+  if(lifetime == uint(0)) { return current; }
+  if(lifetime > uint(1)) {
+    current.data = with_lifetime(current.data, lifetime - uint(1));
+    return current;
+  }
+  Matter m = new_matter(empty_matter);
+  // Keeps its heat
+  m.temperature = current.temperature;
+  return m;
+}
+
+// Matter becomes `becomes` with `probability` when a neighbor in `direction` has any of the
+// `reacts` characteristics. The first reaction that happens wins, over running out of lifetime.
+void react(ivec2 pos) {
+  Matter current = read_matter(pos);
+  Matter m = age(current);
+  for(int i = 0; i < MAX_TRANSITIONS; i++) {
+    uint index = current.matter * uint(MAX_TRANSITIONS) + uint(i);
+    float probability = matter_reaction_probability[index];
+    if(probability <= 0.0) { continue; }
+
+    float p = rand(pos, push_constants.seed + float(i));
+    if(p < probability &&
+       reacts_with_neighbor(pos, matter_reaction_reacts[index], matter_reaction_direction[index])) {
+      m = new_matter(matter_reaction_becomes[index]);
+      m.variation = variation_at(pos);
+      // Keeps its heat
+      m.temperature = current.temperature;
+      break;
+    }
+  }
+  write_matter(pos, m);
+}
+
+void main() { react(get_current_sim_pos()); }
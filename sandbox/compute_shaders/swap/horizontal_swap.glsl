@@ -54,4 +54,4 @@ void cellular_automata_move_horizontal_swap(ivec2 pos) {
   }
 }
 
-void main() { cellular_automata_move_horizontal_swap(get_current_sim_pos()); }
\ No newline at end of file
+void main() { cellular_automata_move_horizontal_swap(get_awake_sim_pos()); }
\ No newline at end of file
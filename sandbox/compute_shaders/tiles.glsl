@@ -0,0 +1,30 @@
+#version 450
+
+#include "includes.glsl"
+
+// Wake up active tiles & their neighbors, and list them for the indirect dispatch of the movement
+// kernels. One thread per tile, `awake_tile_count` is reset before. Tiles at wrapping edges
+// neighbor those across the canvas.
+void wake_tile(ivec2 tile) {
+  if(tile.x >= num_tiles_x() || tile.y >= num_tiles_y()) { return; }
+
+  bool wraps_x = boundary_mode(EDGE_LEFT) == BOUNDARY_WRAP;
+  bool wraps_y = boundary_mode(EDGE_BOTTOM) == BOUNDARY_WRAP;
+  uint awake = uint(0);
+  for(int y = -1; y <= 1; y++) {
+    for(int x = -1; x <= 1; x++) {
+      ivec2 neighbor = tile + ivec2(x, y);
+      if(wraps_x) { neighbor.x = (neighbor.x + num_tiles_x()) % num_tiles_x(); }
+      if(wraps_y) { neighbor.y = (neighbor.y + num_tiles_y()) % num_tiles_y(); }
+      if(neighbor.x >= 0 && neighbor.x < num_tiles_x() && neighbor.y >= 0 && neighbor.y < num_tiles_y()) {
+        awake |= tile_activity[neighbor.y * num_tiles_x() + neighbor.x];
+      }
+    }
+  }
+
+  int index = tile.y * num_tiles_x() + tile.x;
+  tile_awake[index] = awake;
+  if(awake != uint(0)) { awake_tiles[atomicAdd(awake_tile_count, uint(1))] = uint(index); }
+}
+
+void main() { wake_tile(ivec2(gl_GlobalInvocationID.xy)); }
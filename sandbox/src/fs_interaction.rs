@@ -1,15 +1,28 @@
 pub mod asset_loading;
+pub mod chunk_file;
 pub mod config;
+pub mod export;
 pub mod file_utils;
+pub mod image_import;
+pub mod snapshot;
 use bevy_fn_plugin::bevy_plugin;
 pub use file_utils::*;
 
-use crate::fs_interaction::asset_loading::LoadingPlugin;
+use crate::fs_interaction::{
+    asset_loading::LoadingPlugin, export::ExportPlugin, image_import::ImageImportPlugin,
+    snapshot::SnapshotPlugin,
+};
 
 /// Handles loading and saving of levels and save states to disk.
 /// Split into the following sub-plugins:
 /// - [`loading_plugin`] handles loading of assets.
+/// - [`snapshot_plugin`] handles saving and loading of world snapshots.
+/// - [`image_import_plugin`] handles importing images as matter layouts.
+/// - [`export_plugin`] handles screenshots and gif recordings of the canvas.
 #[bevy_plugin]
 pub fn FileSystemPlugin(app: &mut App) {
-    app.add_plugin(LoadingPlugin);
+    app.add_plugin(LoadingPlugin)
+        .add_plugin(SnapshotPlugin)
+        .add_plugin(ImageImportPlugin)
+        .add_plugin(ExportPlugin);
 }
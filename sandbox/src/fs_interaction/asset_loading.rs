@@ -1,70 +1,239 @@
 pub mod assets;
+pub mod matter_definition_file;
+
+use std::{collections::HashSet, path::Path};
 
 use anyhow::{Context, Result};
-use bevy::{prelude::*, utils::HashMap};
+use bevy::{asset::LoadState, prelude::*};
 use bevy_asset_loader::prelude::*;
-use bevy_common_assets::{ron::RonAssetPlugin, toml::TomlAssetPlugin};
+use bevy_common_assets::toml::TomlAssetPlugin;
 use bevy_fn_plugin::bevy_plugin;
 use bevy_mod_sysfail::macros::*;
 use iyes_progress::{Progress, ProgressPlugin};
 
 use crate::{
-    fs_interaction::config::GameConfig,
+    fs_interaction::{config::GameConfig, FileUtils},
     matter::{
-        matter_definition::{validate_matter_definitions, MatterDefinitions},
+        default_matter_definitions,
+        matter_definition::{
+            parse_matter_definitions, MatterDefinitionDiagnostic, MatterDefinitionError,
+            MatterDefinitions,
+        },
         Matters, MATTER_DEFINITION_FILE,
     },
     GameState,
 };
 
-use self::assets::SimulationAssets;
+use self::{
+    assets::SimulationAssets,
+    matter_definition_file::{MatterDefinitionFile, MatterDefinitionFileLoader},
+};
 
 #[bevy_plugin]
 pub fn LoadingPlugin(app: &mut App) {
     use iyes_progress::ProgressSystem;
 
     app.add_plugin(TomlAssetPlugin::<GameConfig>::new(&["game.toml"]))
-        .add_plugin(RonAssetPlugin::<MatterDefinitions>::new(&["matter.ron"]))
+        .add_asset::<MatterDefinitionFile>()
+        .init_asset_loader::<MatterDefinitionFileLoader>()
         .add_plugin(ProgressPlugin::new(GameState::Loading).continue_to(GameState::Simulating))
         .add_loading_state(LoadingState::new(GameState::Loading))
         .add_collection_to_loading_state::<_, SimulationAssets>(GameState::Loading)
+        .add_event::<MatterDefinitionsChanged>()
+        .init_resource::<MatterDefinitionErrors>()
         // Update after loading
         .add_systems((
             update_config,
+            load_matter_definitions_asset
+                .run_if(resource_added::<GameConfig>())
+                .run_if(in_state(GameState::Loading)),
             wait_for_config
                 .track_progress()
-                .run_if(resource_added::<GameConfig>())
+                .run_if(resource_exists::<GameConfig>())
                 .run_if(in_state(GameState::Loading)),
+            reload_matter_definitions.run_if(in_state(GameState::Simulating)),
         ));
 }
 
-fn wait_for_config(mut commands: Commands, config: Res<GameConfig>) -> Progress {
-    let definition_path = if let Some(path) = &config.definition_path {
-        path
-    } else {
-        MATTER_DEFINITION_FILE
-    };
+/// Folder the asset server loads from. Matter definitions in it are loaded as an asset, so they
+/// are reloaded when the file changes (with the `native-dev` file watcher).
+pub const ASSET_DIR: &str = "assets";
 
-    let matter_definitions =
-        if let Some(defs) = crate::utils::read_matter_definitions_file(definition_path) {
-            defs
-        } else {
-            crate::matter::default_matter_definitions()
-        };
+/// Matter definitions were replaced, e.g. by the matter window or by reloading their file.
+/// `id_map` holds the new id of each old id if ids changed, matter without one is gone.
+pub struct MatterDefinitionsChanged {
+    pub id_map: Option<Vec<Option<u32>>>,
+}
+
+/// Problems found the last time the matter definition file was loaded, kept until they're
+/// dismissed or the file loads cleanly. The simulation keeps using the last good definitions
+/// meanwhile, or the built in ones if there were none.
+#[derive(Resource, Default)]
+pub struct MatterDefinitionErrors {
+    pub path: String,
+    pub diagnostics: Vec<MatterDefinitionDiagnostic>,
+}
+
+impl MatterDefinitionErrors {
+    /// Log each diagnostic as `path:line:col: error` and keep them for the error window
+    pub fn report(&mut self, path: &str, diagnostics: Vec<MatterDefinitionDiagnostic>) {
+        for diagnostic in diagnostics.iter() {
+            error!("{}:{}", path, diagnostic);
+        }
+        self.path = path.to_string();
+        self.diagnostics = diagnostics;
+    }
 
-    validate_matter_definitions(&matter_definitions);
+    pub fn clear(&mut self) {
+        self.diagnostics.clear();
+    }
+}
+
+/// The matter definition file asset, `None` if the definitions don't come from a file in
+/// `ASSET_DIR`
+#[derive(Resource)]
+struct MatterDefinitionsHandle(Option<Handle<MatterDefinitionFile>>);
+
+fn load_matter_definitions_asset(
+    mut commands: Commands,
+    config: Res<GameConfig>,
+    asset_server: Res<AssetServer>,
+) {
+    let definition_path = Path::new(matter_definition_path(&config));
+    let handle = match definition_path.strip_prefix(ASSET_DIR) {
+        Ok(asset_path) if definition_path.exists() => Some(asset_server.load(asset_path)),
+        _ => None,
+    };
+    commands.insert_resource(MatterDefinitionsHandle(handle));
+}
 
-    let mut matters = HashMap::new();
-    for definition in matter_definitions.definitions.iter() {
-        matters.insert(definition.id, definition.name.clone());
+fn wait_for_config(
+    mut commands: Commands,
+    mut is_done: Local<bool>,
+    config: Res<GameConfig>,
+    handle: Option<Res<MatterDefinitionsHandle>>,
+    asset_server: Res<AssetServer>,
+    files: Res<Assets<MatterDefinitionFile>>,
+    mut errors: ResMut<MatterDefinitionErrors>,
+) -> Progress {
+    if *is_done {
+        return true.into();
     }
+    let Some(handle) = handle else { return false.into() };
 
-    commands.insert_resource(Matters(matters));
+    let loaded = match &handle.0 {
+        Some(handle) => match asset_server.get_load_state(handle) {
+            LoadState::Loaded => parse_matter_definitions(&files.get(handle).unwrap().text),
+            // Read the file directly, or fall back to the built in definitions
+            LoadState::Failed => load_matter_definitions(&config),
+            _ => return false.into(),
+        },
+        None => load_matter_definitions(&config),
+    };
+    let matter_definitions = loaded.unwrap_or_else(|diagnostics| {
+        errors.report(matter_definition_path(&config), diagnostics);
+        warn!("Using the built in matter definitions");
+        default_matter_definitions()
+    });
+
+    commands.insert_resource(Matters::from_definitions(&matter_definitions));
     commands.insert_resource(matter_definitions);
 
+    *is_done = true;
     true.into()
 }
 
+/// Replace the matter definitions when their asset changes. Matter keeps its name, so cells are
+/// remapped by name, see `reload_id_map`. Definitions with problems are reported and the current
+/// ones are kept.
+fn reload_matter_definitions(
+    handle: Res<MatterDefinitionsHandle>,
+    config: Res<GameConfig>,
+    files: Res<Assets<MatterDefinitionFile>>,
+    mut asset_events: EventReader<AssetEvent<MatterDefinitionFile>>,
+    mut matter_definitions: ResMut<MatterDefinitions>,
+    mut errors: ResMut<MatterDefinitionErrors>,
+    mut changed_events: EventWriter<MatterDefinitionsChanged>,
+) {
+    let Some(handle) = &handle.0 else { return };
+    for event in asset_events.iter() {
+        let AssetEvent::Modified { handle: modified } = event else { continue };
+        if modified != handle {
+            continue;
+        }
+        let Some(file) = files.get(handle) else { continue };
+        let reloaded = match parse_matter_definitions(&file.text) {
+            Ok(reloaded) => reloaded,
+            Err(diagnostics) => {
+                errors.report(matter_definition_path(&config), diagnostics);
+                warn!("Keeping the current matter definitions");
+                continue;
+            }
+        };
+        errors.clear();
+
+        let id_map = reload_id_map(&matter_definitions, &reloaded);
+        let keeps_ids = id_map
+            .iter()
+            .enumerate()
+            .all(|(old, new)| *new == Some(old as u32));
+        *matter_definitions = reloaded;
+        info!("Reloaded matter definitions");
+        changed_events.send(MatterDefinitionsChanged {
+            id_map: (!keeps_ids).then_some(id_map),
+        });
+    }
+}
+
+/// New id of each old matter by name. Matter whose name is gone keeps its id if no other matter
+/// moved there, i.e. it was renamed in place, otherwise it was removed.
+fn reload_id_map(old: &MatterDefinitions, new: &MatterDefinitions) -> Vec<Option<u32>> {
+    let mut id_map = new.id_map_by_name(old);
+    let claimed = id_map.iter().flatten().copied().collect::<HashSet<u32>>();
+    for (old_id, new_id) in id_map.iter_mut().enumerate() {
+        let old_id = old_id as u32;
+        if new_id.is_none()
+            && (old_id as usize) < new.definitions.len()
+            && !claimed.contains(&old_id)
+        {
+            *new_id = Some(old_id);
+        }
+    }
+    id_map
+}
+
+/// The configured matter definition file, or the default one
+pub fn matter_definition_path(config: &GameConfig) -> &str {
+    if let Some(path) = &config.definition_path {
+        path
+    } else {
+        MATTER_DEFINITION_FILE
+    }
+}
+
+/// Read matter definitions from the configured path (or the default file), the built in
+/// definitions if there's no such file. Errors hold every problem found in the file.
+pub fn load_matter_definitions(
+    config: &GameConfig,
+) -> Result<MatterDefinitions, Vec<MatterDefinitionDiagnostic>> {
+    let definition_path = matter_definition_path(config);
+    if !Path::new(definition_path).exists() {
+        info!(
+            "No matter definitions at {}, using the built in ones",
+            definition_path
+        );
+        return Ok(default_matter_definitions());
+    }
+
+    let text = FileUtils::read_str(definition_path).map_err(|e| {
+        vec![MatterDefinitionDiagnostic {
+            error: MatterDefinitionError::Read(e.to_string()),
+            position: None,
+        }]
+    })?;
+    parse_matter_definitions(&text)
+}
+
 #[sysfail(log(level = "error"))]
 fn update_config(
     mut commands: Commands,
@@ -89,3 +258,43 @@ fn update_config(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matter::{MATTER_GAS, MATTER_WATER};
+
+    #[test]
+    fn reload_keeps_unchanged_ids() {
+        let matter_definitions = default_matter_definitions();
+        let id_map = reload_id_map(&matter_definitions, &matter_definitions);
+        let ids = (0..matter_definitions.definitions.len() as u32)
+            .map(Some)
+            .collect::<Vec<_>>();
+        assert_eq!(id_map, ids);
+    }
+
+    #[test]
+    fn reload_follows_removed_matter() {
+        let old = default_matter_definitions();
+        let mut new = old.clone();
+        let removed = new.remove(MATTER_WATER).unwrap();
+        assert_eq!(reload_id_map(&old, &new), removed);
+    }
+
+    #[test]
+    fn reload_keeps_renamed_matter() {
+        let old = default_matter_definitions();
+        let mut new = old.clone();
+        new.definitions[MATTER_WATER as usize].name = "Brine".to_string();
+        let id_map = reload_id_map(&old, &new);
+        assert_eq!(id_map[MATTER_WATER as usize], Some(MATTER_WATER));
+
+        // Unless another matter moved to its id
+        new.remove(MATTER_GAS).unwrap();
+        new.definitions[MATTER_WATER as usize].name = "Gas".to_string();
+        let id_map = reload_id_map(&old, &new);
+        assert_eq!(id_map[MATTER_WATER as usize], None);
+        assert_eq!(id_map[MATTER_GAS as usize], Some(MATTER_WATER));
+    }
+}
@@ -0,0 +1,36 @@
+use bevy::{
+    asset::{AssetLoader, LoadContext, LoadedAsset},
+    reflect::TypeUuid,
+    utils::BoxedFuture,
+};
+
+/// Text of a matter definition file. It's parsed where it's used rather than by the loader, so
+/// problems with it can be reported with their positions instead of only failing the load.
+#[derive(Debug, TypeUuid)]
+#[uuid = "083c8d83-8f6f-49c3-bb8f-d7485573324b"]
+pub struct MatterDefinitionFile {
+    pub text: String,
+}
+
+#[derive(Default)]
+pub struct MatterDefinitionFileLoader;
+
+impl AssetLoader for MatterDefinitionFileLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let text = String::from_utf8(bytes.to_vec())?;
+            load_context.set_default_asset(LoadedAsset::new(MatterDefinitionFile {
+                text,
+            }));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["matter.ron"]
+    }
+}
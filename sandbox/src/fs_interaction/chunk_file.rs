@@ -0,0 +1,133 @@
+use std::{
+    io::{Read, Write},
+    path::Path,
+};
+
+use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression};
+use thiserror::Error;
+
+use super::{FileUtils, FileUtilsError};
+use crate::simulator::backend::CellGrids;
+
+/// First bytes of every chunk file
+pub const CHUNK_FILE_MAGIC: [u8; 4] = *b"MUDC";
+/// Bump when the layout below changes, older versions are rejected
+pub const CHUNK_FILE_VERSION: u32 = 2;
+/// Bytes per cell, its matter, temperature & cell data
+const CELL_BYTES: usize = 12;
+
+pub type ChunkFileResult<T> = std::result::Result<T, ChunkFileError>;
+
+#[derive(Error, Debug)]
+pub enum ChunkFileError {
+    #[error("Not a chunk file")]
+    NotAChunkFile,
+    #[error("Unsupported chunk file version {}, expected {}", .0, CHUNK_FILE_VERSION)]
+    UnsupportedVersion(u32),
+    #[error("Chunk has {} cells, expected {}", .0, .1)]
+    InvalidCells(usize, usize),
+
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
+    #[error(transparent)]
+    File(#[from] FileUtilsError),
+}
+
+/// A chunk of the world paged out to disk. On disk:
+/// - `CHUNK_FILE_MAGIC`, `CHUNK_FILE_VERSION` (u32)
+/// - zlib compressed: chunk x, chunk y (i32 each), chunk size (u32), then each cell's `MatterCell`
+///   (u32), temperature (f32) and `CellData` (u32) as three grids, row by row starting from y = 0
+///
+/// All numbers are little endian. Matter ids are those of the session's matter definitions.
+#[derive(Debug, Clone)]
+pub struct ChunkFile {
+    pub chunk_pos: [i32; 2],
+    pub chunk_size: u32,
+    pub cells: CellGrids,
+}
+
+impl ChunkFile {
+    pub fn save(&self, path: impl AsRef<Path>) -> ChunkFileResult<()> {
+        FileUtils::write_bytes(path, &self.to_bytes()?)?;
+        Ok(())
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> ChunkFileResult<ChunkFile> {
+        ChunkFile::from_bytes(&FileUtils::read_bytes(path.as_ref())?)
+    }
+
+    pub fn to_bytes(&self) -> ChunkFileResult<Vec<u8>> {
+        let mut bytes = CHUNK_FILE_MAGIC.to_vec();
+        bytes.extend_from_slice(&CHUNK_FILE_VERSION.to_le_bytes());
+
+        let mut encoder = ZlibEncoder::new(bytes, Compression::fast());
+        encoder.write_all(&self.chunk_pos[0].to_le_bytes())?;
+        encoder.write_all(&self.chunk_pos[1].to_le_bytes())?;
+        encoder.write_all(&self.chunk_size.to_le_bytes())?;
+        for matter in self.cells.matter.iter() {
+            encoder.write_all(&matter.to_le_bytes())?;
+        }
+        for temperature in self.cells.temperature.iter() {
+            encoder.write_all(&temperature.to_le_bytes())?;
+        }
+        for cell_data in self.cells.cell_data.iter() {
+            encoder.write_all(&cell_data.to_le_bytes())?;
+        }
+
+        Ok(encoder.finish()?)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> ChunkFileResult<ChunkFile> {
+        if bytes.len() < 8 || bytes[0..4] != CHUNK_FILE_MAGIC {
+            return Err(ChunkFileError::NotAChunkFile);
+        }
+        let version = u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]);
+        if version != CHUNK_FILE_VERSION {
+            return Err(ChunkFileError::UnsupportedVersion(version));
+        }
+
+        let mut decoder = ZlibDecoder::new(&bytes[8..]);
+        let chunk_pos = [
+            read_u32(&mut decoder)? as i32,
+            read_u32(&mut decoder)? as i32,
+        ];
+        let chunk_size = read_u32(&mut decoder)?;
+
+        let mut cell_bytes = vec![];
+        decoder.read_to_end(&mut cell_bytes)?;
+        let num_cells = chunk_size as usize * chunk_size as usize;
+        if Some(cell_bytes.len()) != num_cells.checked_mul(CELL_BYTES) {
+            return Err(ChunkFileError::InvalidCells(
+                cell_bytes.len() / CELL_BYTES,
+                num_cells,
+            ));
+        }
+        let mut words = cell_bytes.chunks_exact(4).map(|b| [b[0], b[1], b[2], b[3]]);
+        let cells = CellGrids {
+            matter: words
+                .by_ref()
+                .take(num_cells)
+                .map(u32::from_le_bytes)
+                .collect(),
+            temperature: words
+                .by_ref()
+                .take(num_cells)
+                .map(f32::from_le_bytes)
+                .collect(),
+            cell_data: words.map(u32::from_le_bytes).collect(),
+        };
+
+        Ok(ChunkFile {
+            chunk_pos,
+            chunk_size,
+            cells,
+        })
+    }
+}
+
+fn read_u32(reader: &mut impl Read) -> std::io::Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
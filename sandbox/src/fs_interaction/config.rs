@@ -1,6 +1,11 @@
 use bevy::{prelude::*, reflect::TypeUuid};
 use serde::{Deserialize, Serialize};
 
+use crate::{
+    simulator::{backend::SimulationBackendKind, boundary::BoundariesConfig},
+    SIM_CANVAS_SIZE,
+};
+
 #[derive(
     Debug,
     Clone,
@@ -17,4 +22,27 @@ use serde::{Deserialize, Serialize};
 #[uuid = "93a7c64b-4d6e-4420-b8c1-dfca481d9387"]
 pub struct GameConfig {
     pub definition_path: Option<String>,
+    #[serde(default)]
+    pub simulation_backend: SimulationBackendKind,
+    /// Simulation seed, random on each start if not set
+    #[serde(default)]
+    pub seed: Option<u32>,
+    /// Canvas width & height, rounded up to multiples of `KERNEL_SIZE`. 512x512 if not set.
+    #[serde(default)]
+    pub canvas_size: Option<[u32; 2]>,
+    /// Unbounded world: the canvas follows the camera through the world, chunks leaving it are
+    /// paged out to memory & disk. The canvas size is rounded up to whole chunks.
+    #[serde(default)]
+    pub streaming: bool,
+    /// What lies beyond each canvas edge, walls if not set
+    #[serde(default)]
+    pub boundaries: BoundariesConfig,
+}
+
+impl GameConfig {
+    pub fn canvas_size(&self) -> UVec2 {
+        self.canvas_size
+            .map(UVec2::from)
+            .unwrap_or(UVec2::splat(SIM_CANVAS_SIZE))
+    }
 }
@@ -0,0 +1,381 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::mpsc::{self, SyncSender, TrySendError},
+    thread::JoinHandle,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, ensure, Context, Result};
+use bevy::prelude::*;
+use bevy_fn_plugin::bevy_plugin;
+use bevy_mod_sysfail::macros::*;
+use image::{
+    codecs::gif::{GifEncoder, Repeat},
+    Delay, Frame, RgbaImage,
+};
+
+use crate::{
+    matter::matter_definition::MatterDefinitions,
+    simulator::{
+        census::MatterCensus,
+        cpu_simulator::cell_color,
+        grid_readback::{GridCopyInfo, MatterGridReadback},
+        simulation::Simulation,
+    },
+    GameState, SIM_FPS,
+};
+
+pub const SCREENSHOT_DIR: &str = "screenshots";
+pub const RECORDING_DIR: &str = "recordings";
+pub const CENSUS_DIR: &str = "census";
+
+/// Record every n:th simulation step by default
+pub const RECORD_EVERY_STEPS: u32 = 2;
+/// Gif encoder speed (1-30), lower is better quality but slower
+const GIF_SPEED: i32 = 10;
+/// Frames waiting for the encoder before more are skipped (or waited for)
+const MAX_QUEUED_FRAMES: usize = 8;
+
+/// Canvas image recolored from a matter grid readback. These are the colors matter is drawn with,
+/// unlike the canvas color image which is in linear space for the swapchain.
+pub fn grid_image(
+    grid: &[u32],
+    canvas_size: UVec2,
+    matter_definitions: &MatterDefinitions,
+) -> RgbaImage {
+    RgbaImage::from_fn(canvas_size.x, canvas_size.y, |x, y| {
+        // Canvas y grows upwards, images grow downwards
+        let matter = grid[((canvas_size.y - 1 - y) * canvas_size.x + x) as usize];
+        image::Rgba(cell_color(matter, matter_definitions))
+    })
+}
+
+pub fn save_png(
+    path: impl AsRef<Path>,
+    grid: &[u32],
+    canvas_size: UVec2,
+    matter_definitions: &MatterDefinitions,
+) -> Result<()> {
+    let path = path.as_ref();
+    create_parent_dir(path)?;
+    grid_image(grid, canvas_size, matter_definitions)
+        .save(path)
+        .with_context(|| format!("Failed to write image {:?}", path))
+}
+
+/// Path like `dir/name_<unix time in ms>.extension`
+pub fn timestamped_path(dir: &str, name: &str, extension: &str) -> PathBuf {
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_millis())
+        .unwrap_or_default();
+    Path::new(dir).join(format!("{name}_{millis}.{extension}"))
+}
+
+fn create_parent_dir(path: &Path) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    Ok(())
+}
+
+/// A matter grid on its way to the encoder thread
+struct GifFrame {
+    grid: Vec<u32>,
+    canvas_size: UVec2,
+    delay: Delay,
+}
+
+impl GifFrame {
+    /// Frame shown for `steps` simulation steps
+    fn new(grid: Vec<u32>, canvas_size: UVec2, steps: u32) -> GifFrame {
+        GifFrame {
+            grid,
+            canvas_size,
+            delay: Delay::from_numer_denom_ms(steps * 1000, SIM_FPS as u32),
+        }
+    }
+}
+
+/// Streams canvas frames into an animated gif. Frames are encoded on their own thread, each is
+/// shown for as many simulation steps as passed until the next one.
+pub struct GifRecorder {
+    path: PathBuf,
+    every: u32,
+    until: Option<u32>,
+    /// Size & origin of the canvas the first frame was copied from
+    canvas: Option<(UVec2, IVec2)>,
+    /// Latest frame, it's sent once the next one tells how long it's shown
+    pending: Option<(GridCopyInfo, Vec<u32>)>,
+    frames: u32,
+    sender: SyncSender<GifFrame>,
+    encoder: JoinHandle<Result<()>>,
+}
+
+impl GifRecorder {
+    /// Record a frame at least every `every` simulation steps, played back at simulation speed.
+    /// With `until`, the frame at or after that step is the last one.
+    pub fn create(
+        path: impl Into<PathBuf>,
+        every: u32,
+        until: Option<u32>,
+        matter_definitions: &MatterDefinitions,
+    ) -> Result<GifRecorder> {
+        let path = path.into();
+        create_parent_dir(&path)?;
+        let file = File::create(&path).with_context(|| format!("Failed to create {:?}", path))?;
+
+        let mut encoder = GifEncoder::new_with_speed(BufWriter::new(file), GIF_SPEED);
+        encoder.set_repeat(Repeat::Infinite)?;
+        let (sender, receiver) = mpsc::sync_channel(MAX_QUEUED_FRAMES);
+        let matter_definitions = matter_definitions.clone();
+        let encoder = std::thread::spawn(move || {
+            for frame in receiver {
+                let image = grid_image(&frame.grid, frame.canvas_size, &matter_definitions);
+                encoder.encode_frame(Frame::from_parts(image, 0, 0, frame.delay))?;
+            }
+            Ok(())
+        });
+
+        Ok(GifRecorder {
+            path,
+            every: every.max(1),
+            until,
+            canvas: None,
+            pending: None,
+            frames: 0,
+            sender,
+            encoder,
+        })
+    }
+
+    /// Whether a grid copied at `sim_steps` would be recorded
+    pub fn is_due(&self, sim_steps: u32) -> bool {
+        match &self.pending {
+            Some((last, _)) => sim_steps >= last.sim_steps + self.every && !self.is_complete(),
+            None => true,
+        }
+    }
+
+    /// The last frame before `until` was recorded
+    pub fn is_complete(&self) -> bool {
+        match (self.until, &self.pending) {
+            (Some(until), Some((last, _))) => last.sim_steps >= until,
+            _ => false,
+        }
+    }
+
+    /// Add a grid copy as a frame if it's due, waiting while the encoder is busy
+    pub fn record(&mut self, info: GridCopyInfo, grid: Vec<u32>) -> Result<()> {
+        self.record_frame(info, grid, true)
+    }
+
+    /// Add a grid copy as a frame if it's due, unless the encoder is busy. The frame before is
+    /// then shown for longer instead.
+    pub fn try_record(&mut self, info: GridCopyInfo, grid: Vec<u32>) -> Result<()> {
+        self.record_frame(info, grid, false)
+    }
+
+    fn record_frame(&mut self, info: GridCopyInfo, grid: Vec<u32>, wait: bool) -> Result<()> {
+        if !self.is_due(info.sim_steps) {
+            return Ok(());
+        }
+        let canvas = (info.canvas_size, info.canvas_origin);
+        ensure!(
+            *self.canvas.get_or_insert(canvas) == canvas,
+            "Canvas was resized or moved, a recording keeps to one canvas"
+        );
+
+        if let Some((last, grid)) = self.pending.take() {
+            let frame = GifFrame::new(grid, last.canvas_size, info.sim_steps - last.sim_steps);
+            let sent = if wait {
+                self.sender.send(frame).is_ok()
+            } else {
+                match self.sender.try_send(frame) {
+                    Err(TrySendError::Full(frame)) => {
+                        self.pending = Some((last, frame.grid));
+                        return Ok(());
+                    }
+                    sent => sent.is_ok(),
+                }
+            };
+            // A stopped encoder's error is returned by `finish`
+            ensure!(sent, "Gif encoder stopped, finish the recording");
+            self.frames += 1;
+        }
+        self.pending = Some((info, grid));
+        Ok(())
+    }
+
+    /// Encode the last frame & wait for the encoder to finish the file. Returns the number of
+    /// frames.
+    pub fn finish(mut self) -> Result<u32> {
+        if let Some((last, grid)) = self.pending.take() {
+            let frame = GifFrame::new(grid, last.canvas_size, self.every);
+            // A stopped encoder's error is returned below
+            if self.sender.send(frame).is_ok() {
+                self.frames += 1;
+            }
+        }
+        drop(self.sender);
+        self.encoder
+            .join()
+            .map_err(|_| anyhow!("Gif encoder panicked"))??;
+        Ok(self.frames)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn frames(&self) -> u32 {
+        self.frames
+    }
+}
+
+/// Gif recording in progress, if any
+#[derive(Resource, Default)]
+pub struct Recording {
+    pub recorder: Option<GifRecorder>,
+    /// Simulation steps a new recording lasts, until stopped if 0
+    pub steps: u32,
+    /// `MatterGridReadback::num_copies` of the last copy seen
+    num_copies: u64,
+}
+
+impl Recording {
+    fn finish(&mut self) -> Result<()> {
+        if let Some(recorder) = self.recorder.take() {
+            let path = recorder.path().to_path_buf();
+            let frames = recorder.finish()?;
+            info!("Saved recording {:?} ({} frames)", path, frames);
+        }
+        Ok(())
+    }
+}
+
+/// Screenshot waiting for a grid copy, holds `MatterGridReadback::num_copies` at the request
+#[derive(Resource, Default)]
+struct PendingScreenshot(Option<u64>);
+
+/// Sent by the gui & export keys
+pub enum ExportEvent {
+    Screenshot,
+    ToggleRecording,
+    /// The matter census series as csv
+    Census,
+}
+
+#[bevy_plugin]
+pub fn ExportPlugin(app: &mut App) {
+    app.init_resource::<Recording>()
+        .init_resource::<PendingScreenshot>()
+        .add_event::<ExportEvent>()
+        .add_systems(
+            (handle_export_events, save_screenshot, record_frames)
+                .chain()
+                .distributive_run_if(in_state(GameState::Simulating)),
+        );
+}
+
+#[sysfail(log(level = "error"))]
+fn handle_export_events(
+    simulation: Res<Simulation>,
+    census: Res<MatterCensus>,
+    matter_definitions: Res<MatterDefinitions>,
+    mut grid_readback: ResMut<MatterGridReadback>,
+    mut pending_screenshot: ResMut<PendingScreenshot>,
+    mut recording: ResMut<Recording>,
+    mut export_events: EventReader<ExportEvent>,
+) -> Result<()> {
+    for event in export_events.iter() {
+        match event {
+            ExportEvent::Screenshot => {
+                pending_screenshot.0 = Some(grid_readback.num_copies());
+                grid_readback.request();
+            }
+            ExportEvent::ToggleRecording if recording.recorder.is_some() => recording.finish()?,
+            ExportEvent::ToggleRecording => {
+                let path = timestamped_path(RECORDING_DIR, "recording", "gif");
+                let until = (recording.steps > 0).then(|| simulation.sim_steps() + recording.steps);
+                // Copies from before the recording aren't part of it
+                recording.num_copies = grid_readback.num_copies();
+                recording.recorder = Some(GifRecorder::create(
+                    &path,
+                    RECORD_EVERY_STEPS,
+                    until,
+                    &matter_definitions,
+                )?);
+                info!("Recording {:?}", path);
+            }
+            ExportEvent::Census => {
+                let path = timestamped_path(CENSUS_DIR, "census", "csv");
+                create_parent_dir(&path)?;
+                let file =
+                    File::create(&path).with_context(|| format!("Failed to create {:?}", path))?;
+                let mut writer = BufWriter::new(file);
+                census.write_csv(&mut writer, &matter_definitions)?;
+                writer.flush()?;
+                info!("Saved census {:?}", path);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Save the pending screenshot once a grid copy arrived after it was taken, without waiting on
+/// the simulation
+#[sysfail(log(level = "error"))]
+fn save_screenshot(
+    matter_definitions: Res<MatterDefinitions>,
+    mut grid_readback: ResMut<MatterGridReadback>,
+    mut pending_screenshot: ResMut<PendingScreenshot>,
+) -> Result<()> {
+    let Some(num_copies) = pending_screenshot.0 else { return Ok(()) };
+    if grid_readback.num_copies() <= num_copies {
+        grid_readback.request();
+        return Ok(());
+    }
+    pending_screenshot.0 = None;
+
+    let info = grid_readback.info();
+    let path = timestamped_path(SCREENSHOT_DIR, "screenshot", "png");
+    save_png(
+        &path,
+        grid_readback.grid(),
+        info.canvas_size,
+        &matter_definitions,
+    )?;
+    info!(
+        "Saved screenshot {:?} of step {}, canvas at world cell {}",
+        path, info.sim_steps, info.canvas_origin
+    );
+    Ok(())
+}
+
+/// Record the grid copies as they arrive, without waiting on the simulation
+#[sysfail(log(level = "error"))]
+fn record_frames(
+    mut grid_readback: ResMut<MatterGridReadback>,
+    mut recording: ResMut<Recording>,
+) -> Result<()> {
+    let Recording { recorder: Some(recorder), num_copies, .. } = &mut *recording else { return Ok(()) };
+    grid_readback.request();
+    if grid_readback.num_copies() == *num_copies {
+        return Ok(());
+    }
+    *num_copies = grid_readback.num_copies();
+
+    let info = grid_readback.info();
+    if recorder.is_due(info.sim_steps) {
+        let result = recorder.try_record(info, grid_readback.grid().to_vec());
+        if result.is_err() || recorder.is_complete() {
+            recording.finish()?;
+        }
+        result?;
+    }
+    Ok(())
+}
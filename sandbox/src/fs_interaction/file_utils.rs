@@ -20,17 +20,22 @@ pub enum FileUtilsError {
     Io(#[from] std::io::Error),
 
     #[error(transparent)]
-    Std(Box<dyn std::error::Error>),
+    Std(Box<dyn std::error::Error + Send + Sync>),
 
     #[error(transparent)]
-    RonError(#[from] ron::Error),
+    RonError(#[from] ron::error::SpannedError),
+
+    #[error(transparent)]
+    TomlError(#[from] toml::de::Error),
+
+    #[error(transparent)]
+    TomlSerError(#[from] toml::ser::Error),
 }
 
 pub struct FileUtils;
 impl FileUtils {
     // Reading files
 
-    #[allow(unused)]
     pub fn read_str<Path: Into<PathBuf>>(path: Path) -> FileResult<String> {
         let path: PathBuf = path.into();
         match std::fs::read_to_string(path) {
@@ -39,6 +44,21 @@ impl FileUtils {
         }
     }
 
+    pub fn read_bytes<Path: Into<PathBuf>>(path: Path) -> FileResult<Vec<u8>> {
+        let path: PathBuf = path.into();
+        match std::fs::read(path) {
+            Ok(bytes) => Ok(bytes),
+            Err(e) => Err(FileUtilsError::Io(e)),
+        }
+    }
+
+    pub fn read_toml<T: for<'a> serde::Deserialize<'a>>(
+        path: impl Into<PathBuf>,
+    ) -> FileResult<T> {
+        let toml_str = Self::read_str(path)?;
+        Ok(toml::from_str::<T>(&toml_str)?)
+    }
+
     pub fn read_ron<T: for<'a> serde::Deserialize<'a>>(path: impl Into<PathBuf>) -> FileResult<T> {
         let path: PathBuf = path.into();
         std::fs::canonicalize::<PathBuf>(path).map_or_else(
@@ -48,7 +68,7 @@ impl FileUtils {
                     |err| Err(FileUtilsError::Io(err)),
                     |file| {
                         ron::de::from_reader::<_, T>(file).map_or_else(
-                            |err| Err(FileUtilsError::RonError(err.code)),
+                            |err| Err(FileUtilsError::RonError(err)),
                             |ron| Ok(ron),
                         )
                     },
@@ -57,8 +77,15 @@ impl FileUtils {
         )
     }
 
-    #[allow(unused)]
     pub fn write_str<P: AsRef<Path>>(path: P, value: &str) -> FileResult<()> {
+        Self::write_bytes(path, value.as_bytes())
+    }
+
+    pub fn write_toml<T: serde::Serialize>(path: impl AsRef<Path>, value: &T) -> FileResult<()> {
+        Self::write_str(path, &toml::to_string(value)?)
+    }
+
+    pub fn write_bytes<P: AsRef<Path>>(path: P, value: &[u8]) -> FileResult<()> {
         let path = path.as_ref();
         let path_string = match path.to_str() {
             Some(s) => s.to_string(),
@@ -0,0 +1,238 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use bevy::prelude::*;
+use bevy_fn_plugin::bevy_plugin;
+use bevy_mod_sysfail::macros::*;
+use image::{imageops::FilterType, RgbaImage};
+use serde::{Deserialize, Serialize};
+use strum_macros::EnumIter;
+
+use super::FileUtils;
+use crate::{
+    matter::matter_definition::{MatterDefinition, MatterDefinitions},
+    simulator::{
+        cpu_simulator::{drawn_matter, pos_from_index},
+        simulation::Simulation,
+    },
+    GameState,
+};
+
+/// How an image that doesn't match the canvas size is brought to it
+#[derive(EnumIter, Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum ImageFit {
+    /// Resize to the canvas, ignoring aspect ratio
+    #[default]
+    Stretch,
+    /// Resize to fit inside the canvas keeping aspect ratio, the rest stays empty
+    Fit,
+    /// Keep pixel size, cut off what doesn't fit around the center
+    Crop,
+}
+
+impl std::fmt::Display for ImageFit {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl std::str::FromStr for ImageFit {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "stretch" => Ok(ImageFit::Stretch),
+            "fit" => Ok(ImageFit::Fit),
+            "crop" => Ok(ImageFit::Crop),
+            other => anyhow::bail!("Unknown image fit {other}, expected stretch, fit or crop"),
+        }
+    }
+}
+
+/// Explicit color to matter table, read from `.ron` or `.toml`:
+/// ```toml
+/// [[colors]]
+/// color = 0xc2b280
+/// matter = "Sand"
+/// ```
+/// Pixels with colors not in the table use the nearest color of the table.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ColorMapping {
+    pub colors: Vec<ColorMappingEntry>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ColorMappingEntry {
+    /// 0xRRGGBB
+    pub color: u32,
+    /// Matter name
+    pub matter: String,
+}
+
+impl ColorMapping {
+    pub fn read(path: impl AsRef<Path>) -> Result<ColorMapping> {
+        let path = path.as_ref();
+        let mapping = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => FileUtils::read_toml::<ColorMapping>(path)?,
+            _ => FileUtils::read_ron::<ColorMapping>(path)?,
+        };
+        Ok(mapping)
+    }
+
+    /// Map to each matter's own color
+    pub fn from_matter_definitions(matter_definitions: &MatterDefinitions) -> ColorMapping {
+        ColorMapping {
+            colors: matter_definitions
+                .definitions
+                .iter()
+                .map(|def| ColorMappingEntry {
+                    color: def.color >> 8,
+                    matter: def.name.clone(),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Import a png as a matter grid of `canvas_size`. Pixels map to the matter with the nearest
+/// color, fully transparent pixels stay empty.
+pub fn import_png(
+    path: impl AsRef<Path>,
+    mapping: Option<&ColorMapping>,
+    fit: ImageFit,
+    matter_definitions: &MatterDefinitions,
+    canvas_size: UVec2,
+) -> Result<Vec<u32>> {
+    let path = path.as_ref();
+    let image = image::open(path)
+        .with_context(|| format!("Failed to open image {:?}", path))?
+        .into_rgba8();
+    let image = fit_to_canvas(&image, fit, canvas_size);
+
+    let default_mapping;
+    let mapping = match mapping {
+        Some(mapping) => mapping,
+        None => {
+            default_mapping = ColorMapping::from_matter_definitions(matter_definitions);
+            &default_mapping
+        }
+    };
+    let palette = mapping
+        .colors
+        .iter()
+        .map(|entry| {
+            matter_definitions
+                .definitions
+                .iter()
+                .find(|def| def.name == entry.matter)
+                .map(|def| (entry.color, def))
+                .with_context(|| format!("Unknown matter {} in color mapping", entry.matter))
+        })
+        .collect::<Result<Vec<(u32, &MatterDefinition)>>>()?;
+
+    let empty = &matter_definitions.definitions[matter_definitions.empty as usize];
+    let num_cells = (canvas_size.x * canvas_size.y) as usize;
+    let grid = (0..num_cells)
+        .map(|index| {
+            let pos = pos_from_index(index, canvas_size);
+            // Canvas y grows upwards, images grow downwards
+            let pixel = image.get_pixel(pos.x as u32, canvas_size.y - 1 - pos.y as u32);
+            let definition = if pixel[3] == 0 {
+                empty
+            } else {
+                nearest_matter(&palette, [pixel[0], pixel[1], pixel[2]]).unwrap_or(empty)
+            };
+            drawn_matter(pos, definition.id)
+        })
+        .collect();
+
+    Ok(grid)
+}
+
+fn nearest_matter<'a>(
+    palette: &[(u32, &'a MatterDefinition)],
+    rgb: [u8; 3],
+) -> Option<&'a MatterDefinition> {
+    palette
+        .iter()
+        .min_by_key(|(color, _)| {
+            let channel = |shift: u32| ((color >> shift) & 255) as i32;
+            let dr = channel(16) - rgb[0] as i32;
+            let dg = channel(8) - rgb[1] as i32;
+            let db = channel(0) - rgb[2] as i32;
+            dr * dr + dg * dg + db * db
+        })
+        .map(|(_, def)| *def)
+}
+
+/// Image of exactly canvas size. Nearest filtering keeps level colors exact.
+fn fit_to_canvas(image: &RgbaImage, fit: ImageFit, canvas_size: UVec2) -> RgbaImage {
+    let (width, height) = (canvas_size.x, canvas_size.y);
+    if image.dimensions() == (width, height) {
+        return image.clone();
+    }
+
+    match fit {
+        ImageFit::Stretch => image::imageops::resize(image, width, height, FilterType::Nearest),
+        ImageFit::Fit => {
+            // Largest scale at which both sides still fit
+            let scale =
+                (width as f32 / image.width() as f32).min(height as f32 / image.height() as f32);
+            let resized = image::imageops::resize(
+                image,
+                ((image.width() as f32 * scale) as u32).clamp(1, width),
+                ((image.height() as f32 * scale) as u32).clamp(1, height),
+                FilterType::Nearest,
+            );
+            centered_on_canvas(&resized, canvas_size)
+        }
+        ImageFit::Crop => centered_on_canvas(image, canvas_size),
+    }
+}
+
+/// Center image on a transparent (empty) canvas, cutting off what doesn't fit
+fn centered_on_canvas(image: &RgbaImage, canvas_size: UVec2) -> RgbaImage {
+    let mut canvas = RgbaImage::new(canvas_size.x, canvas_size.y);
+    image::imageops::overlay(
+        &mut canvas,
+        image,
+        (canvas_size.x as i64 - image.width() as i64) / 2,
+        (canvas_size.y as i64 - image.height() as i64) / 2,
+    );
+    canvas
+}
+
+/// Import an image into the running simulation, sent by the gui
+pub struct ImageImportEvent {
+    pub path: PathBuf,
+    pub mapping: Option<PathBuf>,
+    pub fit: ImageFit,
+}
+
+#[bevy_plugin]
+pub fn ImageImportPlugin(app: &mut App) {
+    app.add_event::<ImageImportEvent>()
+        .add_system(handle_image_import_events.run_if(in_state(GameState::Simulating)));
+}
+
+#[sysfail(log(level = "error"))]
+fn handle_image_import_events(
+    mut simulation: ResMut<Simulation>,
+    matter_definitions: Res<MatterDefinitions>,
+    mut image_import_events: EventReader<ImageImportEvent>,
+) -> Result<()> {
+    for event in image_import_events.iter() {
+        let mapping = event.mapping.as_ref().map(ColorMapping::read).transpose()?;
+        let grid = import_png(
+            &event.path,
+            mapping.as_ref(),
+            event.fit,
+            &matter_definitions,
+            simulation.canvas_size(),
+        )?;
+        simulation.write_matter_grid(&grid)?;
+        info!("Imported image {:?}", event.path);
+    }
+
+    Ok(())
+}
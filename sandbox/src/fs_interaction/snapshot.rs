@@ -0,0 +1,458 @@
+use std::{
+    io::{Read, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+use bevy::prelude::*;
+use bevy_fn_plugin::bevy_plugin;
+use bevy_mod_sysfail::macros::*;
+use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression};
+use thiserror::Error;
+
+use super::{FileUtils, FileUtilsError};
+use crate::{
+    matter::matter_definition::{parse_matter_definitions, MatterDefinitions},
+    settings::AppSettings,
+    simulator::{backend::CellGrids, emitters::Emitter, simulation::Simulation},
+    GameState,
+};
+
+/// First bytes of every snapshot file
+pub const SNAPSHOT_MAGIC: [u8; 4] = *b"MUDS";
+/// Bump when the layout below changes, other versions are rejected
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+pub const QUICKSAVE_FILE: &str = "saves/quicksave.snapshot";
+
+pub type SnapshotResult<T> = std::result::Result<T, SnapshotError>;
+
+#[derive(Error, Debug)]
+pub enum SnapshotError {
+    #[error("Not a snapshot file")]
+    NotASnapshot,
+    #[error("Unsupported snapshot version {}, expected {}", .0, SNAPSHOT_VERSION)]
+    UnsupportedVersion(u32),
+    #[error("Snapshot grid has {} cells, expected {}", .0, .1)]
+    InvalidGrid(usize, usize),
+    #[error("Snapshot canvas {}x{} is too large", .0, .1)]
+    InvalidCanvasSize(u32, u32),
+    #[error("Invalid matter definitions in snapshot: {}", .0)]
+    InvalidMatterDefinitions(String),
+
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
+    #[error(transparent)]
+    File(#[from] FileUtilsError),
+
+    #[error(transparent)]
+    RonError(#[from] ron::error::SpannedError),
+
+    #[error(transparent)]
+    RonWriteError(#[from] ron::Error),
+}
+
+/// Everything needed to continue a simulation later. On disk:
+/// - `SNAPSHOT_MAGIC`, `SNAPSHOT_VERSION` (u32)
+/// - zlib compressed: width, height, sim steps, seed (u32 each), matter definitions (u32 length +
+///   ron), emitters (u32 length + ron), then each cell's `MatterCell` (u32), temperature (f32) and
+///   `CellData` (u32) as three grids, row by row from y = 0
+///
+/// All numbers are little endian.
+#[derive(Debug, Clone)]
+pub struct WorldSnapshot {
+    pub canvas_size: [u32; 2],
+    pub sim_steps: u32,
+    pub seed: u32,
+    pub matter_definitions: MatterDefinitions,
+    pub cells: CellGrids,
+    /// Sources & sinks, in world cells
+    pub emitters: Vec<Emitter>,
+}
+
+impl WorldSnapshot {
+    pub fn save(&self, path: impl AsRef<Path>) -> SnapshotResult<()> {
+        FileUtils::write_bytes(path, &self.to_bytes()?)?;
+        Ok(())
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> SnapshotResult<WorldSnapshot> {
+        WorldSnapshot::from_bytes(&FileUtils::read_bytes(path.as_ref())?)
+    }
+
+    pub fn to_bytes(&self) -> SnapshotResult<Vec<u8>> {
+        let mut bytes = SNAPSHOT_MAGIC.to_vec();
+        bytes.extend_from_slice(&SNAPSHOT_VERSION.to_le_bytes());
+
+        let mut encoder = ZlibEncoder::new(bytes, Compression::default());
+        for value in [
+            self.canvas_size[0],
+            self.canvas_size[1],
+            self.sim_steps,
+            self.seed,
+        ] {
+            encoder.write_all(&value.to_le_bytes())?;
+        }
+
+        let definitions = self.matter_definitions.serialize();
+        encoder.write_all(&(definitions.len() as u32).to_le_bytes())?;
+        encoder.write_all(definitions.as_bytes())?;
+
+        let emitters = ron::to_string(&self.emitters)?;
+        encoder.write_all(&(emitters.len() as u32).to_le_bytes())?;
+        encoder.write_all(emitters.as_bytes())?;
+
+        for matter in self.cells.matter.iter() {
+            encoder.write_all(&matter.to_le_bytes())?;
+        }
+        for temperature in self.cells.temperature.iter() {
+            encoder.write_all(&temperature.to_le_bytes())?;
+        }
+        for cell_data in self.cells.cell_data.iter() {
+            encoder.write_all(&cell_data.to_le_bytes())?;
+        }
+
+        Ok(encoder.finish()?)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> SnapshotResult<WorldSnapshot> {
+        if bytes.len() < 8 || bytes[0..4] != SNAPSHOT_MAGIC {
+            return Err(SnapshotError::NotASnapshot);
+        }
+        let version = u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]);
+        if version != SNAPSHOT_VERSION {
+            return Err(SnapshotError::UnsupportedVersion(version));
+        }
+
+        let mut decoder = ZlibDecoder::new(&bytes[8..]);
+        let canvas_size = [read_u32(&mut decoder)?, read_u32(&mut decoder)?];
+        let sim_steps = read_u32(&mut decoder)?;
+        let seed = read_u32(&mut decoder)?;
+        let num_cells = (canvas_size[0] as usize)
+            .checked_mul(canvas_size[1] as usize)
+            .ok_or(SnapshotError::InvalidCanvasSize(
+                canvas_size[0],
+                canvas_size[1],
+            ))?;
+
+        let definitions = read_length_prefixed(&mut decoder)?;
+        let definitions = String::from_utf8(definitions)
+            .map_err(|e| SnapshotError::InvalidMatterDefinitions(e.to_string()))?;
+        let matter_definitions = parse_matter_definitions(&definitions).map_err(|diagnostics| {
+            SnapshotError::InvalidMatterDefinitions(
+                diagnostics
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            )
+        })?;
+
+        let emitters = ron::de::from_bytes::<Vec<Emitter>>(&read_length_prefixed(&mut decoder)?)?;
+
+        // 4 bytes per cell in each grid, read no more than that
+        let num_bytes = num_cells
+            .checked_mul(4 * 3)
+            .ok_or(SnapshotError::InvalidCanvasSize(
+                canvas_size[0],
+                canvas_size[1],
+            ))?;
+        let mut grid_bytes = vec![];
+        decoder
+            .take(num_bytes as u64 + 1)
+            .read_to_end(&mut grid_bytes)?;
+        if grid_bytes.len() != num_bytes {
+            return Err(SnapshotError::InvalidGrid(
+                grid_bytes.len() / (4 * 3),
+                num_cells,
+            ));
+        }
+
+        let mut words = grid_bytes.chunks_exact(4).map(|b| [b[0], b[1], b[2], b[3]]);
+        let cells = CellGrids {
+            matter: words
+                .by_ref()
+                .take(num_cells)
+                .map(u32::from_le_bytes)
+                .collect(),
+            temperature: words
+                .by_ref()
+                .take(num_cells)
+                .map(f32::from_le_bytes)
+                .collect(),
+            cell_data: words.map(u32::from_le_bytes).collect(),
+        };
+
+        Ok(WorldSnapshot {
+            canvas_size,
+            sim_steps,
+            seed,
+            matter_definitions,
+            cells,
+            emitters,
+        })
+    }
+
+    /// Remap matter ids of the cells & emitters to `matter_definitions` by matter name. Matter that
+    /// does not exist there becomes empty, emitters of it are dropped. Returns the names of those
+    /// missing matters.
+    pub fn remap_matters(&mut self, matter_definitions: &MatterDefinitions) -> Vec<String> {
+        let empty = matter_definitions.empty;
+
+        let id_map = matter_definitions.id_map_by_name(&self.matter_definitions);
+        let missing = self
+            .matter_definitions
+            .definitions
+            .iter()
+            .zip(id_map.iter())
+            .filter(|(_, id)| id.is_none())
+            .map(|(old, _)| old.name.clone())
+            .collect();
+
+        // Keeps the color variation, empty isn't varied anyway
+        self.cells.remap_matters(&id_map, matter_definitions);
+
+        self.emitters.retain_mut(|emitter| {
+            let id = id_map.get(emitter.matter as usize).copied().flatten();
+            emitter.matter = id.unwrap_or(empty);
+            id.is_some()
+        });
+
+        self.matter_definitions = matter_definitions.clone();
+        missing
+    }
+}
+
+fn read_u32(reader: &mut impl Read) -> std::io::Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+/// Bytes after their u32 length. Only what's actually there is allocated, whatever the length
+/// claims.
+fn read_length_prefixed(reader: &mut impl Read) -> std::io::Result<Vec<u8>> {
+    let len = read_u32(reader)? as usize;
+    let mut bytes = vec![];
+    reader.take(len as u64).read_to_end(&mut bytes)?;
+    if bytes.len() != len {
+        return Err(std::io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(bytes)
+}
+
+/// Save or load the world, sent by the gui & the quicksave keys
+pub enum SnapshotEvent {
+    Save(PathBuf),
+    Load(PathBuf),
+}
+
+#[bevy_plugin]
+pub fn SnapshotPlugin(app: &mut App) {
+    app.add_event::<SnapshotEvent>()
+        .add_system(handle_snapshot_events.run_if(in_state(GameState::Simulating)));
+}
+
+#[sysfail(log(level = "error"))]
+fn handle_snapshot_events(
+    mut settings: ResMut<AppSettings>,
+    mut simulation: ResMut<Simulation>,
+    matter_definitions: Res<MatterDefinitions>,
+    mut snapshot_events: EventReader<SnapshotEvent>,
+) -> Result<()> {
+    for event in snapshot_events.iter() {
+        match event {
+            SnapshotEvent::Save(path) => {
+                simulation
+                    .snapshot(settings.seed, &matter_definitions)?
+                    .save(path)?;
+                info!("Saved snapshot {:?}", path);
+            }
+            SnapshotEvent::Load(path) => {
+                let snapshot = WorldSnapshot::load(path)?;
+                settings.seed = snapshot.seed;
+                simulation.load_snapshot(snapshot, &matter_definitions)?;
+                // Keep the snapshot's canvas size instead of resizing back
+                settings.canvas_size = simulation.canvas_size();
+                info!("Loaded snapshot {:?}", path);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        matter::{
+            default_matter_definitions, MatterCell, MATTER_EMPTY, MATTER_GAS, MATTER_SAND,
+            MATTER_WATER,
+        },
+        simulator::emitters::EmitterKind,
+    };
+
+    const CANVAS_SIZE: [u32; 2] = [4, 2];
+
+    fn emitter(matter: u32) -> Emitter {
+        Emitter {
+            kind: EmitterKind::Source,
+            pos: [3, -2],
+            matter,
+            radius: 2.0,
+            is_square: false,
+            rate: 0.5,
+        }
+    }
+
+    fn snapshot() -> WorldSnapshot {
+        let matter_definitions = default_matter_definitions();
+        let ids = [
+            MATTER_SAND,
+            MATTER_WATER,
+            MATTER_EMPTY,
+            MATTER_GAS,
+            MATTER_SAND,
+            MATTER_SAND,
+            MATTER_WATER,
+            MATTER_EMPTY,
+        ];
+        let matter = ids.iter().map(|&id| MatterCell::new(id, 7).value).collect();
+        let mut cells = CellGrids::at_defaults(matter, &matter_definitions);
+        cells.temperature[0] = 250.0;
+        cells.cell_data[3] = 17;
+        WorldSnapshot {
+            canvas_size: CANVAS_SIZE,
+            sim_steps: 123,
+            seed: 42,
+            matter_definitions,
+            cells,
+            emitters: vec![emitter(MATTER_WATER), emitter(MATTER_SAND)],
+        }
+    }
+
+    /// Snapshot bytes up to & including the emitters, followed by `grid_bytes`
+    fn snapshot_bytes(canvas_size: [u32; 2], emitters: &str, grid_bytes: &[u8]) -> Vec<u8> {
+        let mut bytes = SNAPSHOT_MAGIC.to_vec();
+        bytes.extend_from_slice(&SNAPSHOT_VERSION.to_le_bytes());
+        let mut encoder = ZlibEncoder::new(bytes, Compression::default());
+        for value in [canvas_size[0], canvas_size[1], 7, 42] {
+            encoder.write_all(&value.to_le_bytes()).unwrap();
+        }
+        for section in [&default_matter_definitions().serialize(), emitters] {
+            encoder
+                .write_all(&(section.len() as u32).to_le_bytes())
+                .unwrap();
+            encoder.write_all(section.as_bytes()).unwrap();
+        }
+        encoder.write_all(grid_bytes).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn round_trip() {
+        let snapshot = snapshot();
+        let loaded = WorldSnapshot::from_bytes(&snapshot.to_bytes().unwrap()).unwrap();
+        assert_eq!(loaded.canvas_size, snapshot.canvas_size);
+        assert_eq!(loaded.sim_steps, snapshot.sim_steps);
+        assert_eq!(loaded.seed, snapshot.seed);
+        assert_eq!(loaded.cells, snapshot.cells);
+        assert_eq!(loaded.emitters, snapshot.emitters);
+        assert_eq!(
+            loaded.matter_definitions.serialize(),
+            snapshot.matter_definitions.serialize()
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_bytes() {
+        assert!(matches!(
+            WorldSnapshot::from_bytes(b"SNAP0000"),
+            Err(SnapshotError::NotASnapshot)
+        ));
+
+        let mut bytes = snapshot().to_bytes().unwrap();
+        bytes[4..8].copy_from_slice(&(SNAPSHOT_VERSION + 1).to_le_bytes());
+        assert!(matches!(
+            WorldSnapshot::from_bytes(&bytes),
+            Err(SnapshotError::UnsupportedVersion(_))
+        ));
+
+        // Grids shorter than the canvas
+        let bytes = snapshot_bytes(CANVAS_SIZE, "[]", &[0; 4 * 3 * 5]);
+        assert!(matches!(
+            WorldSnapshot::from_bytes(&bytes),
+            Err(SnapshotError::InvalidGrid(5, 8))
+        ));
+    }
+
+    #[test]
+    fn bounds_allocations_by_the_data() {
+        // Lengths larger than the data end the read instead of allocating what they claim
+        let mut bytes = SNAPSHOT_MAGIC.to_vec();
+        bytes.extend_from_slice(&SNAPSHOT_VERSION.to_le_bytes());
+        let mut encoder = ZlibEncoder::new(bytes, Compression::default());
+        for value in [u32::MAX, u32::MAX, 0, 0, u32::MAX] {
+            encoder.write_all(&value.to_le_bytes()).unwrap();
+        }
+        encoder.write_all(b"(empty: 0").unwrap();
+        let bytes = encoder.finish().unwrap();
+        assert!(matches!(
+            WorldSnapshot::from_bytes(&bytes),
+            Err(SnapshotError::Io(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof
+        ));
+
+        // As does a canvas with more bytes than can be addressed
+        let bytes = snapshot_bytes([u32::MAX, u32::MAX], "[]", &[]);
+        assert!(matches!(
+            WorldSnapshot::from_bytes(&bytes),
+            Err(SnapshotError::InvalidCanvasSize(u32::MAX, u32::MAX))
+        ));
+    }
+
+    #[test]
+    fn remap_matters_by_name() {
+        let mut snapshot = snapshot();
+        let mut matter_definitions = default_matter_definitions();
+        matter_definitions.remove(MATTER_WATER).unwrap();
+
+        let missing = snapshot.remap_matters(&matter_definitions);
+        assert_eq!(missing, vec!["Water".to_string()]);
+
+        let ids = snapshot
+            .cells
+            .matter
+            .iter()
+            .map(|&cell| MatterCell::from(cell).matter_id())
+            .collect::<Vec<u32>>();
+        let gas = MATTER_GAS - 1;
+        assert_eq!(ids, vec![
+            MATTER_SAND,
+            MATTER_EMPTY,
+            MATTER_EMPTY,
+            gas,
+            MATTER_SAND,
+            MATTER_SAND,
+            MATTER_EMPTY,
+            MATTER_EMPTY
+        ]);
+        // Variations stay, removed matter restarts as empty
+        assert!(snapshot
+            .cells
+            .matter
+            .iter()
+            .all(|&cell| MatterCell::from(cell).variation() == 7));
+        let empty_temperature = matter_definitions.temperature(MATTER_EMPTY);
+        assert_eq!(snapshot.cells.temperature[0], 250.0);
+        assert_eq!(snapshot.cells.temperature[1], empty_temperature);
+        assert_eq!(snapshot.cells.cell_data[3], 17);
+
+        assert_eq!(snapshot.emitters, vec![emitter(MATTER_SAND)]);
+        assert_eq!(
+            snapshot.matter_definitions.serialize(),
+            matter_definitions.serialize()
+        );
+    }
+}
@@ -15,6 +15,7 @@ use bevy_vulkano::{
 
 use crate::{
     render::camera::OrthographicCamera,
+    settings::AppSettings,
     simulator::simulation::Simulation,
     time::{RenderTimer, SimulationTimer},
 };
@@ -39,6 +40,7 @@ pub fn user_interface(
     render_timer: Res<RenderTimer>,
     camera: Res<OrthographicCamera>,
     sim_timer: Res<SimulationTimer>,
+    settings: Res<AppSettings>,
 
     mut simulator: ResMut<Simulation>,
     mut painter: ResMut<EditorPainter>,
@@ -68,6 +70,19 @@ pub fn user_interface(
                 size,
             );
 
+            sized_text(
+                ui,
+                format!("Seed: {} (step {})", settings.seed, simulator.sim_steps()),
+                size,
+            );
+
+            let canvas_size = simulator.canvas_size();
+            sized_text(
+                ui,
+                format!("Canvas: {}x{}", canvas_size.x, canvas_size.y),
+                size,
+            );
+
             sized_text(
                 ui,
                 format!("Render Time: {:.2} ms", render_timer.0.time_average_ms(),),
@@ -1,4 +1,5 @@
 pub mod draw_state;
+pub mod history;
 pub mod painter;
 pub mod windows;
 
@@ -12,26 +13,49 @@ use bevy_vulkano::{
 };
 use vulkano::format::Format;
 
-use crate::{matter::matter_definition::MatterDefinitions, utils::AppExt, GameState};
+use self::{history::EditHistory, painter::EditorPainter};
+use crate::{
+    fs_interaction::{
+        asset_loading::MatterDefinitionsChanged, image_import::ImageFit, snapshot::QUICKSAVE_FILE,
+    },
+    matter::{
+        matter_definition::{MatterDefinition, MatterDefinitionError, MatterDefinitions},
+        MatterCell, Matters,
+    },
+    utils::AppExt,
+    GameState,
+};
 
 pub const IMAGE_FORMAT: Format = Format::R8G8B8A8_UNORM;
 
 #[bevy_plugin]
 pub fn EditorPlugin(app: &mut App) {
     app.init_resource_on_enter::<_, Editor>(GameState::Simulating)
-        .add_plugin(windows::EditorWindowsPlugin);
+        .add_plugin(windows::EditorWindowsPlugin)
+        .add_system(update_editor_matters.run_if(in_state(GameState::Simulating)));
 }
 
 #[derive(Resource)]
 pub struct Editor {
     pub show_info_view: bool,
     pub show_edit_view: bool,
-    // pub show_load_view: bool,
+    pub show_load_view: bool,
     // pub show_guide_view: bool,
     pub show_settings_view: bool,
-    // pub show_new_matter_view: bool,
-    // add_matter: MatterDefinition,
+    pub show_matter_view: bool,
+    /// Matter definition shown in the matter window
+    pub edited_matter: u32,
+    /// Edit of the shown matter definition that made the definitions invalid, with its problems.
+    /// It's shown instead of the applied definition until it's fixed.
+    pub matter_draft: Option<(MatterDefinition, Vec<MatterDefinitionError>)>,
     pub matter_texture_ids: BTreeMap<u32, TextureId>,
+    pub snapshot_path: String,
+    pub import_path: String,
+    /// Color mapping file, nearest matter color if empty
+    pub import_mapping_path: String,
+    pub import_fit: ImageFit,
+    /// Only matters whose name contains this are shown in the palette
+    pub matter_filter: String,
 }
 
 impl FromWorld for Editor {
@@ -60,8 +84,17 @@ impl Editor {
         Self {
             show_edit_view: true,
             show_info_view: false,
+            show_load_view: false,
             show_settings_view: false,
+            show_matter_view: false,
+            edited_matter: 0,
+            matter_draft: None,
             matter_texture_ids: BTreeMap::new(),
+            snapshot_path: QUICKSAVE_FILE.to_string(),
+            import_path: String::new(),
+            import_mapping_path: String::new(),
+            import_fit: ImageFit::default(),
+            matter_filter: String::new(),
         }
     }
 
@@ -96,6 +129,40 @@ impl Editor {
         self.matter_texture_ids
             .iter()
             .for_each(|(_key, texture)| gui.unregister_user_image(*texture));
+        // Removed matters keep no texture
+        self.matter_texture_ids.clear();
         self.register_matter_gui_images(gui, matter_definitions);
     }
 }
+
+/// Follow changed matter definitions: palette textures, matter names, the brush matter and the
+/// matter kept for undo
+fn update_editor_matters(
+    mut editor: ResMut<Editor>,
+    mut painter: ResMut<EditorPainter>,
+    mut history: ResMut<EditHistory>,
+    mut matters: ResMut<Matters>,
+    matter_definitions: Res<MatterDefinitions>,
+    mut changed_events: EventReader<MatterDefinitionsChanged>,
+    mut vulkano_windows: NonSendMut<BevyVulkanoWindows>,
+    window_query: Query<Entity, With<PrimaryWindow>>,
+) {
+    if changed_events.is_empty() {
+        return;
+    }
+
+    let empty = matter_definitions.empty;
+    for event in changed_events.iter() {
+        let Some(id_map) = &event.id_map else { continue };
+        history.remap_matters(|cell| MatterCell::from(cell).remapped(id_map, empty).value);
+        let brush_matter = id_map.get(painter.get_matter() as usize).copied().flatten();
+        painter.set_matter(brush_matter.unwrap_or(empty));
+    }
+
+    *matters = Matters::from_definitions(&matter_definitions);
+    if let Some(primary_window) =
+        crate::utils::get_primary_window_mut(&window_query, &mut vulkano_windows)
+    {
+        editor.update_matter_gui_textures(&mut primary_window.gui, &matter_definitions);
+    }
+}
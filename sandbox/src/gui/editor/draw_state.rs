@@ -11,6 +11,8 @@ pub enum DrawTransition {
 pub struct CanvasDrawState {
     pub prev: Option<Vec2>,
     pub current: Option<Vec2>,
+    /// Min & max of all positions since the stroke started
+    pub bounds: Option<(Vec2, Vec2)>,
 }
 
 impl CanvasDrawState {
@@ -28,18 +30,20 @@ impl CanvasDrawState {
         match draw_event {
             DrawTransition::Start(v) => {
                 self.current = Some(v);
-
+                self.bounds = Some((v, v));
                 None
             }
             DrawTransition::Draw(v) => {
                 self.prev = self.current;
                 self.current = Some(v);
+                self.bounds = self.bounds.map(|(min, max)| (min.min(v), max.max(v)));
                 None
             }
             DrawTransition::End => {
                 let result = self.clone();
                 self.prev = None;
                 self.current = None;
+                self.bounds = None;
                 Some(result)
             }
         }
@@ -53,7 +57,8 @@ impl CanvasDrawState {
         self.transition(DrawTransition::Draw(pos));
     }
 
-    pub fn end(&mut self) {
-        self.transition(DrawTransition::End);
+    /// End the stroke, returns its last state
+    pub fn end(&mut self) -> Option<CanvasDrawState> {
+        self.transition(DrawTransition::End)
     }
 }
@@ -0,0 +1,162 @@
+use std::collections::VecDeque;
+
+use anyhow::{ensure, Result};
+use bevy::prelude::*;
+
+use crate::{simulator::simulation::Simulation, utils::region_rows};
+
+/// Number of strokes that can be undone
+pub const MAX_HISTORY: usize = 64;
+
+/// Undo or redo the last canvas edit, sent by the gui & Ctrl+Z / Ctrl+Shift+Z
+pub enum HistoryEvent {
+    Undo,
+    Redo,
+}
+
+/// Matter cells of a rectangle of the canvas, row by row starting from `min`
+#[derive(Debug, Clone)]
+struct GridRegion {
+    canvas_size: UVec2,
+    canvas_origin: IVec2,
+    min: UVec2,
+    size: UVec2,
+    cells: Vec<u32>,
+}
+
+impl GridRegion {
+    fn read(simulation: &mut Simulation, min: UVec2, size: UVec2) -> Result<GridRegion> {
+        Ok(GridRegion {
+            canvas_size: simulation.canvas_size(),
+            canvas_origin: simulation.canvas_origin(),
+            min,
+            size,
+            cells: simulation.read_matter_region(min, size)?,
+        })
+    }
+
+    fn max(&self) -> UVec2 {
+        self.min + self.size
+    }
+
+    /// Copy our cells over `other`'s, we must lie within it
+    fn paste_into(&self, other: &mut GridRegion) {
+        let rows = region_rows(other.size, self.min - other.min, self.size);
+        for (row, cells) in rows.zip(self.cells.chunks_exact(self.size.x as usize)) {
+            other.cells[row].copy_from_slice(cells);
+        }
+    }
+
+    /// Write the region to the simulation, returns what was there before
+    fn swap(&self, simulation: &mut Simulation) -> Result<GridRegion> {
+        ensure!(
+            simulation.canvas_size() == self.canvas_size,
+            "Canvas was resized since the edit"
+        );
+        ensure!(
+            simulation.canvas_origin() == self.canvas_origin,
+            "Canvas was moved through the world since the edit"
+        );
+        let previous = GridRegion::read(simulation, self.min, self.size)?;
+        simulation.write_matter_region(self.min, self.size, &self.cells)?;
+        Ok(previous)
+    }
+}
+
+/// Stroke level undo & redo. Each stroke keeps only the part of the grid it touched.
+#[derive(Resource, Default)]
+pub struct EditHistory {
+    undo: VecDeque<GridRegion>,
+    redo: Vec<GridRegion>,
+    /// Cells the current stroke has covered, as they were before it painted them
+    stroke: Option<GridRegion>,
+}
+
+impl EditHistory {
+    /// Keep the cells a stroke segment (canvas positions) with the brush radius is about to paint.
+    /// Call before painting it, only cells the stroke hasn't covered yet are read back.
+    pub fn extend_stroke(
+        &mut self,
+        simulation: &mut Simulation,
+        start: Vec2,
+        end: Vec2,
+        radius: f32,
+    ) -> Result<()> {
+        let canvas_size = simulation.canvas_size();
+        let clamp = |pos: Vec2| pos.max(Vec2::ZERO).as_uvec2().min(canvas_size);
+        let min = clamp((start.min(end) - radius - 1.0).floor());
+        let max = clamp((start.max(end) + radius + 1.0).ceil());
+        // Segment entirely outside the canvas
+        if max.x <= min.x || max.y <= min.y {
+            return Ok(());
+        }
+
+        // A resize or move ends the stroke, its cells are on the old canvas
+        let canvas_origin = simulation.canvas_origin();
+        if self.stroke.as_ref().map_or(false, |stroke| {
+            stroke.canvas_size != canvas_size || stroke.canvas_origin != canvas_origin
+        }) {
+            self.end_stroke();
+        }
+        let (min, max) = match &self.stroke {
+            Some(stroke) if min.cmpge(stroke.min).all() && max.cmple(stroke.max()).all() => {
+                return Ok(());
+            }
+            Some(stroke) => (min.min(stroke.min), max.max(stroke.max())),
+            None => (min, max),
+        };
+
+        // The grown region is read as it is now, the cells painted so far are put back to what
+        // they were before the stroke
+        let mut region = GridRegion::read(simulation, min, max - min)?;
+        if let Some(stroke) = &self.stroke {
+            stroke.paste_into(&mut region);
+        }
+        self.stroke = Some(region);
+        Ok(())
+    }
+
+    /// Keep the current stroke as one undo step
+    pub fn end_stroke(&mut self) {
+        let Some(stroke) = self.stroke.take() else { return };
+        self.redo.clear();
+        self.undo.push_back(stroke);
+        if self.undo.len() > MAX_HISTORY {
+            self.undo.pop_front();
+        }
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    /// Change the matter of every kept cell, e.g. after matter definitions were removed
+    pub fn remap_matters(&mut self, remap: impl Fn(u32) -> u32) {
+        let regions = self.undo.iter_mut().chain(self.redo.iter_mut());
+        for region in regions.chain(self.stroke.as_mut()) {
+            for cell in region.cells.iter_mut() {
+                *cell = remap(*cell);
+            }
+        }
+    }
+
+    /// Restore the grid before the last stroke
+    pub fn undo(&mut self, simulation: &mut Simulation) -> Result<()> {
+        if let Some(region) = self.undo.pop_back() {
+            self.redo.push(region.swap(simulation)?);
+        }
+        Ok(())
+    }
+
+    /// Restore the last undone stroke
+    pub fn redo(&mut self, simulation: &mut Simulation) -> Result<()> {
+        if let Some(region) = self.redo.pop() {
+            self.undo.push_back(region.swap(simulation)?);
+        }
+        Ok(())
+    }
+}
@@ -1,13 +1,31 @@
 use anyhow::Result;
 use bevy::prelude::{Resource, Vec2};
 
-use crate::simulator::simulation::Simulation;
+use crate::simulator::{
+    emitters::{Emitter, EmitterKind},
+    simulation::Simulation,
+};
+
+/// What a left click on the canvas does
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum PaintTool {
+    /// Paint matter
+    #[default]
+    Brush,
+    /// Place a source of the matter
+    Source,
+    /// Place a sink of the matter
+    Sink,
+}
 
 #[derive(Resource)]
 pub struct EditorPainter {
     radius: f32,
     matter: u32,
     is_square: bool,
+    tool: PaintTool,
+    /// Rate of placed emitters, see `Emitter::rate`
+    emitter_rate: f32,
 }
 
 impl Default for EditorPainter {
@@ -16,11 +34,17 @@ impl Default for EditorPainter {
             matter: 1,
             radius: 4.0,
             is_square: false,
+            tool: PaintTool::default(),
+            emitter_rate: 0.1,
         }
     }
 }
 
 impl EditorPainter {
+    pub fn radius(&self) -> f32 {
+        self.radius
+    }
+
     pub fn radius_mut(&mut self) -> &mut f32 {
         &mut self.radius
     }
@@ -36,6 +60,18 @@ impl EditorPainter {
     pub fn set_matter(&mut self, matter: u32) {
         self.matter = matter;
     }
+
+    pub fn tool(&self) -> PaintTool {
+        self.tool
+    }
+
+    pub fn tool_mut(&mut self) -> &mut PaintTool {
+        &mut self.tool
+    }
+
+    pub fn emitter_rate_mut(&mut self) -> &mut f32 {
+        &mut self.emitter_rate
+    }
 }
 
 impl EditorPainter {
@@ -48,4 +84,22 @@ impl EditorPainter {
         let start = start.unwrap_or(end);
         simulation.paint_round(start, end, self.matter, self.radius, self.is_square)
     }
+
+    /// Place a source or sink of the brush's matter & shape at a world position, if an emitter
+    /// tool is selected
+    pub fn place_emitter(&self, world_pos: Vec2, simulation: &mut Simulation) -> Result<()> {
+        let kind = match self.tool {
+            PaintTool::Brush => return Ok(()),
+            PaintTool::Source => EmitterKind::Source,
+            PaintTool::Sink => EmitterKind::Sink,
+        };
+        simulation.add_emitter(Emitter {
+            kind,
+            pos: world_pos.floor().as_ivec2().to_array(),
+            matter: self.matter,
+            radius: self.radius,
+            is_square: self.is_square,
+            rate: self.emitter_rate,
+        })
+    }
 }
@@ -1,5 +1,9 @@
+pub mod definition_errors_window;
 pub mod editor_window;
 pub mod info_window;
+pub mod load_window;
+pub mod matter_window;
+pub mod settings_window;
 pub mod top_editor;
 
 use bevy::prelude::*;
@@ -14,6 +18,10 @@ pub fn EditorWindowsPlugin(app: &mut App) {
             top_editor::top_editor,
             info_window::info_window,
             editor_window::editor_window,
+            load_window::load_window,
+            matter_window::matter_window,
+            settings_window::settings_window,
+            definition_errors_window::definition_errors_window,
         )
             .distributive_run_if(in_state(GameState::Simulating)),
     );
@@ -0,0 +1,46 @@
+use bevy::{prelude::*, window::PrimaryWindow};
+use bevy_vulkano::{
+    egui_winit_vulkano::egui::{self, Color32, RichText},
+    BevyVulkanoWindows,
+};
+
+use crate::fs_interaction::asset_loading::MatterDefinitionErrors;
+
+/// Problems with the matter definition file, shown until dismissed or the file loads cleanly
+pub fn definition_errors_window(
+    mut errors: ResMut<MatterDefinitionErrors>,
+    vulkan_windows: NonSend<BevyVulkanoWindows>,
+    window_query: Query<Entity, With<PrimaryWindow>>,
+) {
+    if errors.diagnostics.is_empty() {
+        return;
+    }
+    let Some(primary_window) = crate::utils::get_primary_window(&window_query, &vulkan_windows) else { return; };
+    let ctx = primary_window.gui.context();
+
+    let mut is_open = true;
+    egui::Window::new("Matter definition errors")
+        .open(&mut is_open)
+        .default_width(450.0)
+        .show(&ctx, |ui| {
+            ui.label(format!(
+                "{} has problems, the last valid matter definitions are used",
+                errors.path
+            ));
+            ui.separator();
+            egui::ScrollArea::vertical()
+                .max_height(300.0)
+                .show(ui, |ui| {
+                    for diagnostic in errors.diagnostics.iter() {
+                        ui.label(
+                            RichText::new(format!("{}:{}", errors.path, diagnostic))
+                                .color(Color32::LIGHT_RED),
+                        );
+                    }
+                });
+        });
+
+    if !is_open {
+        errors.clear();
+    }
+}
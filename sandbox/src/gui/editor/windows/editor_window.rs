@@ -3,15 +3,26 @@ use bevy_vulkano::{
     egui_winit_vulkano::egui::{self, ImageButton, Ui},
     BevyVulkanoWindows,
 };
+use strum::IntoEnumIterator;
 
 use crate::{
-    gui::editor::{painter::EditorPainter, Editor},
+    gui::editor::{
+        painter::{EditorPainter, PaintTool},
+        Editor,
+    },
     matter::matter_definition::{MatterDefinition, MatterDefinitions},
+    settings::AppSettings,
+    simulator::{
+        boundary::{Boundaries, Boundary, Edge},
+        simulation::Simulation,
+    },
 };
 
 pub fn editor_window(
-    editor: Res<Editor>,
+    mut editor: ResMut<Editor>,
     mut painter: ResMut<EditorPainter>,
+    mut settings: ResMut<AppSettings>,
+    mut simulation: ResMut<Simulation>,
     matter_definitions: Res<MatterDefinitions>,
     vulkan_windows: NonSend<BevyVulkanoWindows>,
     window_query: Query<Entity, With<PrimaryWindow>>,
@@ -35,16 +46,113 @@ pub fn editor_window(
             ui.checkbox(painter.is_square_mut(), "Square brush");
             ui.separator();
 
+            ui.horizontal(|ui| {
+                ui.selectable_value(painter.tool_mut(), PaintTool::Brush, "Brush");
+                ui.selectable_value(painter.tool_mut(), PaintTool::Source, "Source");
+                ui.selectable_value(painter.tool_mut(), PaintTool::Sink, "Sink");
+            });
+            if painter.tool() != PaintTool::Brush {
+                ui.label("Emitter rate");
+                ui.add(egui::Slider::new(painter.emitter_rate_mut(), 0.01..=1.0));
+            }
+            ui.collapsing("Emitters", |ui| {
+                add_emitters(ui, &mut simulation, &matter_definitions);
+            });
+            ui.separator();
+
+            ui.collapsing("Boundaries", |ui| {
+                add_boundaries(
+                    ui,
+                    &mut settings.boundaries,
+                    painter.get_matter(),
+                    &matter_definitions,
+                );
+            });
+            ui.separator();
+
             ui.label(format!(
                 "Matter ({})",
                 &matter_definitions.definitions[painter.get_matter() as usize].name
             ));
             ui.separator();
 
+            ui.horizontal(|ui| {
+                ui.label("Search");
+                ui.text_edit_singleline(&mut editor.matter_filter);
+            });
+
             add_matter_palette(ui, &editor, &mut painter, &matter_definitions);
         });
 }
 
+/// Placed sources & sinks, right click on the canvas removes them too
+fn add_emitters(ui: &mut Ui, simulation: &mut Simulation, matter_definitions: &MatterDefinitions) {
+    let mut removed = None;
+    for (index, emitter) in simulation.emitters().iter().enumerate() {
+        ui.horizontal(|ui| {
+            ui.label(format!(
+                "{:?} of {} at [{}, {}]",
+                emitter.kind,
+                matter_definitions.definitions[emitter.matter as usize].name,
+                emitter.pos[0],
+                emitter.pos[1]
+            ));
+            if ui.small_button("x").clicked() {
+                removed = Some(index);
+            }
+        });
+    }
+    if let Some(index) = removed {
+        simulation.remove_emitter(index);
+    }
+
+    if simulation.emitters().is_empty() {
+        ui.label("Place sources & sinks with their tools");
+    } else if ui.button("Remove all").clicked() {
+        simulation.clear_emitters();
+    }
+}
+
+/// What lies beyond each canvas edge. Source edges emit the brush matter picked at the time.
+fn add_boundaries(
+    ui: &mut Ui,
+    boundaries: &mut Boundaries,
+    brush_matter: u32,
+    matter_definitions: &MatterDefinitions,
+) {
+    let label = |boundary: Boundary| match boundary {
+        Boundary::Source(matter) => format!(
+            "Source ({})",
+            matter_definitions.definitions[matter as usize].name
+        ),
+        _ => boundary.to_string(),
+    };
+
+    egui::Grid::new("boundaries").show(ui, |ui| {
+        for edge in Edge::iter() {
+            let current = boundaries.get(edge);
+            let mut selected = current;
+            ui.label(edge.to_string());
+            egui::ComboBox::from_id_source(edge.to_string())
+                .selected_text(label(current))
+                .show_ui(ui, |ui| {
+                    for boundary in [
+                        Boundary::Wall,
+                        Boundary::Wrap,
+                        Boundary::Void,
+                        Boundary::Source(brush_matter),
+                    ] {
+                        ui.selectable_value(&mut selected, boundary, label(boundary));
+                    }
+                });
+            if selected != current {
+                boundaries.set(edge, selected);
+            }
+            ui.end_row();
+        }
+    });
+}
+
 fn add_matter_palette(
     ui: &mut Ui,
     editor: &Editor,
@@ -53,7 +161,20 @@ fn add_matter_palette(
 ) {
     let num_cols = 4;
     let button_size = egui::Vec2::new(24.0, 24.0);
-    let grouped_matters = get_grouped_matters(&matter_definitions.definitions);
+    let filter = editor.matter_filter.to_lowercase();
+    let matters = matter_definitions
+        .definitions
+        .iter()
+        .filter(|m| m.name.to_lowercase().contains(&filter))
+        .collect::<Vec<_>>();
+    ui.label(format!(
+        "{} of {} matters",
+        matters.len(),
+        matter_definitions.definitions.len()
+    ));
+    ui.separator();
+
+    let grouped_matters = get_grouped_matters(&matters);
 
     for m_group in grouped_matters.iter() {
         let state = m_group[0].state;
@@ -87,24 +208,16 @@ fn add_matter_palette(
     }
 }
 
-fn get_grouped_matters(matters: &[MatterDefinition]) -> Vec<Vec<MatterDefinition>> {
-    let mut matters: Vec<MatterDefinition> = matters.to_vec();
-    matters.sort_unstable_by_key(|m| m.state);
-
-    let mut grouped_matters = vec![];
-    let mut is_next_group = true;
-    let mut last_state = matters.first().unwrap().state;
-    for matter in matters.iter() {
-        if matter.state != last_state {
-            is_next_group = true;
-        }
+/// Matters grouped by state, in id order within a group
+fn get_grouped_matters<'a>(matters: &[&'a MatterDefinition]) -> Vec<Vec<&'a MatterDefinition>> {
+    let mut matters = matters.to_vec();
+    matters.sort_by_key(|m| m.state);
 
-        if is_next_group {
-            grouped_matters.push(vec![matter.clone()]);
-            last_state = matter.state;
-            is_next_group = false;
-        } else {
-            grouped_matters.last_mut().unwrap().push(matter.clone());
+    let mut grouped_matters: Vec<Vec<&MatterDefinition>> = vec![];
+    for matter in matters {
+        match grouped_matters.last_mut() {
+            Some(group) if group[0].state == matter.state => group.push(matter),
+            _ => grouped_matters.push(vec![matter]),
         }
     }
 
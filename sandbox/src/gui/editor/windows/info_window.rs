@@ -3,11 +3,21 @@ use bevy::{
     prelude::*,
     window::PrimaryWindow,
 };
-use bevy_vulkano::{egui_winit_vulkano::egui, BevyVulkanoWindows};
+use bevy_vulkano::{
+    egui_winit_vulkano::egui::{
+        self,
+        plot::{Legend, Line, Plot, PlotPoints},
+        Color32,
+    },
+    BevyVulkanoWindows,
+};
 
 use crate::{
+    fs_interaction::export::ExportEvent,
     gui::editor::Editor,
-    simulator::simulation::Simulation,
+    matter::matter_definition::MatterDefinitions,
+    settings::AppSettings,
+    simulator::{census::MatterCensus, simulation::Simulation},
     time::{RenderTimer, SimulationTimer},
 };
 #[derive(Resource)]
@@ -24,6 +34,10 @@ pub fn info_window(
     mut fps: Local<f64>,
     editor: Res<Editor>,
     sim: Res<Simulation>,
+    mut settings: ResMut<AppSettings>,
+    mut census: ResMut<MatterCensus>,
+    matter_definitions: Res<MatterDefinitions>,
+    mut export_events: EventWriter<ExportEvent>,
     // mut state: Res<Editor>,
     mut timer: Local<FPSTimer>,
     diagnostics: Res<Diagnostics>,
@@ -65,5 +79,113 @@ pub fn info_window(
                 "CA simulation: {:.3}",
                 sim.ca_timer.time_average_ms()
             ));
+
+            if let Some(chunks) = sim.chunks() {
+                let origin = sim.canvas_origin();
+                ui.label(format!("Canvas origin: {}, {}", origin.x, origin.y));
+                ui.label(format!(
+                    "Paged out chunks: {} in memory, {} on disk",
+                    chunks.num_in_memory(),
+                    chunks.num_on_disk()
+                ));
+            }
+
+            ui.checkbox(&mut settings.show_awake_tiles, "Show awake tiles (F3)");
+
+            ui.separator();
+            ui.collapsing("Matter census", |ui| {
+                add_census(ui, &mut census, &matter_definitions, &mut export_events);
+            });
+        });
+}
+
+/// Cells per matter: the latest counts & their change since the first sample, and the series
+fn add_census(
+    ui: &mut egui::Ui,
+    census: &mut MatterCensus,
+    matter_definitions: &MatterDefinitions,
+    export_events: &mut EventWriter<ExportEvent>,
+) {
+    ui.horizontal(|ui| {
+        ui.label("Count every");
+        ui.add(
+            egui::DragValue::new(&mut census.every_steps)
+                .clamp_range(1..=10_000)
+                .suffix(" steps"),
+        );
+    });
+
+    let (Some(first), Some(latest)) = (census.samples().front(), census.latest()) else {
+        ui.label("No census yet");
+        return;
+    };
+    let count = |sample: &[u32], id: usize| sample.get(id).copied().unwrap_or(0) as i64;
+    // Matters that were never there would only clutter the table & plot
+    let present = matter_definitions
+        .definitions
+        .iter()
+        .filter(|definition| {
+            census
+                .samples()
+                .iter()
+                .any(|sample| count(&sample.counts, definition.id as usize) > 0)
+        })
+        .collect::<Vec<_>>();
+
+    ui.label(format!("Step {}", latest.sim_steps));
+    egui::Grid::new("Census table")
+        .striped(true)
+        .show(ui, |ui| {
+            ui.label("Matter");
+            ui.label("Cells");
+            ui.label(format!("Since step {}", first.sim_steps));
+            ui.end_row();
+            for definition in &present {
+                let id = definition.id as usize;
+                ui.label(&definition.name);
+                ui.label(count(&latest.counts, id).to_string());
+                ui.label(format!(
+                    "{:+}",
+                    count(&latest.counts, id) - count(&first.counts, id)
+                ));
+                ui.end_row();
+            }
         });
+
+    Plot::new("Census plot")
+        .height(160.0)
+        .legend(Legend::default())
+        .show(ui, |plot_ui| {
+            for definition in &present {
+                // Empty would dwarf everything else
+                if definition.id == matter_definitions.empty {
+                    continue;
+                }
+                let points = census
+                    .samples()
+                    .iter()
+                    .map(|sample| {
+                        [
+                            sample.sim_steps as f64,
+                            count(&sample.counts, definition.id as usize) as f64,
+                        ]
+                    })
+                    .collect::<PlotPoints>();
+                let [r, g, b, _] = definition.color.to_be_bytes();
+                plot_ui.line(
+                    Line::new(points)
+                        .name(&definition.name)
+                        .color(Color32::from_rgb(r, g, b)),
+                );
+            }
+        });
+
+    ui.horizontal(|ui| {
+        if ui.button("Export csv").clicked() {
+            export_events.send(ExportEvent::Census);
+        }
+        if ui.button("Clear").clicked() {
+            census.clear();
+        }
+    });
 }
@@ -0,0 +1,112 @@
+use bevy::{prelude::*, window::PrimaryWindow};
+use bevy_vulkano::{egui_winit_vulkano::egui, BevyVulkanoWindows};
+
+use strum::IntoEnumIterator;
+
+use crate::{
+    fs_interaction::{
+        export::{ExportEvent, Recording},
+        image_import::{ImageFit, ImageImportEvent},
+        snapshot::SnapshotEvent,
+    },
+    gui::editor::Editor,
+};
+
+pub fn load_window(
+    mut editor: ResMut<Editor>,
+    vulkan_windows: NonSend<BevyVulkanoWindows>,
+    window_query: Query<Entity, With<PrimaryWindow>>,
+    mut snapshot_events: EventWriter<SnapshotEvent>,
+    mut image_import_events: EventWriter<ImageImportEvent>,
+    mut export_events: EventWriter<ExportEvent>,
+    mut recording: ResMut<Recording>,
+) {
+    let Some(primary_window) = crate::utils::get_primary_window(&window_query, &vulkan_windows) else { return; };
+    let ctx = primary_window.gui.context();
+
+    let Editor {
+        show_load_view,
+        snapshot_path,
+        import_path,
+        import_mapping_path,
+        import_fit,
+        ..
+    } = &mut *editor;
+
+    egui::Window::new("Save / Load")
+        .open(show_load_view)
+        .default_width(300.0)
+        .show(&ctx, |ui| {
+            ui.label("Snapshot file");
+            ui.text_edit_singleline(snapshot_path);
+
+            ui.horizontal(|ui| {
+                if ui.button("Save").clicked() {
+                    snapshot_events.send(SnapshotEvent::Save(snapshot_path.as_str().into()));
+                }
+                if ui.button("Load").clicked() {
+                    snapshot_events.send(SnapshotEvent::Load(snapshot_path.as_str().into()));
+                }
+            });
+
+            ui.label("Quicksave: F5, Quickload: F9");
+            ui.separator();
+
+            ui.label("Import image (png)");
+            ui.text_edit_singleline(import_path);
+            ui.label("Color mapping (ron / toml), nearest matter color if empty");
+            ui.text_edit_singleline(import_mapping_path);
+
+            egui::ComboBox::from_label("Fit")
+                .selected_text(import_fit.to_string())
+                .show_ui(ui, |ui| {
+                    for fit in ImageFit::iter() {
+                        ui.selectable_value(import_fit, fit, fit.to_string());
+                    }
+                });
+
+            if ui.button("Import").clicked() {
+                image_import_events.send(ImageImportEvent {
+                    path: import_path.as_str().into(),
+                    mapping: (!import_mapping_path.is_empty())
+                        .then(|| import_mapping_path.as_str().into()),
+                    fit: *import_fit,
+                });
+            }
+
+            ui.separator();
+
+            ui.label("Export");
+            ui.horizontal(|ui| {
+                if ui.button("Screenshot").clicked() {
+                    export_events.send(ExportEvent::Screenshot);
+                }
+                let record_text = if recording.recorder.is_some() {
+                    "Stop recording"
+                } else {
+                    "Record gif"
+                };
+                if ui.button(record_text).clicked() {
+                    export_events.send(ExportEvent::ToggleRecording);
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.label("Record steps");
+                ui.add_enabled(
+                    recording.recorder.is_none(),
+                    egui::DragValue::new(&mut recording.steps),
+                )
+                .on_hover_text(
+                    "Recording stops after this many simulation steps, 0 records until stopped",
+                );
+            });
+            if let Some(recorder) = &recording.recorder {
+                ui.label(format!(
+                    "Recording {} ({} frames)",
+                    recorder.path().display(),
+                    recorder.frames()
+                ));
+            }
+            ui.label("Screenshot: F12, Record: F10");
+        });
+}
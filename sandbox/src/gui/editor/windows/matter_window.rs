@@ -0,0 +1,342 @@
+use anyhow::{ensure, Result};
+use bevy::{prelude::*, window::PrimaryWindow};
+use bevy_mod_sysfail::macros::*;
+use bevy_vulkano::{
+    egui_winit_vulkano::egui::{self, Color32, RichText, Ui},
+    BevyVulkanoWindows,
+};
+use strum::IntoEnumIterator;
+
+use crate::{
+    fs_interaction::{
+        asset_loading::{matter_definition_path, MatterDefinitionsChanged},
+        config::GameConfig,
+    },
+    gui::editor::Editor,
+    matter::{
+        direction::ALL_DIRECTIONS,
+        matter_definition::{
+            validate_matter_definitions, MatterDefinition, MatterDefinitions, MAX_LIFETIME,
+        },
+        matter_reaction::MatterReaction,
+        matter_state::{MatterCharacteristic, MatterState, ALL_CHARACTERISTICS},
+        MAX_NUM_MATTERS,
+    },
+};
+
+/// What the matter window asks for this frame
+enum MatterEdit {
+    Change(MatterDefinition),
+    Add,
+    Remove(u32),
+    Save,
+}
+
+/// Create, edit & delete matter definitions. Valid edits apply to the running simulation right
+/// away, invalid ones are kept in the window with their problems. "Save" writes the definitions to
+/// the matter definition file.
+#[sysfail(log(level = "error"))]
+pub fn matter_window(
+    mut editor: ResMut<Editor>,
+    mut matter_definitions: ResMut<MatterDefinitions>,
+    mut changed_events: EventWriter<MatterDefinitionsChanged>,
+    config: Res<GameConfig>,
+    vulkan_windows: NonSend<BevyVulkanoWindows>,
+    window_query: Query<Entity, With<PrimaryWindow>>,
+) -> Result<()> {
+    let Some(primary_window) = crate::utils::get_primary_window(&window_query, &vulkan_windows) else { return Ok(()) };
+    let ctx = primary_window.gui.context();
+    let definition_path = matter_definition_path(&config);
+
+    let Editor {
+        show_matter_view,
+        edited_matter,
+        matter_draft,
+        ..
+    } = &mut *editor;
+    let selected = (*edited_matter).min(matter_definitions.definitions.len() as u32 - 1);
+    if matter_draft
+        .as_ref()
+        .map_or(false, |(draft, _)| draft.id != selected)
+    {
+        *matter_draft = None;
+    }
+
+    let mut edit = None;
+    egui::Window::new("Matters")
+        .open(show_matter_view)
+        .vscroll(true)
+        .default_width(300.0)
+        .default_height(600.0)
+        .show(&ctx, |ui| {
+            ui.horizontal(|ui| {
+                egui::ComboBox::from_id_source("edited_matter")
+                    .selected_text(&matter_definitions.definitions[selected as usize].name)
+                    .show_ui(ui, |ui| {
+                        for matter in matter_definitions.definitions.iter() {
+                            ui.selectable_value(edited_matter, matter.id, &matter.name);
+                        }
+                    });
+                if ui.button("New").clicked() {
+                    edit = Some(MatterEdit::Add);
+                }
+                ui.add_enabled(
+                    selected != matter_definitions.empty,
+                    egui::Button::new("Delete"),
+                )
+                .on_hover_text("Matter of it becomes empty")
+                .clicked()
+                .then(|| edit = Some(MatterEdit::Remove(selected)));
+            });
+            ui.separator();
+
+            let mut definition = match matter_draft {
+                Some((draft, _)) => draft.clone(),
+                None => matter_definitions.definitions[selected as usize].clone(),
+            };
+            if add_definition(ui, &mut definition, &matter_definitions) {
+                edit = Some(MatterEdit::Change(definition));
+            }
+            if let Some((_, errors)) = matter_draft {
+                ui.label("Not applied until fixed:");
+                for error in errors.iter() {
+                    ui.label(RichText::new(error.to_string()).color(Color32::LIGHT_RED));
+                }
+            }
+            ui.separator();
+
+            ui.label(definition_path);
+            if ui.button("Save").clicked() {
+                edit = Some(MatterEdit::Save);
+            }
+        });
+
+    let Some(edit) = edit else { return Ok(()) };
+    let id_map = match edit {
+        MatterEdit::Change(definition) => {
+            let mut edited = matter_definitions.clone();
+            edited.definitions[definition.id as usize] = definition.clone();
+            let errors = validate_matter_definitions(&edited);
+            if !errors.is_empty() {
+                editor.matter_draft = Some((definition, errors));
+                return Ok(());
+            }
+            editor.matter_draft = None;
+            *matter_definitions = edited;
+            None
+        }
+        MatterEdit::Add => {
+            let id = matter_definitions.definitions.len() as u32;
+            ensure!(
+                id < MAX_NUM_MATTERS,
+                "Can't add more than {} matters",
+                MAX_NUM_MATTERS
+            );
+            // Names must be unique, one may already be taken by a renamed matter
+            let name = (id..)
+                .map(|n| format!("Matter {}", n))
+                .find(|name| {
+                    matter_definitions
+                        .definitions
+                        .iter()
+                        .all(|m| &m.name != name)
+                })
+                .unwrap();
+            matter_definitions.definitions.push(MatterDefinition {
+                id,
+                color: 0xffffffff,
+                weight: 1.0,
+                name,
+                state: MatterState::Powder,
+                ..MatterDefinition::zero()
+            });
+            editor.edited_matter = id;
+            editor.matter_draft = None;
+            None
+        }
+        MatterEdit::Remove(id) => {
+            let Some(id_map) = matter_definitions.remove(id) else { return Ok(()) };
+            editor.edited_matter = id.saturating_sub(1);
+            editor.matter_draft = None;
+            Some(id_map)
+        }
+        MatterEdit::Save => {
+            let errors = validate_matter_definitions(&matter_definitions);
+            ensure!(
+                errors.is_empty(),
+                "Not saving matter definitions: {}",
+                errors
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+            matter_definitions.save(definition_path)?;
+            info!("Saved matter definitions to {}", definition_path);
+            return Ok(());
+        }
+    };
+
+    changed_events.send(MatterDefinitionsChanged {
+        id_map,
+    });
+    Ok(())
+}
+
+/// Returns whether the definition was changed
+fn add_definition(
+    ui: &mut Ui,
+    definition: &mut MatterDefinition,
+    matter_definitions: &MatterDefinitions,
+) -> bool {
+    let mut changed = false;
+    egui::Grid::new("matter_definition")
+        .num_columns(2)
+        .show(ui, |ui| {
+            ui.label("Name");
+            changed |= ui.text_edit_singleline(&mut definition.name).changed();
+            ui.end_row();
+
+            // Kept as 0xRRGGBBAA, the alpha isn't edited
+            ui.label("Color");
+            let [r, g, b, a] = definition.color.to_be_bytes();
+            let mut rgb = [r, g, b];
+            if egui::color_picker::color_edit_button_srgb(ui, &mut rgb).changed() {
+                definition.color = u32::from_be_bytes([rgb[0], rgb[1], rgb[2], a]);
+                changed = true;
+            }
+            ui.end_row();
+
+            ui.label("State");
+            egui::ComboBox::from_id_source("matter_state")
+                .selected_text(definition.state.to_string())
+                .show_ui(ui, |ui| {
+                    for state in MatterState::iter() {
+                        changed |= ui
+                            .selectable_value(&mut definition.state, state, state.to_string())
+                            .changed();
+                    }
+                });
+            ui.end_row();
+
+            ui.label("Weight");
+            changed |= ui
+                .add(egui::DragValue::new(&mut definition.weight).speed(0.1))
+                .changed();
+            ui.end_row();
+
+            ui.label("Dispersion");
+            changed |= ui
+                .add(egui::DragValue::new(&mut definition.dispersion).clamp_range(0..=16))
+                .changed();
+            ui.end_row();
+
+            ui.label("Temperature");
+            changed |= ui
+                .add(egui::DragValue::new(&mut definition.temperature).suffix(" °C"))
+                .changed();
+            ui.end_row();
+
+            ui.label("Conductivity");
+            changed |= ui
+                .add(egui::Slider::new(&mut definition.conductivity, 0.0..=1.0))
+                .changed();
+            ui.end_row();
+
+            ui.label("Heat capacity");
+            changed |= ui
+                .add(
+                    egui::DragValue::new(&mut definition.heat_capacity)
+                        .speed(0.1)
+                        .clamp_range(1.0..=f32::MAX),
+                )
+                .changed();
+            ui.end_row();
+
+            ui.label("Lifetime")
+                .on_hover_text("Steps until it becomes empty, 0 lives forever");
+            changed |= ui
+                .add(egui::DragValue::new(&mut definition.lifetime).clamp_range(0..=MAX_LIFETIME))
+                .changed();
+            ui.end_row();
+        });
+
+    ui.label("Characteristics");
+    ui.horizontal_wrapped(|ui| {
+        changed |= add_characteristics(ui, &mut definition.characteristics);
+    });
+
+    for (i, reaction) in definition.reactions.iter_mut().enumerate() {
+        ui.push_id(i, |ui| {
+            ui.collapsing(format!("Reaction {}", i + 1), |ui| {
+                changed |= add_reaction(ui, reaction, matter_definitions);
+            });
+        });
+    }
+
+    changed
+}
+
+/// Becomes matter with a probability when touching a neighbor with any of the characteristics it
+/// reacts to in the chosen directions. Returns whether the reaction was changed.
+fn add_reaction(
+    ui: &mut Ui,
+    reaction: &mut MatterReaction,
+    matter_definitions: &MatterDefinitions,
+) -> bool {
+    let mut changed = false;
+    ui.horizontal(|ui| {
+        ui.label("Becomes");
+        egui::ComboBox::from_id_source("becomes")
+            .selected_text(&matter_definitions.definitions[reaction.becomes as usize].name)
+            .show_ui(ui, |ui| {
+                for matter in matter_definitions.definitions.iter() {
+                    changed |= ui
+                        .selectable_value(&mut reaction.becomes, matter.id, &matter.name)
+                        .changed();
+                }
+            });
+    });
+
+    ui.horizontal(|ui| {
+        ui.label("Probability");
+        changed |= ui
+            .add(egui::Slider::new(&mut reaction.probability, 0.0..=1.0))
+            .changed();
+    });
+
+    ui.label("Reacts to");
+    ui.horizontal_wrapped(|ui| {
+        changed |= add_characteristics(ui, &mut reaction.reacts);
+    });
+
+    ui.label("Directions");
+    ui.horizontal_wrapped(|ui| {
+        for (direction, name) in ALL_DIRECTIONS {
+            let mut is_set = reaction.direction.contains(direction);
+            if ui.checkbox(&mut is_set, name).changed() {
+                reaction.direction.set(direction, is_set);
+                changed = true;
+            }
+        }
+    });
+
+    changed
+}
+
+/// Returns whether any characteristic was toggled
+fn add_characteristics(ui: &mut Ui, characteristics: &mut MatterCharacteristic) -> bool {
+    let mut changed = false;
+    for (characteristic, name, description) in ALL_CHARACTERISTICS {
+        let mut is_set = characteristics.contains(characteristic);
+        if ui
+            .checkbox(&mut is_set, name)
+            .on_hover_text(description)
+            .changed()
+        {
+            characteristics.set(characteristic, is_set);
+            changed = true;
+        }
+    }
+    changed
+}
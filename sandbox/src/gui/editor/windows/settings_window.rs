@@ -0,0 +1,90 @@
+use bevy::{prelude::*, window::PrimaryWindow};
+use bevy_vulkano::{egui_winit_vulkano::egui, BevyVulkanoWindows};
+use strum::IntoEnumIterator;
+
+use crate::{
+    gui::editor::Editor,
+    settings::{AppSettings, DeviceProperties, QualityPreset, UserSettings, USER_SETTINGS_FILE},
+};
+
+pub fn settings_window(
+    mut editor: ResMut<Editor>,
+    mut settings: ResMut<AppSettings>,
+    device_properties: Res<DeviceProperties>,
+    vulkan_windows: NonSend<BevyVulkanoWindows>,
+    window_query: Query<Entity, With<PrimaryWindow>>,
+) {
+    let Some(primary_window) = crate::utils::get_primary_window(&window_query, &vulkan_windows) else { return; };
+    let ctx = primary_window.gui.context();
+
+    let Editor {
+        show_settings_view,
+        ..
+    } = &mut *editor;
+
+    egui::Window::new("Settings")
+        .open(show_settings_view)
+        .default_width(250.0)
+        .show(&ctx, |ui| {
+            ui.label("Device");
+            egui::Grid::new("device_properties").show(ui, |ui| {
+                ui.label("Name");
+                ui.label(device_properties.device_name());
+                ui.end_row();
+
+                ui.label("Type");
+                ui.label(format!("{:?}", device_properties.device_type()));
+                ui.end_row();
+
+                ui.label("Memory");
+                ui.label(format!("{:.2} gb", device_properties.max_mem_gb()));
+                ui.end_row();
+            });
+            ui.separator();
+
+            ui.label("Simulation");
+            let preset = QualityPreset::of(&settings);
+            egui::ComboBox::from_label("Quality")
+                .selected_text(preset.map_or("Custom".to_string(), |p| p.to_string()))
+                .show_ui(ui, |ui| {
+                    for option in QualityPreset::iter() {
+                        if ui
+                            .selectable_label(preset == Some(option), option.to_string())
+                            .clicked()
+                        {
+                            option.apply(&mut settings);
+                        }
+                    }
+                });
+
+            ui.add(egui::Slider::new(&mut settings.movement_steps, 1..=3).text("Movement steps"))
+                .on_hover_text("Movement passes per step");
+            ui.add(
+                egui::Slider::new(&mut settings.dispersion_steps, 1..=20).text("Dispersion steps"),
+            )
+            .on_hover_text("How far liquids & gases spread sideways per step");
+            ui.checkbox(&mut settings.is_paused, "Pause (Space)");
+            ui.checkbox(&mut settings.print_performance, "Print performance")
+                .on_hover_text("Log time averages every second");
+            ui.separator();
+
+            ui.label(format!(
+                "Saved to {}, used over the device defaults on launch",
+                USER_SETTINGS_FILE
+            ));
+            ui.horizontal(|ui| {
+                if ui.button("Save").clicked() {
+                    match UserSettings::new(&settings).save(USER_SETTINGS_FILE) {
+                        Ok(()) => info!("Saved user settings to {}", USER_SETTINGS_FILE),
+                        Err(e) => error!("Failed to save user settings: {}", e),
+                    }
+                }
+                if ui.button("Device defaults").clicked() {
+                    settings.use_device_defaults(&device_properties);
+                    if let Err(e) = UserSettings::remove(USER_SETTINGS_FILE) {
+                        error!("Failed to remove user settings: {}", e);
+                    }
+                }
+            });
+        });
+}
@@ -1,10 +1,21 @@
 use bevy::{prelude::*, window::PrimaryWindow};
 use bevy_vulkano::{egui_winit_vulkano::egui, BevyVulkanoWindows};
 
-use crate::gui::editor::Editor;
+use crate::{
+    gui::editor::{
+        history::{EditHistory, HistoryEvent},
+        Editor,
+    },
+    settings::AppSettings,
+    simulator::{SimulationClock, MAX_SIM_SPEED, MIN_SIM_SPEED},
+};
 
 pub fn top_editor(
     mut state: ResMut<Editor>,
+    mut settings: ResMut<AppSettings>,
+    mut clock: ResMut<SimulationClock>,
+    history: Res<EditHistory>,
+    mut history_events: EventWriter<HistoryEvent>,
     vulkan_windows: NonSend<BevyVulkanoWindows>,
     window_query: Query<Entity, With<PrimaryWindow>>,
 ) {
@@ -19,17 +30,62 @@ pub fn top_editor(
                     state.show_edit_view = !state.show_edit_view;
                 });
 
+            ui.selectable_label(state.show_matter_view, "Matters")
+                .clicked()
+                .then(|| {
+                    state.show_matter_view = !state.show_matter_view;
+                });
+
             ui.selectable_label(state.show_settings_view, "Settings")
                 .clicked()
                 .then(|| {
                     state.show_settings_view = !state.show_settings_view;
                 });
 
+            ui.selectable_label(state.show_load_view, "Save / Load")
+                .clicked()
+                .then(|| {
+                    state.show_load_view = !state.show_load_view;
+                });
+
             ui.selectable_label(state.show_info_view, "Info")
                 .clicked()
                 .then(|| {
                     state.show_info_view = !state.show_info_view;
                 });
+
+            ui.separator();
+
+            ui.add_enabled(history.can_undo(), egui::Button::new("Undo"))
+                .on_hover_text("Ctrl+Z")
+                .clicked()
+                .then(|| history_events.send(HistoryEvent::Undo));
+
+            ui.add_enabled(history.can_redo(), egui::Button::new("Redo"))
+                .on_hover_text("Ctrl+Shift+Z")
+                .clicked()
+                .then(|| history_events.send(HistoryEvent::Redo));
+
+            ui.separator();
+
+            ui.selectable_label(settings.is_paused, "Pause")
+                .on_hover_text("Space")
+                .clicked()
+                .then(|| {
+                    settings.is_paused = !settings.is_paused;
+                });
+
+            ui.add_enabled(settings.is_paused, egui::Button::new("Step"))
+                .on_hover_text("Period, advances one step while paused")
+                .clicked()
+                .then(|| clock.request_step());
+
+            ui.add(
+                egui::Slider::new(&mut settings.sim_speed, MIN_SIM_SPEED..=MAX_SIM_SPEED)
+                    .logarithmic(true)
+                    .suffix("x")
+                    .text("Speed"),
+            );
         });
     });
 }
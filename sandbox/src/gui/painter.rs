@@ -1,16 +1,38 @@
-use bevy::prelude::*;
+use bevy::{prelude::*, window::PrimaryWindow};
 use bevy_fn_plugin::bevy_plugin;
 use bevy_mod_sysfail::sysfail;
+use bevy_vulkano::{
+    egui_winit_vulkano::egui::{self, Color32, Stroke},
+    BevyVulkanoWindows,
+};
 
-use super::editor::{draw_state::CanvasDrawState, painter::EditorPainter};
-use crate::{input::InputState, simulator::simulation::Simulation, GameState};
+use super::editor::{
+    draw_state::CanvasDrawState,
+    history::{EditHistory, HistoryEvent},
+    painter::{EditorPainter, PaintTool},
+};
+use crate::{
+    input::InputState,
+    render::camera::OrthographicCamera,
+    simulator::{emitters::EmitterKind, simulation::Simulation},
+    GameState,
+};
 use anyhow::Result;
 
+const SOURCE_OUTLINE_COLOR: Color32 = Color32::from_rgb(80, 220, 120);
+const SINK_OUTLINE_COLOR: Color32 = Color32::from_rgb(230, 80, 80);
+
 #[bevy_plugin]
 pub fn PainterPlugin(app: &mut App) {
     app.init_resource::<EditorPainter>()
         .init_resource::<CanvasDrawState>()
-        .add_system(painter.run_if(in_state(GameState::Simulating)));
+        .init_resource::<EditHistory>()
+        .add_event::<HistoryEvent>()
+        .add_systems(
+            (painter, handle_history_events, emitter_outlines)
+                .chain()
+                .distributive_run_if(in_state(GameState::Simulating)),
+        );
 }
 
 #[sysfail(log(level = "error"))]
@@ -19,20 +41,93 @@ fn painter(
     buttons: Res<Input<MouseButton>>,
     mut simulator: ResMut<Simulation>,
     mut painter: ResMut<EditorPainter>,
+    mut history: ResMut<EditHistory>,
     mut draw_state: ResMut<CanvasDrawState>,
 ) -> Result<()> {
-    if buttons.just_pressed(MouseButton::Left) {
+    // Clicks on the gui don't paint (and don't end up in the history)
+    let clicked = buttons.just_pressed(MouseButton::Left) && !input_state.pointer_over_gui();
+    if clicked && painter.tool() != PaintTool::Brush {
+        painter.place_emitter(input_state.mouse_world_pos(), &mut simulator)?;
+    } else if clicked {
         draw_state.start(input_state.mouse_canvas_pos());
     }
-    if buttons.pressed(MouseButton::Left) {
+    if buttons.pressed(MouseButton::Left) && draw_state.started() {
         draw_state.draw(input_state.mouse_canvas_pos());
     }
     if buttons.just_released(MouseButton::Left) {
         draw_state.end();
+        history.end_stroke();
     }
 
     if draw_state.started() {
-        painter.paint_round_line(draw_state.prev, draw_state.current.unwrap(), &mut simulator)?
+        let end = draw_state.current.unwrap();
+        let start = draw_state.prev.unwrap_or(end);
+        history.extend_stroke(&mut simulator, start, end, painter.radius())?;
+        painter.paint_round_line(draw_state.prev, end, &mut simulator)?
+    }
+
+    // Right click removes emitters with any tool
+    if buttons.just_pressed(MouseButton::Right) && !input_state.pointer_over_gui() {
+        simulator.remove_emitter_at(input_state.mouse_world_pos().floor().as_ivec2());
+    }
+
+    Ok(())
+}
+
+/// Outline sources & sinks over the canvas
+fn emitter_outlines(
+    simulation: Res<Simulation>,
+    camera: Res<OrthographicCamera>,
+    window_query: Query<(Entity, &Window), With<PrimaryWindow>>,
+    vulkan_windows: NonSend<BevyVulkanoWindows>,
+) {
+    let Ok((window_entity, window)) = window_query.get_single() else { return };
+    let Some(vulkan_window) = vulkan_windows.get_vulkano_window(window_entity) else { return };
+    if simulation.emitters().is_empty() {
+        return;
+    }
+
+    let painter = vulkan_window
+        .gui
+        .context()
+        .layer_painter(egui::LayerId::new(
+            egui::Order::Background,
+            egui::Id::new("Emitter outlines"),
+        ));
+    for emitter in simulation.emitters() {
+        let color = match emitter.kind {
+            EmitterKind::Source => SOURCE_OUTLINE_COLOR,
+            EmitterKind::Sink => SINK_OUTLINE_COLOR,
+        };
+        let stroke = Stroke::new(1.5, color);
+        // Around the outer cells of the shape
+        let center = camera.world_to_gui_pos(window, emitter.world_pos().as_vec2() + 0.5);
+        let center = egui::pos2(center.x, center.y);
+        if emitter.is_square {
+            let half_size = ((emitter.radius * 2.0) as i32 / 2) as f32 + 0.5;
+            let half_size = egui::Vec2::splat(half_size / camera.scale());
+            painter.rect_stroke(
+                egui::Rect::from_center_size(center, half_size * 2.0),
+                0.0,
+                stroke,
+            );
+        } else {
+            painter.circle_stroke(center, (emitter.radius + 0.5) / camera.scale(), stroke);
+        }
+    }
+}
+
+#[sysfail(log(level = "error"))]
+fn handle_history_events(
+    mut simulator: ResMut<Simulation>,
+    mut history: ResMut<EditHistory>,
+    mut history_events: EventReader<HistoryEvent>,
+) -> Result<()> {
+    for event in history_events.iter() {
+        match event {
+            HistoryEvent::Undo => history.undo(&mut simulator)?,
+            HistoryEvent::Redo => history.redo(&mut simulator)?,
+        }
     }
 
     Ok(())
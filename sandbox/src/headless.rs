@@ -0,0 +1,459 @@
+use std::{
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use anyhow::{anyhow, bail, ensure, Context, Result};
+use bevy::math::UVec2;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use vulkano::device::DeviceExtensions;
+use vulkano_util::context::{VulkanoConfig, VulkanoContext};
+
+use crate::{
+    fs_interaction::{
+        asset_loading::{load_matter_definitions, matter_definition_path},
+        config::GameConfig,
+        export::{save_png, GifRecorder, RECORD_EVERY_STEPS},
+        image_import::{import_png, ColorMapping, ImageFit},
+        snapshot::WorldSnapshot,
+        FileUtils,
+    },
+    matter::{
+        matter_definition::{has_version_field, parse_matter_definitions, MatterDefinitions},
+        matter_file::MATTER_FILE_VERSION,
+        MatterCell,
+    },
+    settings::AppSettings,
+    simulator::{
+        backend::SimulationBackendKind,
+        ca_simulator::CASimulator,
+        cpu_simulator::{drawn_matter, pos_from_index, CPUSimulator},
+        grid_readback::GridCopyInfo,
+        simulation::Simulation,
+    },
+    utils::round_canvas_size,
+};
+
+pub const GAME_CONFIG_FILE: &str = "assets/config.game.toml";
+
+pub const USAGE: &str = "\
+Usage: bevy_mudslide_vulkano --headless [options]
+
+Options:
+    --steps <n>               Number of simulation steps (default 600)
+    --config <path>           Game config (default assets/config.game.toml)
+    --backend <Gpu|Cpu>       Override the configured simulation backend
+    --movement-steps <n>      Override AppSettings::movement_steps
+    --dispersion-steps <n>    Override AppSettings::dispersion_steps
+    --seed <n>                Override the configured simulation seed
+    --width <n>               Override the configured canvas width (rounded up to 32)
+    --height <n>              Override the configured canvas height (rounded up to 32)
+    --fill <name>=<density>   Randomly fill the canvas with matter, may be repeated
+    --load <path>             Start from a snapshot instead (uses its seed unless --seed is given)
+    --import <path>           Start from a png matter layout instead
+    --import-mapping <path>   Color to matter table (ron / toml), default nearest color
+    --import-fit <fit>        Stretch, fit or crop images not matching the canvas (default stretch)
+    --out-grid <path>         Write the final matter grid (little endian u32 per cell)
+    --out-image <path>        Write the final color image (png)
+    --out-gif <path>          Record an animated gif of the run
+    --gif-from <step>         First step recorded to the gif (default 0), steps count on from
+                              a loaded snapshot's step count
+    --gif-every <n>           Record every n:th step to the gif (default 2)
+    --out-snapshot <path>     Write a snapshot of the final world
+    --migrate-matters <path>  Rewrite a version 1 matter definition file in the current version,
+                              keeping the old one as <path>.v1.bak, & exit";
+
+/// Arguments of a headless run, everything after `--headless`
+#[derive(Debug)]
+pub struct HeadlessArgs {
+    pub steps: u32,
+    pub config: PathBuf,
+    pub backend: Option<SimulationBackendKind>,
+    pub movement_steps: Option<u32>,
+    pub dispersion_steps: Option<u32>,
+    pub seed: Option<u32>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub fill: Vec<(String, f32)>,
+    pub load: Option<PathBuf>,
+    pub import: Option<PathBuf>,
+    pub import_mapping: Option<PathBuf>,
+    pub import_fit: ImageFit,
+    pub out_grid: Option<PathBuf>,
+    pub out_image: Option<PathBuf>,
+    pub out_gif: Option<PathBuf>,
+    pub gif_from: u32,
+    pub gif_every: u32,
+    pub out_snapshot: Option<PathBuf>,
+    pub migrate_matters: Option<PathBuf>,
+}
+
+impl Default for HeadlessArgs {
+    fn default() -> Self {
+        HeadlessArgs {
+            steps: 600,
+            config: PathBuf::from(GAME_CONFIG_FILE),
+            backend: None,
+            movement_steps: None,
+            dispersion_steps: None,
+            seed: None,
+            width: None,
+            height: None,
+            fill: vec![],
+            load: None,
+            import: None,
+            import_mapping: None,
+            import_fit: ImageFit::default(),
+            out_grid: None,
+            out_image: None,
+            out_gif: None,
+            gif_from: 0,
+            gif_every: RECORD_EVERY_STEPS,
+            out_snapshot: None,
+            migrate_matters: None,
+        }
+    }
+}
+
+impl HeadlessArgs {
+    /// `None` if `--help` was asked for, `USAGE` is printed instead of running
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Option<HeadlessArgs>> {
+        let mut headless_args = HeadlessArgs::default();
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .with_context(|| format!("Missing value for {arg}"))
+            };
+            match arg.as_str() {
+                "--headless" => {}
+                "--steps" => headless_args.steps = parse_value(&value()?)?,
+                "--config" => headless_args.config = PathBuf::from(value()?),
+                "--backend" => {
+                    headless_args.backend = Some(match value()?.to_lowercase().as_str() {
+                        "gpu" => SimulationBackendKind::Gpu,
+                        "cpu" => SimulationBackendKind::Cpu,
+                        other => bail!("Unknown backend {other}, expected Gpu or Cpu"),
+                    })
+                }
+                "--movement-steps" => headless_args.movement_steps = Some(parse_value(&value()?)?),
+                "--dispersion-steps" => {
+                    headless_args.dispersion_steps = Some(parse_value(&value()?)?)
+                }
+                "--seed" => headless_args.seed = Some(parse_value(&value()?)?),
+                "--width" => headless_args.width = Some(parse_value(&value()?)?),
+                "--height" => headless_args.height = Some(parse_value(&value()?)?),
+                "--fill" => {
+                    let fill = value()?;
+                    let (name, density) = fill
+                        .split_once('=')
+                        .with_context(|| format!("Expected <name>=<density>, got {fill}"))?;
+                    headless_args
+                        .fill
+                        .push((name.to_string(), parse_value(density)?));
+                }
+                "--load" => headless_args.load = Some(PathBuf::from(value()?)),
+                "--import" => headless_args.import = Some(PathBuf::from(value()?)),
+                "--import-mapping" => headless_args.import_mapping = Some(PathBuf::from(value()?)),
+                "--import-fit" => headless_args.import_fit = value()?.parse()?,
+                "--out-grid" => headless_args.out_grid = Some(PathBuf::from(value()?)),
+                "--out-image" => headless_args.out_image = Some(PathBuf::from(value()?)),
+                "--out-gif" => headless_args.out_gif = Some(PathBuf::from(value()?)),
+                "--gif-from" => headless_args.gif_from = parse_value(&value()?)?,
+                "--gif-every" => headless_args.gif_every = parse_value(&value()?)?,
+                "--out-snapshot" => headless_args.out_snapshot = Some(PathBuf::from(value()?)),
+                "--migrate-matters" => {
+                    headless_args.migrate_matters = Some(PathBuf::from(value()?))
+                }
+                "--help" | "-h" => return Ok(None),
+                other => bail!("Unknown argument {other}\n\n{USAGE}"),
+            }
+        }
+
+        Ok(Some(headless_args))
+    }
+}
+
+fn parse_value<T: FromStr>(value: &str) -> Result<T>
+where
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    value
+        .parse::<T>()
+        .with_context(|| format!("Invalid value {value}"))
+}
+
+/// Read a matter definition file of any version and write it back in the current one. The old
+/// file is kept next to it as `<file>.v1.bak`.
+fn migrate_matter_file(path: &Path) -> Result<()> {
+    let text = FileUtils::read_str(path)?;
+    let matter_definitions = parse_matter_definitions(&text).map_err(|diagnostics| {
+        let diagnostics = diagnostics
+            .iter()
+            .map(|diagnostic| format!("{}:{}", path.display(), diagnostic))
+            .collect::<Vec<_>>();
+        anyhow!(
+            "Not migrating invalid matter definitions\n{}",
+            diagnostics.join("\n")
+        )
+    })?;
+    if has_version_field(&text) {
+        println!(
+            "{} is already a versioned matter file, not migrating",
+            path.display()
+        );
+        return Ok(());
+    }
+
+    let mut backup = path.as_os_str().to_owned();
+    backup.push(".v1.bak");
+    let backup = PathBuf::from(backup);
+    ensure!(
+        !backup.exists(),
+        "Backup {} already exists, move it before migrating",
+        backup.display()
+    );
+    std::fs::copy(path, &backup).with_context(|| {
+        format!(
+            "Failed to back up {} to {}",
+            path.display(),
+            backup.display()
+        )
+    })?;
+    println!("Backed up {} to {}", path.display(), backup.display());
+
+    matter_definitions.save(path)?;
+    println!(
+        "Wrote {} as matter file version {}",
+        path.display(),
+        MATTER_FILE_VERSION
+    );
+    Ok(())
+}
+
+/// Run the simulation without a window, gui or bevy app. Loads the game config & matter
+/// definitions, seeds the grid, steps `args.steps` times and writes the requested outputs.
+pub fn run(args: HeadlessArgs) -> Result<()> {
+    if let Some(path) = &args.migrate_matters {
+        return migrate_matter_file(path);
+    }
+
+    // Unlike the app, a run doesn't fall back to defaults, its results would silently differ
+    let config = FileUtils::read_toml::<GameConfig>(&args.config)
+        .with_context(|| format!("Failed to read game config {:?}", args.config))?;
+    let matter_definitions = load_matter_definitions(&config).map_err(|diagnostics| {
+        for diagnostic in &diagnostics {
+            eprintln!("{}:{}", matter_definition_path(&config), diagnostic);
+        }
+        anyhow!(
+            "{} has {} problems",
+            matter_definition_path(&config),
+            diagnostics.len()
+        )
+    })?;
+
+    let mut settings = AppSettings::new();
+    if let Some(movement_steps) = args.movement_steps {
+        settings.movement_steps = movement_steps;
+    }
+    if let Some(dispersion_steps) = args.dispersion_steps {
+        settings.dispersion_steps = dispersion_steps;
+    }
+    if let Some(seed) = args.seed.or(config.seed) {
+        settings.seed = seed;
+    }
+    settings.boundaries = config.boundaries.validated(&matter_definitions);
+
+    let config_size = config.canvas_size();
+    let canvas_size = round_canvas_size(UVec2::new(
+        args.width.unwrap_or(config_size.x),
+        args.height.unwrap_or(config_size.y),
+    ));
+
+    // Keep the vulkano context alive for as long as the simulation
+    let mut vulkano_context = None;
+    let backend_kind = args.backend.unwrap_or(config.simulation_backend);
+    let mut simulation = match backend_kind {
+        SimulationBackendKind::Gpu => {
+            let context = vulkano_context.insert(VulkanoContext::new(VulkanoConfig {
+                // Nothing is presented, so don't require a swapchain
+                device_extensions: DeviceExtensions::empty(),
+                ..VulkanoConfig::default()
+            }));
+            Simulation::from_backend(
+                Box::new(CASimulator::new(
+                    context.memory_allocator(),
+                    context.compute_queue().clone(),
+                    &matter_definitions,
+                    canvas_size,
+                )?),
+                &matter_definitions,
+            )?
+        }
+        SimulationBackendKind::Cpu => Simulation::from_backend(
+            Box::new(CPUSimulator::new(&matter_definitions, canvas_size)),
+            &matter_definitions,
+        )?,
+    };
+
+    if let Some(path) = &args.load {
+        let snapshot = WorldSnapshot::load(path)?;
+        if args.seed.is_none() {
+            settings.seed = snapshot.seed;
+        }
+        simulation.load_snapshot(snapshot, &matter_definitions)?;
+    } else if let Some(path) = &args.import {
+        let mapping = args
+            .import_mapping
+            .as_ref()
+            .map(ColorMapping::read)
+            .transpose()?;
+        let grid = import_png(
+            path,
+            mapping.as_ref(),
+            args.import_fit,
+            &matter_definitions,
+            canvas_size,
+        )?;
+        simulation.write_matter_grid(&grid)?;
+    } else {
+        let grid = seed_grid(&matter_definitions, &args.fill, settings.seed, canvas_size)?;
+        simulation.write_matter_grid(&grid)?;
+    }
+
+    let canvas_size = simulation.canvas_size();
+    println!(
+        "Running {} steps on {:?} backend with seed {} on a {}x{} canvas",
+        args.steps, backend_kind, settings.seed, canvas_size.x, canvas_size.y
+    );
+    let mut recorder = args
+        .out_gif
+        .as_ref()
+        .map(|path| GifRecorder::create(path, args.gif_every, None, &matter_definitions))
+        .transpose()?;
+    for _ in 0..args.steps {
+        simulation.step(&settings)?;
+        if let Some(recorder) = &mut recorder {
+            // Step counts continue those of a loaded snapshot, as do the recorded frames'
+            let sim_steps = simulation.sim_steps();
+            if sim_steps >= args.gif_from && recorder.is_due(sim_steps) {
+                let info = GridCopyInfo {
+                    sim_steps,
+                    canvas_size: simulation.canvas_size(),
+                    canvas_origin: simulation.canvas_origin(),
+                };
+                recorder.record(info, simulation.read_matter_grid()?)?;
+            }
+        }
+    }
+    println!(
+        "Simulated {} steps, avg {:.3} ms per step",
+        simulation.sim_steps(),
+        simulation.ca_timer.time_average_ms()
+    );
+
+    if let Some(path) = &args.out_grid {
+        let grid = simulation.read_matter_grid()?;
+        let bytes = grid
+            .iter()
+            .flat_map(|matter| matter.to_le_bytes())
+            .collect::<Vec<u8>>();
+        FileUtils::write_bytes(path, &bytes)?;
+        println!("Wrote matter grid to {:?}", path);
+    }
+
+    if let Some(path) = &args.out_image {
+        save_png(
+            path,
+            &simulation.read_matter_grid()?,
+            canvas_size,
+            &matter_definitions,
+        )?;
+        println!("Wrote color image to {:?}", path);
+    }
+
+    if let Some(recorder) = recorder {
+        let path = recorder.path().to_path_buf();
+        println!("Wrote {} gif frames to {:?}", recorder.finish()?, path);
+    }
+
+    if let Some(path) = &args.out_snapshot {
+        simulation
+            .snapshot(settings.seed, &matter_definitions)?
+            .save(path)?;
+        println!("Wrote snapshot to {:?}", path);
+    }
+
+    Ok(())
+}
+
+/// Empty grid randomly filled with matter by density. Later fills overwrite earlier ones. The same
+/// seed gives the same grid.
+fn seed_grid(
+    matter_definitions: &MatterDefinitions,
+    fill: &[(String, f32)],
+    seed: u32,
+    canvas_size: UVec2,
+) -> Result<Vec<u32>> {
+    let num_cells = (canvas_size.x * canvas_size.y) as usize;
+    let mut grid = vec![MatterCell::new(matter_definitions.empty, 0).value; num_cells];
+
+    let mut rng = StdRng::seed_from_u64(seed as u64);
+    for (name, density) in fill {
+        let definition = matter_definitions
+            .definitions
+            .iter()
+            .find(|def| def.name.eq_ignore_ascii_case(name))
+            .with_context(|| format!("Unknown matter {name}"))?;
+        for (index, cell) in grid.iter_mut().enumerate() {
+            if rng.gen::<f32>() < *density {
+                *cell = drawn_matter(pos_from_index(index, canvas_size), definition.id);
+            }
+        }
+    }
+
+    Ok(grid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Option<HeadlessArgs>> {
+        HeadlessArgs::parse(args.iter().map(ToString::to_string))
+    }
+
+    #[test]
+    fn parses_options() {
+        let args = parse(&[
+            "--headless",
+            "--steps",
+            "10",
+            "--fill",
+            "Sand=0.5",
+            "--gif-from",
+            "4",
+        ])
+        .unwrap()
+        .unwrap();
+        assert_eq!(args.steps, 10);
+        assert_eq!(args.fill, vec![("Sand".to_string(), 0.5)]);
+        assert_eq!(args.gif_from, 4);
+        assert_eq!(args.gif_every, RECORD_EVERY_STEPS);
+    }
+
+    #[test]
+    fn help_stops_parsing() {
+        assert!(parse(&["--headless", "--help"]).unwrap().is_none());
+        assert!(parse(&["-h", "--unknown"]).unwrap().is_none());
+    }
+
+    #[test]
+    fn rejects_invalid_arguments() {
+        assert!(parse(&["--unknown"]).is_err());
+        assert!(parse(&["--steps"]).is_err());
+        assert!(parse(&["--steps", "many"]).is_err());
+        assert!(parse(&["--fill", "Sand"]).is_err());
+    }
+}
@@ -8,7 +8,7 @@ use bevy::{
 use bevy_fn_plugin::bevy_plugin;
 use bevy_vulkano::BevyVulkanoWindows;
 
-use crate::{render::camera::OrthographicCamera, GameState};
+use crate::{render::camera::OrthographicCamera, simulator::simulation::Simulation, GameState};
 
 #[bevy_plugin]
 pub fn InputPlugin(app: &mut App) {
@@ -24,17 +24,28 @@ pub struct InputState {
     mouse_world_pos: Vec2,
     mouse_canvas_pos: Vec2,
     left_button_down: bool,
+    pointer_over_gui: bool,
 }
 
 impl InputState {
+    pub fn mouse_world_pos(&self) -> Vec2 {
+        self.mouse_world_pos
+    }
+
     pub fn mouse_canvas_pos(&self) -> Vec2 {
         self.mouse_canvas_pos
     }
+
+    /// The gui gets mouse input instead of the canvas
+    pub fn pointer_over_gui(&self) -> bool {
+        self.pointer_over_gui
+    }
 }
 
 pub fn update_input_state(
     window_query: Query<(Entity, &Window)>,
     camera: Res<OrthographicCamera>,
+    simulation: Res<Simulation>,
     mut input_state: ResMut<InputState>,
     vulkano_windows: NonSend<BevyVulkanoWindows>,
     mut mouse_button_input_events: EventReader<MouseButtonInput>,
@@ -51,9 +62,11 @@ pub fn update_input_state(
         // GUI gets priority input
         input_state.left_button_down = false;
         input_state.can_scroll = false;
+        input_state.pointer_over_gui = true;
         return;
     } else {
         input_state.can_scroll = true;
+        input_state.pointer_over_gui = false;
     }
 
     // Determine button state
@@ -66,7 +79,9 @@ pub fn update_input_state(
     // Update Mouse Position
     if let Some(cursor_pos) = primary.cursor_position() {
         input_state.mouse_world_pos = camera.screen_to_world_pos(primary, cursor_pos);
-        input_state.mouse_canvas_pos =
-            crate::utils::world_pos_to_canvas_pos(input_state.mouse_world_pos);
+        input_state.mouse_canvas_pos = crate::utils::world_pos_to_canvas_pos(
+            input_state.mouse_world_pos,
+            simulation.canvas_origin(),
+        );
     }
 }
@@ -1,6 +1,16 @@
-use crate::{settings::AppSettings, GameState};
-use bevy::prelude::*;
+use crate::{
+    fs_interaction::{
+        export::ExportEvent,
+        snapshot::{SnapshotEvent, QUICKSAVE_FILE},
+    },
+    gui::editor::history::HistoryEvent,
+    settings::AppSettings,
+    simulator::{simulation::Simulation, SimulationClock},
+    GameState,
+};
+use bevy::{prelude::*, window::PrimaryWindow};
 use bevy_fn_plugin::bevy_plugin;
+use bevy_vulkano::BevyVulkanoWindows;
 
 #[bevy_plugin]
 pub fn KeyboardPlugin(app: &mut App) {
@@ -9,9 +19,63 @@ pub fn KeyboardPlugin(app: &mut App) {
 
 pub fn keyboard_input_system(
     mut settings: ResMut<AppSettings>,
+    mut simulation: ResMut<Simulation>,
+    mut clock: ResMut<SimulationClock>,
     keyboard_input: Res<Input<KeyCode>>,
+    mut snapshot_events: EventWriter<SnapshotEvent>,
+    mut export_events: EventWriter<ExportEvent>,
+    mut history_events: EventWriter<HistoryEvent>,
+    vulkan_windows: NonSend<BevyVulkanoWindows>,
+    window_query: Query<Entity, With<PrimaryWindow>>,
 ) {
-    if keyboard_input.just_pressed(KeyCode::Space) {
+    // Keys typed into a gui text field aren't hotkeys
+    let is_typing = crate::utils::get_primary_window(&window_query, &vulkan_windows)
+        .map_or(false, |window| window.gui.context().wants_keyboard_input());
+
+    if !is_typing && keyboard_input.just_pressed(KeyCode::Space) {
         settings.is_paused = !settings.is_paused;
     }
+
+    // Advance a single step while paused, e.g. to follow rules cell by cell
+    if !is_typing && settings.is_paused && keyboard_input.just_pressed(KeyCode::Period) {
+        clock.request_step();
+    }
+
+    // Restart the step count, so the seeds of the following steps repeat
+    if !is_typing && keyboard_input.just_pressed(KeyCode::R) {
+        simulation.reset_clock();
+    }
+
+    // Debug overlay of the tiles that are simulated
+    if keyboard_input.just_pressed(KeyCode::F3) {
+        settings.show_awake_tiles = !settings.show_awake_tiles;
+    }
+
+    // Quicksave & quickload
+    if keyboard_input.just_pressed(KeyCode::F5) {
+        snapshot_events.send(SnapshotEvent::Save(QUICKSAVE_FILE.into()));
+    }
+    if keyboard_input.just_pressed(KeyCode::F9) {
+        snapshot_events.send(SnapshotEvent::Load(QUICKSAVE_FILE.into()));
+    }
+
+    // Undo & redo strokes
+    let ctrl = keyboard_input.any_pressed([KeyCode::LControl, KeyCode::RControl]);
+    let shift = keyboard_input.any_pressed([KeyCode::LShift, KeyCode::RShift]);
+    // Text fields have their own undo
+    if !is_typing && ctrl && keyboard_input.just_pressed(KeyCode::Z) {
+        history_events.send(if shift {
+            HistoryEvent::Redo
+        } else {
+            HistoryEvent::Undo
+        });
+    }
+
+    // Screenshot & start / stop gif recording
+    if keyboard_input.just_pressed(KeyCode::F12) {
+        export_events.send(ExportEvent::Screenshot);
+    }
+    if keyboard_input.just_pressed(KeyCode::F10) {
+        export_events.send(ExportEvent::ToggleRecording);
+    }
 }
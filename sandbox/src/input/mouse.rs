@@ -1,6 +1,8 @@
 use crate::{matter::Matters, simulator::simulation::Simulation, GameState};
+use anyhow::Result;
 use bevy::{prelude::*, window::PrimaryWindow};
 use bevy_fn_plugin::bevy_plugin;
+use bevy_mod_sysfail::macros::*;
 use bevy_vulkano::{egui_winit_vulkano::egui, BevyVulkanoWindows};
 
 use super::InputState;
@@ -10,14 +12,15 @@ pub fn MousePlugin(app: &mut App) {
     app.add_systems((mouse_input_system,).distributive_run_if(in_state(GameState::Simulating)));
 }
 
+#[sysfail(log(level = "error"))]
 pub fn mouse_input_system(
     matters: Res<Matters>,
     input_state: Res<InputState>,
     mut simulation: ResMut<Simulation>,
     vulkan_windows: NonSend<BevyVulkanoWindows>,
     window_query: Query<Entity, With<PrimaryWindow>>,
-) {
-    let Some(vulkan_window) = crate::utils::get_primary_window(&window_query, &vulkan_windows) else { return };
+) -> Result<()> {
+    let Some(vulkan_window) = crate::utils::get_primary_window(&window_query, &vulkan_windows) else { return Ok(()) };
     let ctx = vulkan_window.gui.context();
 
     let InputState {
@@ -25,6 +28,8 @@ pub fn mouse_input_system(
         mouse_canvas_pos,
         ..
     } = &*input_state;
+    // Arrives a frame or two after the cursor got there
+    let hovered_matter = simulation.query_matter(mouse_canvas_pos.as_ivec2())?;
 
     egui::containers::show_tooltip_at_pointer(&ctx, egui::Id::new("Hover tooltip"), |ui| {
         ui.label(format!(
@@ -36,11 +41,12 @@ pub fn mouse_input_system(
             mouse_canvas_pos.x, mouse_canvas_pos.y
         ));
 
-        if let Some(matter) = simulation.query_matter(mouse_canvas_pos.as_ivec2()) {
+        if let Some(matter) = hovered_matter {
             ui.label(format!(
                 "Matter: {}",
                 matters.0.get(&matter).unwrap_or(&"Unknown".to_string())
             ));
         }
     });
+    Ok(())
 }
@@ -3,6 +3,7 @@
 mod bevy_config;
 mod fs_interaction;
 mod gui;
+mod headless;
 mod input;
 mod matter;
 mod render;
@@ -14,11 +15,11 @@ mod utils;
 use bevy::{ecs::schedule::LogLevel, prelude::*, window::close_on_esc};
 
 pub const WORLD_UNIT_SIZE: f32 = 10.0;
+/// Default canvas width & height, see `GameConfig::canvas_size`
 pub const SIM_CANVAS_SIZE: u32 = 512;
 
-/// Kernel size x & y
+/// Kernel size x & y, canvas sizes are rounded up to multiples of it
 pub const KERNEL_SIZE: u32 = 32;
-pub const NUM_WORK_GROUPS: u32 = SIM_CANVAS_SIZE / KERNEL_SIZE;
 
 pub const CLEAR_COLOR: [f32; 4] = [0.05; 4];
 pub const CAMERA_MOVE_SPEED: f32 = 200.0;
@@ -34,6 +35,23 @@ enum GameState {
 }
 
 fn main() {
+    // Run the simulation without window, gui or bevy app, e.g. for batch experiments on servers
+    if std::env::args().any(|arg| arg == "--headless") {
+        let result =
+            headless::HeadlessArgs::parse(std::env::args().skip(1)).and_then(|args| match args {
+                Some(args) => headless::run(args),
+                None => {
+                    println!("{}", headless::USAGE);
+                    Ok(())
+                }
+            });
+        if let Err(e) = result {
+            eprintln!("Headless simulation failed: {:?}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
     App::new()
         .edit_schedule(bevy::app::CoreSchedule::Main, |schedule| {
             schedule.set_build_settings(bevy::ecs::schedule::ScheduleBuildSettings {
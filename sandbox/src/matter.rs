@@ -1,53 +1,152 @@
 pub mod direction;
 pub mod matter_definition;
+pub mod matter_file;
 pub mod matter_reaction;
 pub mod matter_state;
 
-use bevy::{prelude::Resource, utils::HashMap};
+use bevy::{math::IVec2, prelude::Resource, utils::HashMap};
 
 use self::{
     matter_definition::{MatterDefinition, MatterDefinitions},
-    matter_reaction::MatterReaction,
+    matter_reaction::{MatterReaction, TemperatureTransition},
     matter_state::{MatterCharacteristic, MatterState},
 };
-use crate::utils::u8_rgba_to_u32_rgba;
-
 #[derive(Resource)]
 pub struct Matters(pub HashMap<u32, String>);
 
-/// Matter data where first 3 bytes are saved for color and last 4th byte is saved for matter
-/// identifier
+impl Matters {
+    pub fn from_definitions(matter_definitions: &MatterDefinitions) -> Matters {
+        Matters(
+            matter_definitions
+                .definitions
+                .iter()
+                .map(|definition| (definition.id, definition.name.clone()))
+                .collect(),
+        )
+    }
+}
+
+/// A single cell of the matter grid. Same layout as in `compute_shaders/helpers/matter.glsl`:
+/// - bits 0-15: matter identifier
+/// - bits 16-23: color variation, the color itself comes from the matter's definition
+/// - bits 24-31: unused
 #[derive(Default, Copy, Clone)]
-pub struct MatterWithColor {
+pub struct MatterCell {
     pub value: u32,
 }
 
-impl MatterWithColor {
-    /// Creates a new matter with color from matter id giving it a slightly randomized color
-    pub fn new(matter_id: u32, color: [u8; 4]) -> MatterWithColor {
-        MatterWithColor {
-            value: u8_rgba_to_u32_rgba(color[0], color[1], color[2], matter_id as u8),
+impl MatterCell {
+    pub fn new(matter_id: u32, variation: u8) -> MatterCell {
+        MatterCell {
+            value: ((variation as u32) << 16) | (matter_id & MATTER_ID_MASK),
         }
     }
 
     pub fn matter_id(&self) -> u32 {
-        self.value & 255
+        self.value & MATTER_ID_MASK
+    }
+
+    pub fn variation(&self) -> u8 {
+        (self.value >> 16) as u8
+    }
+
+    /// Other matter, same color variation
+    pub fn with_matter_id(self, matter_id: u32) -> MatterCell {
+        MatterCell::new(matter_id, self.variation())
+    }
+
+    /// Matter moved to its id in `id_map`, empty if it has none (e.g. it was removed)
+    pub fn remapped(self, id_map: &[Option<u32>], empty_matter: u32) -> MatterCell {
+        let id = id_map.get(self.matter_id() as usize).copied().flatten();
+        self.with_matter_id(id.unwrap_or(empty_matter))
+    }
+}
+
+impl From<u32> for MatterCell {
+    fn from(item: u32) -> Self {
+        Self { value: item }
+    }
+}
+
+/// State of a single cell that moves along with its matter, kept in a grid next to the matter
+/// grid. Same layout as in `compute_shaders/helpers/matter.glsl`:
+/// - bits 0-7: lifetime, steps left until the matter becomes empty (0 lives forever)
+/// - bits 8-15: velocity x, signed
+/// - bits 16-23: velocity y, signed
+/// - bits 24-31: flags, free for kernels to use
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq)]
+pub struct CellData {
+    pub value: u32,
+}
+
+impl CellData {
+    /// Velocity components are clamped to -128..=127
+    pub fn new(lifetime: u8, velocity: IVec2, flags: u8) -> CellData {
+        CellData::default()
+            .with_lifetime(lifetime)
+            .with_velocity(velocity)
+            .with_flags(flags)
+    }
+
+    pub fn lifetime(&self) -> u8 {
+        (self.value & 255) as u8
+    }
+
+    pub fn velocity(&self) -> IVec2 {
+        IVec2::new(
+            (self.value >> 8) as u8 as i8 as i32,
+            (self.value >> 16) as u8 as i8 as i32,
+        )
+    }
+
+    pub fn flags(&self) -> u8 {
+        (self.value >> 24) as u8
+    }
+
+    pub fn with_lifetime(self, lifetime: u8) -> CellData {
+        CellData {
+            value: (self.value & !255) | lifetime as u32,
+        }
+    }
+
+    pub fn with_velocity(self, velocity: IVec2) -> CellData {
+        let component = |v: i32| v.clamp(i8::MIN as i32, i8::MAX as i32) as i8 as u8 as u32;
+        CellData {
+            value: (self.value & 0xff00_00ff)
+                | (component(velocity.x) << 8)
+                | (component(velocity.y) << 16),
+        }
+    }
+
+    pub fn with_flags(self, flags: u8) -> CellData {
+        CellData {
+            value: (self.value & 0x00ff_ffff) | ((flags as u32) << 24),
+        }
     }
 }
 
-impl From<u32> for MatterWithColor {
+impl From<u32> for CellData {
     fn from(item: u32) -> Self {
         Self { value: item }
     }
 }
 
-pub const MAX_NUM_MATTERS: u32 = 256;
+/// Matter ids fit in the 16 id bits of a `MatterCell`
+pub const MAX_NUM_MATTERS: u32 = 1 << 16;
+const MATTER_ID_MASK: u32 = MAX_NUM_MATTERS - 1;
 pub const MATTER_DEFINITION_FILE: &str = "assets/matter_definitions.matter.ron";
 
 pub const MATTER_EMPTY: u32 = 0;
 pub const MATTER_SAND: u32 = 1;
 pub const MATTER_WATER: u32 = 2;
 pub const MATTER_GAS: u32 = 3;
+pub const MATTER_ACID: u32 = 4;
+pub const MATTER_LAVA: u32 = 5;
+pub const MATTER_ICE: u32 = 6;
+pub const MATTER_STEAM: u32 = 7;
+pub const MATTER_GLASS: u32 = 8;
+pub const MATTER_FIRE: u32 = 9;
+pub const MATTER_SMOKE: u32 = 10;
 
 pub fn default_matter_definitions() -> MatterDefinitions {
     MatterDefinitions {
@@ -62,6 +161,9 @@ pub fn default_matter_definitions() -> MatterDefinitions {
                 state: MatterState::Empty,
                 reactions: MatterReaction::all_zero(),
                 characteristics: MatterCharacteristic::empty(),
+                // Air, barely conducts
+                conductivity: 0.02,
+                ..MatterDefinition::zero()
             },
             MatterDefinition {
                 id: MATTER_SAND,
@@ -70,8 +172,25 @@ pub fn default_matter_definitions() -> MatterDefinitions {
                 color: 0xc2b280ff,
                 name: "Sand".to_string(),
                 state: MatterState::Powder,
-                reactions: MatterReaction::all_zero(),
+                reactions: [
+                    MatterReaction::becomes_on_touch(
+                        0.1,
+                        MatterCharacteristic::CORROSIVE,
+                        MATTER_EMPTY,
+                    ),
+                    MatterReaction::becomes_on_touch(
+                        0.01,
+                        MatterCharacteristic::MELTING,
+                        MATTER_LAVA,
+                    ),
+                    MatterReaction::zero(),
+                    MatterReaction::zero(),
+                    MatterReaction::zero(),
+                ],
                 characteristics: (MatterCharacteristic::MELTS | MatterCharacteristic::CORRODES),
+                conductivity: 0.3,
+                melts: Some(TemperatureTransition::new(1000.0, MATTER_GLASS)),
+                ..MatterDefinition::zero()
             },
             MatterDefinition {
                 id: MATTER_WATER,
@@ -82,6 +201,11 @@ pub fn default_matter_definitions() -> MatterDefinitions {
                 state: MatterState::Liquid,
                 reactions: MatterReaction::all_zero(),
                 characteristics: MatterCharacteristic::empty(),
+                conductivity: 0.6,
+                heat_capacity: 4.0,
+                boils: Some(TemperatureTransition::new(100.0, MATTER_STEAM)),
+                freezes: Some(TemperatureTransition::new(-1.0, MATTER_ICE)),
+                ..MatterDefinition::zero()
             },
             MatterDefinition {
                 id: MATTER_GAS,
@@ -93,6 +217,104 @@ pub fn default_matter_definitions() -> MatterDefinitions {
                 reactions: MatterReaction::all_zero(),
                 ..MatterDefinition::zero()
             },
+            MatterDefinition {
+                id: MATTER_ACID,
+                weight: 1.1,
+                dispersion: 8,
+                color: 0x80ff00ff,
+                name: "Acid".to_string(),
+                state: MatterState::Liquid,
+                reactions: [
+                    MatterReaction::becomes_on_touch(
+                        0.05,
+                        MatterCharacteristic::CORRODES,
+                        MATTER_EMPTY,
+                    ),
+                    MatterReaction::zero(),
+                    MatterReaction::zero(),
+                    MatterReaction::zero(),
+                    MatterReaction::zero(),
+                ],
+                characteristics: MatterCharacteristic::CORROSIVE,
+                conductivity: 0.5,
+                heat_capacity: 3.0,
+                ..MatterDefinition::zero()
+            },
+            MatterDefinition {
+                id: MATTER_LAVA,
+                weight: 2.0,
+                dispersion: 2,
+                color: 0xff4500ff,
+                name: "Lava".to_string(),
+                state: MatterState::Liquid,
+                reactions: MatterReaction::all_zero(),
+                characteristics: MatterCharacteristic::MELTING,
+                temperature: 1500.0,
+                conductivity: 0.5,
+                heat_capacity: 2.0,
+                ..MatterDefinition::zero()
+            },
+            MatterDefinition {
+                id: MATTER_ICE,
+                weight: 1.0,
+                dispersion: 0,
+                color: 0xa5f2f3ff,
+                name: "Ice".to_string(),
+                state: MatterState::Solid,
+                temperature: -10.0,
+                conductivity: 0.8,
+                heat_capacity: 2.0,
+                melts: Some(TemperatureTransition::new(1.0, MATTER_WATER)),
+                ..MatterDefinition::zero()
+            },
+            MatterDefinition {
+                id: MATTER_STEAM,
+                weight: 0.05,
+                dispersion: 5,
+                color: 0xd0dde6ff,
+                name: "Steam".to_string(),
+                state: MatterState::Gas,
+                temperature: 110.0,
+                conductivity: 0.1,
+                heat_capacity: 2.0,
+                freezes: Some(TemperatureTransition::new(95.0, MATTER_WATER)),
+                ..MatterDefinition::zero()
+            },
+            MatterDefinition {
+                id: MATTER_GLASS,
+                weight: 2.5,
+                dispersion: 0,
+                color: 0xb9d7d9ff,
+                name: "Glass".to_string(),
+                state: MatterState::Solid,
+                conductivity: 0.4,
+                ..MatterDefinition::zero()
+            },
+            MatterDefinition {
+                id: MATTER_FIRE,
+                weight: 0.05,
+                dispersion: 3,
+                color: 0xff8c00ff,
+                name: "Fire".to_string(),
+                state: MatterState::Gas,
+                temperature: 800.0,
+                conductivity: 0.3,
+                // Burns out
+                lifetime: 40,
+                ..MatterDefinition::zero()
+            },
+            MatterDefinition {
+                id: MATTER_SMOKE,
+                weight: 0.08,
+                dispersion: 4,
+                color: 0x505050ff,
+                name: "Smoke".to_string(),
+                state: MatterState::Gas,
+                conductivity: 0.05,
+                // Dissipates
+                lifetime: 200,
+                ..MatterDefinition::zero()
+            },
         ],
     }
 }
@@ -1,7 +1,7 @@
 use std::fmt;
 
 use bitflags::bitflags;
-use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
 
 use crate::utils::U32Visitor;
 
@@ -51,7 +51,8 @@ impl<'de> Deserialize<'de> for Direction {
         D: Deserializer<'de>,
     {
         let res = deserializer.deserialize_u32(U32Visitor)?;
-        Ok(Direction::from_bits(res).unwrap())
+        Direction::from_bits(res)
+            .ok_or_else(|| D::Error::custom(format!("invalid direction bits {:#b}", res)))
     }
 }
 
@@ -1,13 +1,35 @@
-use bevy::{prelude::Resource, reflect::TypeUuid};
+use std::{cmp::Ordering, collections::HashSet, fmt, path::Path};
+
+use bevy::prelude::Resource;
 use serde::{Deserialize, Serialize};
+use thiserror::Error;
 
 use super::{
-    matter_reaction::MatterReaction,
+    matter_file::{MatterFile, MATTER_FILE_VERSION},
+    matter_reaction::{MatterReaction, TemperatureTransition},
     matter_state::{MatterCharacteristic, MatterState},
+    CellData, MAX_NUM_MATTERS,
 };
+use crate::fs_interaction::file_utils::{FileResult, FileUtils};
 
 /// If you touch this, also change shaders...
 pub const MAX_TRANSITIONS: u32 = 5;
+/// Melts, boils & freezes, in this order. If you touch this, also change shaders...
+pub const NUM_TEMPERATURE_TRANSITIONS: u32 = 3;
+
+/// Lifetime is a byte of `CellData`
+pub const MAX_LIFETIME: u32 = 255;
+
+/// Temperature (°C) of matter that doesn't define its own
+pub const AMBIENT_TEMPERATURE: f32 = 20.0;
+
+pub(super) fn default_temperature() -> f32 {
+    AMBIENT_TEMPERATURE
+}
+
+pub(super) fn default_heat_capacity() -> f32 {
+    1.0
+}
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct MatterDefinition {
@@ -30,6 +52,33 @@ pub struct MatterDefinition {
     ///   (corroding)".
     /// Probability will affect the speed at which matter changes
     pub reactions: [MatterReaction; MAX_TRANSITIONS as usize],
+
+    /// Temperature (°C) the matter is drawn & created with
+    #[serde(default = "default_temperature")]
+    pub temperature: f32,
+    /// How well heat flows to neighbors, 0 (insulates) to 1. Between two cells the lower
+    /// conductivity wins.
+    #[serde(default)]
+    pub conductivity: f32,
+    /// Heat needed to change temperature, at least 1. Higher heats up & cools down slower.
+    #[serde(default = "default_heat_capacity")]
+    pub heat_capacity: f32,
+
+    /// Becomes another matter at or above the melting point, e.g. sand -> glass
+    #[serde(default)]
+    pub melts: Option<TemperatureTransition>,
+    /// Becomes another matter at or above the boiling point, e.g. water -> steam
+    #[serde(default)]
+    pub boils: Option<TemperatureTransition>,
+    /// Becomes another matter at or below the freezing point, e.g. water -> ice. Keep it below
+    /// the melting point of what it becomes, or matter flips back and forth.
+    #[serde(default)]
+    pub freezes: Option<TemperatureTransition>,
+
+    /// Steps until the matter becomes empty, e.g. fire burning out. 0 lives forever, at most
+    /// `MAX_LIFETIME`.
+    #[serde(default)]
+    pub lifetime: u32,
 }
 
 impl Default for MatterDefinition {
@@ -55,21 +104,112 @@ impl MatterDefinition {
                 MatterReaction::zero(),
                 MatterReaction::zero(),
             ],
+            temperature: AMBIENT_TEMPERATURE,
+            conductivity: 0.0,
+            heat_capacity: 1.0,
+            melts: None,
+            boils: None,
+            freezes: None,
+            lifetime: 0,
         }
     }
+
+    /// (temperature, becomes) of melts, boils & freezes as the kernels read them. Missing
+    /// transitions get a temperature that is never crossed and become the matter itself.
+    pub fn temperature_transitions(&self) -> [(f32, u32); NUM_TEMPERATURE_TRANSITIONS as usize] {
+        let or_never = |transition: Option<TemperatureTransition>, never: f32| {
+            transition.map_or((never, self.id), |t| (t.temperature, t.becomes))
+        };
+        [
+            or_never(self.melts, f32::MAX),
+            or_never(self.boils, f32::MAX),
+            or_never(self.freezes, f32::MIN),
+        ]
+    }
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, Resource, TypeUuid)]
-#[uuid = "f3b0c0f0-1d1a-4b0a-9b0a-1d1a4b0a9b0a"]
+#[derive(Serialize, Deserialize, Debug, Clone, Resource)]
 pub struct MatterDefinitions {
     pub empty: u32,
     pub definitions: Vec<MatterDefinition>,
 }
 
 impl MatterDefinitions {
+    /// Temperature a matter is created with
+    pub fn temperature(&self, matter: u32) -> f32 {
+        self.definitions
+            .get(matter as usize)
+            .map_or(AMBIENT_TEMPERATURE, |def| def.temperature)
+    }
+
+    /// Cell data a matter is created with
+    pub fn cell_data(&self, matter: u32) -> CellData {
+        let lifetime = self
+            .definitions
+            .get(matter as usize)
+            .map_or(0, |def| def.lifetime.min(MAX_LIFETIME));
+        CellData::default().with_lifetime(lifetime as u8)
+    }
+
+    /// New id of each matter of `old` (indexed by its id) by name, `None` for matter that isn't
+    /// defined anymore
+    pub fn id_map_by_name(&self, old: &MatterDefinitions) -> Vec<Option<u32>> {
+        old.definitions
+            .iter()
+            .map(|old| {
+                self.definitions
+                    .iter()
+                    .find(|new| new.name == old.name)
+                    .map(|new| new.id)
+            })
+            .collect()
+    }
+
+    /// Remove a matter, the ids after it move down by one. Reactions that became it turn into no
+    /// reaction, transitions to it are removed. Returns the new id of each old id (`None` for the
+    /// removed matter), or `None` for the empty matter, which can't be removed.
+    pub fn remove(&mut self, id: u32) -> Option<Vec<Option<u32>>> {
+        if id == self.empty || id as usize >= self.definitions.len() {
+            return None;
+        }
+
+        let id_map = (0..self.definitions.len() as u32)
+            .map(|old| match old.cmp(&id) {
+                Ordering::Less => Some(old),
+                Ordering::Equal => None,
+                Ordering::Greater => Some(old - 1),
+            })
+            .collect::<Vec<Option<u32>>>();
+        let new_id = |old: u32| id_map[old as usize].unwrap();
+
+        self.definitions.remove(id as usize);
+        self.empty = new_id(self.empty);
+        for def in self.definitions.iter_mut() {
+            def.id = new_id(def.id);
+            for reaction in def.reactions.iter_mut() {
+                if reaction.becomes == id {
+                    *reaction = MatterReaction::zero();
+                } else {
+                    reaction.becomes = new_id(reaction.becomes);
+                }
+            }
+            for transition in [&mut def.melts, &mut def.boils, &mut def.freezes] {
+                *transition = transition
+                    .filter(|t| t.becomes != id)
+                    .map(|t| TemperatureTransition::new(t.temperature, new_id(t.becomes)));
+            }
+        }
+        Some(id_map)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> FileResult<()> {
+        FileUtils::write_str(path, &self.serialize())
+    }
+
+    /// The definitions as a matter file of the current version, see `MatterFile`
     pub fn serialize(&self) -> String {
         ron::ser::to_string_pretty(
-            self,
+            &MatterFile::new(self),
             ron::ser::PrettyConfig::new()
                 .struct_names(true)
                 .enumerate_arrays(true)
@@ -79,26 +219,539 @@ impl MatterDefinitions {
     }
 }
 
-pub fn validate_matter_definitions(matter_definitions: &MatterDefinitions) {
-    for (i, m) in matter_definitions.definitions.iter().enumerate() {
-        if m.id != i as u32 {
-            panic!(
-                "Invalid matter definition, definition {}: id {} does not equal matter id index {}",
-                m.name,
-                { m.id },
-                i as u32
-            );
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum MatterDefinitionError {
+    #[error("Could not read the file: {0}")]
+    Read(String),
+    #[error("{0}")]
+    Parse(String),
+    #[error(
+        "Matter file version {0} is newer than the supported version {}",
+        MATTER_FILE_VERSION
+    )]
+    UnsupportedVersion(u32),
+    #[error("Empty matter {0:?} is not a matter name")]
+    UnknownEmpty(String),
+    #[error("{name}: there's no matter named {reference:?}")]
+    UnknownMatter {
+        index: usize,
+        name: String,
+        reference: String,
+    },
+    #[error("{name}: {count} reactions, at most {} are supported", MAX_TRANSITIONS)]
+    TooManyReactions {
+        index: usize,
+        name: String,
+        count: usize,
+    },
+    #[error(
+        "Too many matter definitions: {0}, at most {} are supported",
+        MAX_NUM_MATTERS
+    )]
+    TooMany(usize),
+    #[error("{name}: id {id} does not equal its index {index}")]
+    InvalidId { index: usize, name: String, id: u32 },
+    #[error("{name}: the name is used by more than one matter")]
+    DuplicateName { index: usize, name: String },
+    #[error("{name}: reaction {reaction} becomes {becomes}, which is not a matter id")]
+    InvalidReaction {
+        index: usize,
+        name: String,
+        reaction: usize,
+        becomes: u32,
+    },
+    #[error("{name}: {transition} becomes {becomes}, which is not a matter id")]
+    InvalidTransition {
+        index: usize,
+        name: String,
+        transition: &'static str,
+        becomes: u32,
+    },
+    #[error("{name}: lifetime {lifetime} is larger than {}", MAX_LIFETIME)]
+    InvalidLifetime {
+        index: usize,
+        name: String,
+        lifetime: u32,
+    },
+    #[error("Empty matter {0} is not a matter id")]
+    MissingEmpty(u32),
+    #[error("Empty matter {name} ({id}) is in the {state} state, not Empty")]
+    EmptyNotEmpty {
+        id: u32,
+        name: String,
+        state: MatterState,
+    },
+}
+
+impl MatterDefinitionError {
+    /// Index of the definition the error is about, if it's about one
+    pub fn definition_index(&self) -> Option<usize> {
+        match self {
+            MatterDefinitionError::InvalidId {
+                index, ..
+            }
+            | MatterDefinitionError::DuplicateName {
+                index, ..
+            }
+            | MatterDefinitionError::InvalidReaction {
+                index, ..
+            }
+            | MatterDefinitionError::InvalidTransition {
+                index, ..
+            }
+            | MatterDefinitionError::InvalidLifetime {
+                index, ..
+            }
+            | MatterDefinitionError::UnknownMatter {
+                index, ..
+            }
+            | MatterDefinitionError::TooManyReactions {
+                index, ..
+            } => Some(*index),
+            MatterDefinitionError::EmptyNotEmpty {
+                id, ..
+            } => Some(*id as usize),
+            _ => None,
         }
+    }
+}
 
-        if m.reactions
-            .iter()
-            .any(|r| r.becomes >= matter_definitions.definitions.len() as u32)
-        {
-            panic!(
-                "Matter reaction invalid for id: {}, name: {}. 'becomes' must not be larger than \
-                 any id",
-                m.id, m.name
-            )
+/// A matter definition error & its line and column (from 1) in the definition file, if known
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatterDefinitionDiagnostic {
+    pub error: MatterDefinitionError,
+    pub position: Option<(usize, usize)>,
+}
+
+impl fmt::Display for MatterDefinitionDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.position {
+            Some((line, col)) => write!(f, "{}:{}: {}", line, col, self.error),
+            None => write!(f, "{}", self.error),
         }
     }
 }
+
+/// Every problem with the definitions, empty if they're fine to simulate
+pub fn validate_matter_definitions(
+    matter_definitions: &MatterDefinitions,
+) -> Vec<MatterDefinitionError> {
+    let definitions = &matter_definitions.definitions;
+    let num_matters = definitions.len() as u32;
+    let mut errors = vec![];
+
+    // Ids equal indices, so this also keeps ids within a matter cell's id bits
+    if definitions.len() > MAX_NUM_MATTERS as usize {
+        errors.push(MatterDefinitionError::TooMany(definitions.len()));
+    }
+
+    match definitions.get(matter_definitions.empty as usize) {
+        None => errors.push(MatterDefinitionError::MissingEmpty(
+            matter_definitions.empty,
+        )),
+        Some(empty) if empty.state != MatterState::Empty => {
+            errors.push(MatterDefinitionError::EmptyNotEmpty {
+                id: matter_definitions.empty,
+                name: empty.name.clone(),
+                state: empty.state,
+            })
+        }
+        _ => {}
+    }
+
+    let mut names = HashSet::new();
+    for (index, m) in definitions.iter().enumerate() {
+        let name = m.name.clone();
+        if m.id != index as u32 {
+            errors.push(MatterDefinitionError::InvalidId {
+                index,
+                name: name.clone(),
+                id: m.id,
+            });
+        }
+
+        if !names.insert(&m.name) {
+            errors.push(MatterDefinitionError::DuplicateName {
+                index,
+                name: name.clone(),
+            });
+        }
+
+        for (reaction, r) in m.reactions.iter().enumerate() {
+            if r.becomes >= num_matters {
+                errors.push(MatterDefinitionError::InvalidReaction {
+                    index,
+                    name: name.clone(),
+                    reaction,
+                    becomes: r.becomes,
+                });
+            }
+        }
+
+        for (transition, t) in [
+            ("melts", m.melts),
+            ("boils", m.boils),
+            ("freezes", m.freezes),
+        ] {
+            if let Some(t) = t.filter(|t| t.becomes >= num_matters) {
+                errors.push(MatterDefinitionError::InvalidTransition {
+                    index,
+                    name: name.clone(),
+                    transition,
+                    becomes: t.becomes,
+                });
+            }
+        }
+
+        if m.lifetime > MAX_LIFETIME {
+            errors.push(MatterDefinitionError::InvalidLifetime {
+                index,
+                name,
+                lifetime: m.lifetime,
+            });
+        }
+    }
+
+    errors
+}
+
+/// Matter definitions from the text of a definition file, or every problem with them and where
+/// it is in the text
+pub fn parse_matter_definitions(
+    text: &str,
+) -> Result<MatterDefinitions, Vec<MatterDefinitionDiagnostic>> {
+    let matter_definitions = read_matter_file(text)?;
+
+    let errors = validate_matter_definitions(&matter_definitions);
+    if errors.is_empty() {
+        return Ok(matter_definitions);
+    }
+    let names = matter_definitions
+        .definitions
+        .iter()
+        .map(|m| m.name.as_str())
+        .collect::<Vec<&str>>();
+    Err(locate_errors(text, &names, errors))
+}
+
+/// Read a matter file of any version without validating it. Files without a `version` are version
+/// 1, which is migrated by using it as is.
+fn read_matter_file(text: &str) -> Result<MatterDefinitions, Vec<MatterDefinitionDiagnostic>> {
+    let parse_error = |e: ron::error::SpannedError| {
+        vec![MatterDefinitionDiagnostic {
+            error: MatterDefinitionError::Parse(e.code.to_string()),
+            position: Some((e.position.line, e.position.col)),
+        }]
+    };
+
+    if !has_version_field(text) {
+        let matter_definitions = ron::from_str::<MatterDefinitions>(text).map_err(parse_error)?;
+        log::warn!(
+            "Matter definitions are in version 1 of the matter file, saving them writes version {}",
+            MATTER_FILE_VERSION
+        );
+        return Ok(matter_definitions);
+    }
+
+    let matter_file = ron::from_str::<MatterFile>(text).map_err(parse_error)?;
+    if matter_file.version > MATTER_FILE_VERSION {
+        let error = MatterDefinitionError::UnsupportedVersion(matter_file.version);
+        return Err(locate_errors(text, &[], vec![error]));
+    }
+    let names = matter_file
+        .matters
+        .iter()
+        .map(|m| m.name.clone())
+        .collect::<Vec<String>>();
+    matter_file.into_definitions().map_err(|errors| {
+        let names = names.iter().map(String::as_str).collect::<Vec<&str>>();
+        locate_errors(text, &names, errors)
+    })
+}
+
+/// Whether `version:` is in the text, other than as part of a longer name. Matter files without it
+/// are version 1.
+pub fn has_version_field(text: &str) -> bool {
+    find_field(text, "version").is_some()
+}
+
+/// Offset of the first `field:` key in the text, the word in longer keys or quoted names doesn't
+/// count
+fn find_field(text: &str, field: &str) -> Option<usize> {
+    text.match_indices(field)
+        .map(|(offset, _)| offset)
+        .find(|&offset| {
+            let is_start = text[..offset]
+                .chars()
+                .next_back()
+                .map_or(true, |c| !c.is_alphanumeric() && c != '_' && c != '"');
+            is_start && text[offset + field.len()..].trim_start().starts_with(':')
+        })
+}
+
+fn locate_errors(
+    text: &str,
+    names: &[&str],
+    errors: Vec<MatterDefinitionError>,
+) -> Vec<MatterDefinitionDiagnostic> {
+    errors
+        .into_iter()
+        .map(|error| MatterDefinitionDiagnostic {
+            position: find_definition(text, names, &error),
+            error,
+        })
+        .collect()
+}
+
+/// Line & column of the definition an error is about, found by its `name` field. A name used by
+/// earlier definitions too is found after their occurrences of it. Errors about the whole set point
+/// at the `version` or `empty` field, `None` if the text has no such field.
+fn find_definition(
+    text: &str,
+    names: &[&str],
+    error: &MatterDefinitionError,
+) -> Option<(usize, usize)> {
+    let offset = match error.definition_index() {
+        Some(index) => {
+            let name = names.get(index)?;
+            let same_name_before = names[..index].iter().filter(|n| *n == name).count();
+            text.match_indices(&format!("{:?}", name))
+                .map(|(offset, _)| offset)
+                .filter(|offset| {
+                    let before = text[..*offset].trim_end();
+                    before
+                        .strip_suffix(':')
+                        .map_or(false, |field| field.trim_end().ends_with("name"))
+                })
+                .nth(same_name_before)?
+        }
+        None if matches!(error, MatterDefinitionError::UnsupportedVersion(_)) => {
+            find_field(text, "version")?
+        }
+        None => find_field(text, "empty")?,
+    };
+
+    let before = &text[..offset];
+    let line = before.matches('\n').count() + 1;
+    let col = before.len() - before.rfind('\n').map_or(0, |newline| newline + 1) + 1;
+    Some((line, col))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matter::{
+        default_matter_definitions, MATTER_GLASS, MATTER_ICE, MATTER_LAVA, MATTER_SAND,
+        MATTER_STEAM, MATTER_WATER,
+    };
+
+    #[test]
+    fn default_definitions_are_valid() {
+        assert_eq!(
+            validate_matter_definitions(&default_matter_definitions()),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn validate_reports_every_problem() {
+        let mut matter_definitions = default_matter_definitions();
+        let definitions = &mut matter_definitions.definitions;
+        definitions[MATTER_SAND as usize].id = 7;
+        definitions[MATTER_WATER as usize].name = "Sand".to_string();
+        definitions[MATTER_WATER as usize].reactions[0].becomes = 99;
+        definitions[MATTER_ICE as usize].melts = Some(TemperatureTransition::new(1.0, 42));
+        definitions[MATTER_LAVA as usize].lifetime = MAX_LIFETIME + 1;
+
+        assert_eq!(validate_matter_definitions(&matter_definitions), vec![
+            MatterDefinitionError::InvalidId {
+                index: 1,
+                name: "Sand".to_string(),
+                id: 7,
+            },
+            MatterDefinitionError::DuplicateName {
+                index: 2,
+                name: "Sand".to_string(),
+            },
+            MatterDefinitionError::InvalidReaction {
+                index: 2,
+                name: "Sand".to_string(),
+                reaction: 0,
+                becomes: 99,
+            },
+            MatterDefinitionError::InvalidLifetime {
+                index: 5,
+                name: "Lava".to_string(),
+                lifetime: MAX_LIFETIME + 1,
+            },
+            MatterDefinitionError::InvalidTransition {
+                index: 6,
+                name: "Ice".to_string(),
+                transition: "melts",
+                becomes: 42,
+            },
+        ]);
+    }
+
+    #[test]
+    fn validate_requires_an_empty_matter() {
+        let mut matter_definitions = default_matter_definitions();
+        matter_definitions.empty = MATTER_SAND;
+        assert_eq!(validate_matter_definitions(&matter_definitions), vec![
+            MatterDefinitionError::EmptyNotEmpty {
+                id: MATTER_SAND,
+                name: "Sand".to_string(),
+                state: MatterState::Powder,
+            }
+        ]);
+
+        matter_definitions.empty = 100;
+        assert_eq!(validate_matter_definitions(&matter_definitions), vec![
+            MatterDefinitionError::MissingEmpty(100)
+        ]);
+    }
+
+    #[test]
+    fn remove_moves_later_ids_down() {
+        let mut matter_definitions = default_matter_definitions();
+        let num_matters = matter_definitions.definitions.len() as u32;
+        let id_map = matter_definitions.remove(MATTER_WATER).unwrap();
+
+        let expected = (0..num_matters)
+            .map(|id| match id.cmp(&MATTER_WATER) {
+                Ordering::Less => Some(id),
+                Ordering::Equal => None,
+                Ordering::Greater => Some(id - 1),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(id_map, expected);
+        assert!(validate_matter_definitions(&matter_definitions).is_empty());
+        assert_eq!(matter_definitions.definitions.len() as u32, num_matters - 1);
+
+        let definition = |name: &str| {
+            matter_definitions
+                .definitions
+                .iter()
+                .find(|m| m.name == name)
+                .unwrap()
+        };
+        // Transitions to water are gone, others follow their matter
+        assert!(definition("Ice").melts.is_none());
+        assert!(definition("Steam").freezes.is_none());
+        assert_eq!(definition("Steam").id, MATTER_STEAM - 1);
+        assert_eq!(
+            definition("Sand").melts.map(|t| t.becomes),
+            Some(MATTER_GLASS - 1)
+        );
+        assert_eq!(definition("Sand").reactions[1].becomes, MATTER_LAVA - 1);
+    }
+
+    #[test]
+    fn remove_clears_reactions_into_the_removed_matter() {
+        let mut matter_definitions = default_matter_definitions();
+        matter_definitions.remove(MATTER_LAVA).unwrap();
+        let sand = &matter_definitions.definitions[MATTER_SAND as usize];
+        assert_eq!(sand.reactions[1].probability, 0.0);
+        assert_eq!(sand.reactions[1].becomes, 0);
+        // Untouched reactions keep their ids
+        assert_eq!(sand.reactions[0].becomes, matter_definitions.empty);
+        assert!(sand.reactions[0].probability > 0.0);
+    }
+
+    #[test]
+    fn remove_keeps_the_empty_matter() {
+        let mut matter_definitions = default_matter_definitions();
+        let num_matters = matter_definitions.definitions.len();
+        assert_eq!(matter_definitions.remove(matter_definitions.empty), None);
+        assert_eq!(matter_definitions.remove(num_matters as u32), None);
+        assert_eq!(matter_definitions.definitions.len(), num_matters);
+    }
+
+    const DEFINITION_TEXT: &str = r#"MatterFile(
+    version: 2,
+    empty: "Empty",
+    matters: [
+        (name: "Empty"),
+        (name: "Sand", becomes: "Sand"),
+        (name: "Sand"),
+    ],
+)"#;
+
+    #[test]
+    fn find_definition_by_name_field() {
+        let names = ["Empty", "Sand", "Sand"];
+        let duplicate = |index| MatterDefinitionError::DuplicateName {
+            index,
+            name: "Sand".to_string(),
+        };
+        assert_eq!(
+            find_definition(DEFINITION_TEXT, &names, &duplicate(1)),
+            Some((6, 16))
+        );
+        // A name used before is found after the earlier definitions
+        assert_eq!(
+            find_definition(DEFINITION_TEXT, &names, &duplicate(2)),
+            Some((7, 16))
+        );
+        assert_eq!(
+            find_definition(DEFINITION_TEXT, &names, &duplicate(3)),
+            None
+        );
+    }
+
+    #[test]
+    fn find_definition_of_whole_set_errors() {
+        assert_eq!(
+            find_definition(DEFINITION_TEXT, &[], &MatterDefinitionError::TooMany(300)),
+            Some((3, 5))
+        );
+        assert_eq!(
+            find_definition(
+                DEFINITION_TEXT,
+                &[],
+                &MatterDefinitionError::UnsupportedVersion(3)
+            ),
+            Some((2, 5))
+        );
+
+        // Only the field itself counts, `None` without it
+        let text = r#"// An "empty" matter, empty or not
+MatterFile(
+    matters: [(name: "empty")],
+    empty: "empty",
+)"#;
+        assert_eq!(
+            find_definition(text, &[], &MatterDefinitionError::TooMany(300)),
+            Some((4, 5))
+        );
+        assert_eq!(
+            find_definition(text, &[], &MatterDefinitionError::UnsupportedVersion(3)),
+            None
+        );
+    }
+
+    #[test]
+    fn parse_locates_errors() {
+        let text =
+            default_matter_definitions()
+                .serialize()
+                .replacen("name: \"Gas\"", "name: \"Sand\"", 1);
+        let diagnostics = parse_matter_definitions(&text).unwrap_err();
+        assert_eq!(diagnostics.len(), 1);
+        assert!(matches!(
+            diagnostics[0].error,
+            MatterDefinitionError::DuplicateName {
+                index: 3,
+                ..
+            }
+        ));
+
+        let (line, col) = diagnostics[0].position.unwrap();
+        let line_text = text.lines().nth(line - 1).unwrap();
+        assert!(
+            line_text[col - 1..].starts_with("\"Sand\""),
+            "{}",
+            line_text
+        );
+    }
+}
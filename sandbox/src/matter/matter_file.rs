@@ -0,0 +1,387 @@
+use bevy::utils::HashMap;
+use serde::{Deserialize, Serialize};
+use strum::IntoEnumIterator;
+use strum_macros::EnumIter;
+
+use super::{
+    direction::Direction,
+    matter_definition::{
+        default_heat_capacity, default_temperature, MatterDefinition, MatterDefinitionError,
+        MatterDefinitions, MAX_TRANSITIONS,
+    },
+    matter_reaction::{MatterReaction, TemperatureTransition},
+    matter_state::{MatterCharacteristic, MatterState},
+};
+
+/// Version of the matter file schema below. Version 1 is `MatterDefinitions` as is, with ids equal
+/// to indices, `becomes` as ids and flags as bits. Bump when the schema changes & read the older
+/// version in `read_matter_file`.
+pub const MATTER_FILE_VERSION: u32 = 2;
+
+/// Matter definitions as authored. Matter is referenced by name, flags are lists of names and ids
+/// are assigned on load in the order of `matters`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MatterFile {
+    pub version: u32,
+    /// Name of the matter of empty cells
+    pub empty: String,
+    pub matters: Vec<MatterEntry>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MatterEntry {
+    pub name: String,
+    /// 0xRRGGBBAA
+    pub color: u32,
+    pub weight: f32,
+    #[serde(default)]
+    pub dispersion: u32,
+    pub state: MatterState,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub characteristics: Vec<CharacteristicFlag>,
+    /// At most `MAX_TRANSITIONS`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub reactions: Vec<ReactionEntry>,
+    #[serde(default = "default_temperature")]
+    pub temperature: f32,
+    #[serde(default)]
+    pub conductivity: f32,
+    #[serde(default = "default_heat_capacity")]
+    pub heat_capacity: f32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub melts: Option<TransitionEntry>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub boils: Option<TransitionEntry>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub freezes: Option<TransitionEntry>,
+    #[serde(default)]
+    pub lifetime: u32,
+}
+
+/// See `MatterReaction`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ReactionEntry {
+    pub becomes: String,
+    pub probability: f32,
+    pub direction: Vec<DirectionFlag>,
+    #[serde(default)]
+    pub reacts: Vec<CharacteristicFlag>,
+}
+
+/// See `TemperatureTransition`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TransitionEntry {
+    pub temperature: f32,
+    pub becomes: String,
+}
+
+/// A `MatterCharacteristic` by name
+#[derive(EnumIter, Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CharacteristicFlag {
+    Corrosive,
+    Corrodes,
+    Melting,
+    Melts,
+}
+
+impl CharacteristicFlag {
+    pub fn characteristic(self) -> MatterCharacteristic {
+        match self {
+            CharacteristicFlag::Corrosive => MatterCharacteristic::CORROSIVE,
+            CharacteristicFlag::Corrodes => MatterCharacteristic::CORRODES,
+            CharacteristicFlag::Melting => MatterCharacteristic::MELTING,
+            CharacteristicFlag::Melts => MatterCharacteristic::MELTS,
+        }
+    }
+
+    fn flags(characteristics: MatterCharacteristic) -> Vec<CharacteristicFlag> {
+        CharacteristicFlag::iter()
+            .filter(|flag| characteristics.contains(flag.characteristic()))
+            .collect()
+    }
+
+    fn combined(flags: &[CharacteristicFlag]) -> MatterCharacteristic {
+        flags
+            .iter()
+            .fold(MatterCharacteristic::empty(), |characteristics, flag| {
+                characteristics | flag.characteristic()
+            })
+    }
+}
+
+/// A `Direction` by name
+#[derive(EnumIter, Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DirectionFlag {
+    UpLeft,
+    Up,
+    UpRight,
+    Right,
+    DownRight,
+    Down,
+    DownLeft,
+    Left,
+}
+
+impl DirectionFlag {
+    pub fn direction(self) -> Direction {
+        match self {
+            DirectionFlag::UpLeft => Direction::UP_LEFT,
+            DirectionFlag::Up => Direction::UP,
+            DirectionFlag::UpRight => Direction::UP_RIGHT,
+            DirectionFlag::Right => Direction::RIGHT,
+            DirectionFlag::DownRight => Direction::DOWN_RIGHT,
+            DirectionFlag::Down => Direction::DOWN,
+            DirectionFlag::DownLeft => Direction::DOWN_LEFT,
+            DirectionFlag::Left => Direction::LEFT,
+        }
+    }
+
+    fn flags(direction: Direction) -> Vec<DirectionFlag> {
+        DirectionFlag::iter()
+            .filter(|flag| direction.contains(flag.direction()))
+            .collect()
+    }
+
+    fn combined(flags: &[DirectionFlag]) -> Direction {
+        flags.iter().fold(Direction::NONE, |direction, flag| {
+            direction | flag.direction()
+        })
+    }
+}
+
+impl MatterFile {
+    /// The definitions in the current schema version
+    pub fn new(matter_definitions: &MatterDefinitions) -> MatterFile {
+        let definitions = &matter_definitions.definitions;
+        let name_of = |id: u32| {
+            definitions
+                .get(id as usize)
+                .map_or_else(|| id.to_string(), |m| m.name.clone())
+        };
+        let transition = |transition: Option<TemperatureTransition>| {
+            transition.map(|t| TransitionEntry {
+                temperature: t.temperature,
+                becomes: name_of(t.becomes),
+            })
+        };
+
+        MatterFile {
+            version: MATTER_FILE_VERSION,
+            empty: name_of(matter_definitions.empty),
+            matters: definitions
+                .iter()
+                .map(|m| MatterEntry {
+                    name: m.name.clone(),
+                    color: m.color,
+                    weight: m.weight,
+                    dispersion: m.dispersion,
+                    state: m.state,
+                    characteristics: CharacteristicFlag::flags(m.characteristics),
+                    // Reactions that can't happen are left out
+                    reactions: m
+                        .reactions
+                        .iter()
+                        .filter(|r| r.probability > 0.0 && !r.direction.is_empty())
+                        .map(|r| ReactionEntry {
+                            becomes: name_of(r.becomes),
+                            probability: r.probability,
+                            direction: DirectionFlag::flags(r.direction),
+                            reacts: CharacteristicFlag::flags(r.reacts),
+                        })
+                        .collect(),
+                    temperature: m.temperature,
+                    conductivity: m.conductivity,
+                    heat_capacity: m.heat_capacity,
+                    melts: transition(m.melts),
+                    boils: transition(m.boils),
+                    freezes: transition(m.freezes),
+                    lifetime: m.lifetime,
+                })
+                .collect(),
+        }
+    }
+
+    /// Assign ids in order & resolve names to them. Errors hold every name that isn't a matter
+    /// and every matter with too many reactions. Duplicate names resolve to the first matter, they
+    /// are reported by `validate_matter_definitions`.
+    pub fn into_definitions(self) -> Result<MatterDefinitions, Vec<MatterDefinitionError>> {
+        let mut ids = HashMap::default();
+        for (id, m) in self.matters.iter().enumerate() {
+            ids.entry(m.name.clone()).or_insert(id as u32);
+        }
+        let mut errors = vec![];
+
+        let empty = ids.get(&self.empty).copied().unwrap_or_else(|| {
+            errors.push(MatterDefinitionError::UnknownEmpty(self.empty.clone()));
+            0
+        });
+
+        let mut definitions = vec![];
+        for (index, m) in self.matters.into_iter().enumerate() {
+            let mut id_of = |reference: &str| {
+                ids.get(reference).copied().unwrap_or_else(|| {
+                    errors.push(MatterDefinitionError::UnknownMatter {
+                        index,
+                        name: m.name.clone(),
+                        reference: reference.to_string(),
+                    });
+                    empty
+                })
+            };
+
+            let mut reactions = MatterReaction::all_zero();
+            for (reaction, entry) in reactions.iter_mut().zip(m.reactions.iter()) {
+                *reaction = MatterReaction {
+                    becomes: id_of(&entry.becomes),
+                    probability: entry.probability,
+                    direction: DirectionFlag::combined(&entry.direction),
+                    reacts: CharacteristicFlag::combined(&entry.reacts),
+                };
+            }
+            let mut transition = |entry: &Option<TransitionEntry>| {
+                entry
+                    .as_ref()
+                    .map(|t| TemperatureTransition::new(t.temperature, id_of(&t.becomes)))
+            };
+            let (melts, boils, freezes) = (
+                transition(&m.melts),
+                transition(&m.boils),
+                transition(&m.freezes),
+            );
+
+            if m.reactions.len() > MAX_TRANSITIONS as usize {
+                errors.push(MatterDefinitionError::TooManyReactions {
+                    index,
+                    name: m.name.clone(),
+                    count: m.reactions.len(),
+                });
+            }
+
+            definitions.push(MatterDefinition {
+                id: index as u32,
+                color: m.color,
+                weight: m.weight,
+                name: m.name,
+                dispersion: m.dispersion,
+                state: m.state,
+                characteristics: CharacteristicFlag::combined(&m.characteristics),
+                reactions,
+                temperature: m.temperature,
+                conductivity: m.conductivity,
+                heat_capacity: m.heat_capacity,
+                melts,
+                boils,
+                freezes,
+                lifetime: m.lifetime,
+            });
+        }
+
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+        Ok(MatterDefinitions {
+            empty,
+            definitions,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matter::{
+        default_matter_definitions, matter_definition::parse_matter_definitions, MATTER_SAND,
+        MATTER_WATER,
+    };
+
+    #[test]
+    fn definitions_survive_the_matter_file() {
+        let matter_definitions = default_matter_definitions();
+        let file = MatterFile::new(&matter_definitions);
+        assert_eq!(file.version, MATTER_FILE_VERSION);
+        assert_eq!(
+            file.matters[MATTER_SAND as usize]
+                .melts
+                .as_ref()
+                .unwrap()
+                .becomes,
+            "Glass"
+        );
+
+        let definitions = file.into_definitions().unwrap();
+        assert_eq!(definitions.serialize(), matter_definitions.serialize());
+    }
+
+    #[test]
+    fn into_definitions_resolves_names() {
+        let mut file = MatterFile::new(&default_matter_definitions());
+        file.matters[MATTER_WATER as usize].reactions = vec![ReactionEntry {
+            becomes: "Steam".to_string(),
+            probability: 0.5,
+            direction: vec![DirectionFlag::Up, DirectionFlag::Down],
+            reacts: vec![CharacteristicFlag::Melting],
+        }];
+
+        let definitions = file.into_definitions().unwrap();
+        let water = &definitions.definitions[MATTER_WATER as usize];
+        let steam = definitions
+            .definitions
+            .iter()
+            .position(|m| m.name == "Steam");
+        assert_eq!(Some(water.reactions[0].becomes as usize), steam);
+        assert_eq!(
+            water.reactions[0].direction.bits(),
+            (Direction::UP | Direction::DOWN).bits()
+        );
+        assert_eq!(
+            water.reactions[0].reacts.bits(),
+            MatterCharacteristic::MELTING.bits()
+        );
+        assert_eq!(water.reactions[1].probability, 0.0);
+    }
+
+    #[test]
+    fn into_definitions_reports_unknown_names() {
+        let mut file = MatterFile::new(&default_matter_definitions());
+        file.empty = "Air".to_string();
+        file.matters[MATTER_SAND as usize].melts = Some(TransitionEntry {
+            temperature: 1000.0,
+            becomes: "Glas".to_string(),
+        });
+        file.matters[MATTER_WATER as usize].reactions = (0..=MAX_TRANSITIONS)
+            .map(|_| ReactionEntry {
+                becomes: "Empty".to_string(),
+                probability: 0.1,
+                direction: vec![DirectionFlag::Up],
+                reacts: vec![],
+            })
+            .collect();
+
+        assert_eq!(file.into_definitions().unwrap_err(), vec![
+            MatterDefinitionError::UnknownEmpty("Air".to_string()),
+            MatterDefinitionError::UnknownMatter {
+                index: MATTER_SAND as usize,
+                name: "Sand".to_string(),
+                reference: "Glas".to_string(),
+            },
+            MatterDefinitionError::TooManyReactions {
+                index: MATTER_WATER as usize,
+                name: "Water".to_string(),
+                count: MAX_TRANSITIONS as usize + 1,
+            },
+        ]);
+    }
+
+    #[test]
+    fn version_1_files_are_migrated() {
+        let matter_definitions = default_matter_definitions();
+        // Version 1 is the definitions as they are, without a version field
+        let version_1 = ron::to_string(&matter_definitions).unwrap();
+
+        let migrated = parse_matter_definitions(&version_1).unwrap();
+        assert_eq!(migrated.serialize(), matter_definitions.serialize());
+        let resaved = migrated.serialize();
+        assert!(resaved.contains(&format!("version: {}", MATTER_FILE_VERSION)));
+        assert!(resaved.contains("becomes: \"Glass\""));
+    }
+}
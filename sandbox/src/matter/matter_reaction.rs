@@ -2,6 +2,22 @@ use serde::{Deserialize, Serialize};
 
 use super::{direction::Direction, matter_state::MatterCharacteristic};
 
+/// Matter becomes `becomes` once its own temperature crosses `temperature`
+#[derive(Serialize, Deserialize, Debug, Copy, Clone)]
+pub struct TemperatureTransition {
+    pub temperature: f32,
+    pub becomes: u32,
+}
+
+impl TemperatureTransition {
+    pub fn new(temperature: f32, becomes: u32) -> Self {
+        TemperatureTransition {
+            temperature,
+            becomes,
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Copy, Clone)]
 pub struct MatterReaction {
     pub becomes: u32,
@@ -1,7 +1,7 @@
 use core::fmt;
 
 use bitflags::bitflags;
-use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
 use strum_macros::EnumIter;
 
 use crate::utils::U32Visitor;
@@ -57,7 +57,8 @@ impl<'de> Deserialize<'de> for MatterCharacteristic {
         D: Deserializer<'de>,
     {
         let res = deserializer.deserialize_u32(U32Visitor)?;
-        Ok(MatterCharacteristic::from_bits(res).unwrap())
+        MatterCharacteristic::from_bits(res)
+            .ok_or_else(|| D::Error::custom(format!("invalid characteristic bits {:#b}", res)))
     }
 }
 
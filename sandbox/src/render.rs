@@ -1,4 +1,5 @@
 pub mod camera;
+pub mod canvas_upload;
 pub mod fill_render_pass;
 pub mod quad_pipeline;
 pub mod utils;
@@ -6,8 +7,11 @@ pub mod utils;
 use bevy::{prelude::*, window::PrimaryWindow};
 use bevy_fn_plugin::bevy_plugin;
 use bevy_vulkano::{BevyVulkanoContext, BevyVulkanoWindows};
+use vulkano::sync::GpuFuture;
 
-use self::{camera::OrthographicCamera, fill_render_pass::FillScreenRenderPass};
+use self::{
+    camera::OrthographicCamera, canvas_upload::CanvasUpload, fill_render_pass::FillScreenRenderPass,
+};
 use crate::{simulator::simulation::Simulation, time::RenderTimer, GameState, CLEAR_COLOR};
 
 #[bevy_plugin]
@@ -37,6 +41,10 @@ fn create_render_pass(
     );
 
     commands.insert_resource(fill_screen);
+    commands.insert_resource(CanvasUpload::new(
+        context.context.memory_allocator().clone(),
+        primary_window.renderer.graphics_queue(),
+    ));
 }
 
 fn render_pass(
@@ -44,11 +52,28 @@ fn render_pass(
     camera: Res<OrthographicCamera>,
     mut render_timer: ResMut<RenderTimer>,
     mut fill_screen: ResMut<FillScreenRenderPass>,
+    mut canvas_upload: ResMut<CanvasUpload>,
     window_query: Query<Entity, With<PrimaryWindow>>,
     mut vulkano_windows: NonSendMut<BevyVulkanoWindows>,
 ) {
     render_timer.0.start();
     let Some(primary_window) = crate::utils::get_primary_window_mut(&window_query, &mut vulkano_windows) else{return};
+    // Backends without a device image of their own are copied to one, once per frame
+    let (canvas_image, upload) = match simulator.canvas_image() {
+        Some(canvas_image) => (canvas_image, None),
+        None => {
+            let uploaded = simulator
+                .read_color_image()
+                .and_then(|rgba| canvas_upload.upload(&rgba, simulator.canvas_size().to_array()));
+            match uploaded {
+                Ok((canvas_image, upload)) => (canvas_image, Some(upload)),
+                Err(e) => {
+                    bevy::log::error!("Failed to upload canvas: {}", e);
+                    return;
+                }
+            }
+        }
+    };
 
     // Start frame
     let before = match primary_window.renderer.acquire() {
@@ -58,14 +83,24 @@ fn render_pass(
         }
         Ok(f) => f,
     };
+    let before = match upload {
+        Some(upload) => match before.then_execute(canvas_upload.queue(), upload) {
+            Err(e) => {
+                bevy::log::error!("Failed to upload canvas: {}", e);
+                return;
+            }
+            Ok(f) => f.boxed(),
+        },
+        None => before,
+    };
 
-    let canvas_image = simulator.canvas_image();
-
-    // Render
+    // Render, the canvas quad is centered at the origin so move it to where the canvas is
+    let mut canvas_camera = *camera;
+    canvas_camera.translate(simulator.canvas_center());
     let final_image = primary_window.renderer.swapchain_image_view();
     let after_images = fill_screen.draw(
         before,
-        *camera,
+        canvas_camera,
         canvas_image,
         final_image.clone(),
         CLEAR_COLOR,
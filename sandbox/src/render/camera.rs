@@ -5,12 +5,13 @@ pub use ortho_camera::OrthographicCamera;
 use bevy::{input::mouse::MouseWheel, prelude::*, window::WindowResized};
 use bevy_fn_plugin::bevy_plugin;
 
-use crate::{bevy_config::HEIGHT, GameState, CAMERA_MOVE_SPEED, SIM_CANVAS_SIZE};
+use crate::{bevy_config::HEIGHT, simulator::simulation::Simulation, GameState, CAMERA_MOVE_SPEED};
 
 #[bevy_plugin]
 pub fn CameraPlugin(app: &mut App) {
     app.add_startup_system(setup_camera).add_systems(
-        (update_camera, camera_controls).distributive_run_if(in_state(GameState::Simulating)),
+        (update_camera, fit_camera_to_canvas, camera_controls)
+            .distributive_run_if(in_state(GameState::Simulating)),
     );
 }
 
@@ -24,9 +25,6 @@ fn setup_camera(window_query: Query<&Window>, mut commands: Commands) {
     // Update camera to window size
     camera.update(window.width(), window.height());
 
-    // Zoom camera to fit vertical pixels
-    camera.zoom_to_fit_pixels(SIM_CANVAS_SIZE, HEIGHT as u32);
-
     // Insert resources
     commands.insert_resource(camera);
 }
@@ -41,6 +39,18 @@ fn update_camera(
     }
 }
 
+/// Zoom camera to fit vertical canvas pixels, on start & whenever the canvas is resized
+fn fit_camera_to_canvas(
+    simulation: Res<Simulation>,
+    mut camera: ResMut<OrthographicCamera>,
+    mut canvas_size: Local<UVec2>,
+) {
+    if *canvas_size != simulation.canvas_size() {
+        *canvas_size = simulation.canvas_size();
+        camera.zoom_to_fit_pixels(canvas_size.y, HEIGHT as u32);
+    }
+}
+
 /// Input actions for camera movement, zoom and pausing
 fn camera_controls(
     time: Res<Time>,
@@ -63,6 +63,13 @@ impl OrthographicCamera {
         updated_pos * self.scale() - self.pos
     }
 
+    /// Inverse of `screen_to_world_pos`, but in gui coordinates (from the top left of the window)
+    pub fn world_to_gui_pos(&self, window: &Window, world_pos: Vec2) -> Vec2 {
+        let screen_pos = (world_pos + self.pos) / self.scale() * Vec2::new(1.0, -1.0)
+            + Vec2::new(window.width() / 2.0, window.height() / 2.0);
+        Vec2::new(screen_pos.x, window.height() - screen_pos.y)
+    }
+
     pub fn reset_zoom(&mut self) {
         self.zoom(1.0 / self.ortho.scale);
     }
@@ -0,0 +1,92 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use bevy::prelude::*;
+use vulkano::{
+    buffer::{
+        allocator::{SubbufferAllocator, SubbufferAllocatorCreateInfo},
+        BufferUsage,
+    },
+    command_buffer::{
+        allocator::StandardCommandBufferAllocator, AutoCommandBufferBuilder, CommandBufferUsage,
+        CopyBufferToImageInfo, PrimaryAutoCommandBuffer,
+    },
+    device::{DeviceOwned, Queue},
+    format::Format,
+    image::{ImageUsage, StorageImage},
+    memory::allocator::StandardMemoryAllocator,
+};
+use vulkano_util::renderer::DeviceImageView;
+
+/// Canvas image filled from host memory once per frame, for simulation backends that only produce
+/// colors on the host. Frames don't wait for the copy, it's submitted ahead of the canvas draw.
+#[derive(Resource)]
+pub struct CanvasUpload {
+    image: Option<DeviceImageView>,
+    dimensions: [u32; 2],
+    /// Staging memory is handed out per upload, frames in flight keep theirs
+    staging: SubbufferAllocator,
+    queue: Arc<Queue>,
+    allocator: Arc<StandardMemoryAllocator>,
+    command_buffer_allocator: StandardCommandBufferAllocator,
+}
+
+impl CanvasUpload {
+    pub fn new(allocator: Arc<StandardMemoryAllocator>, queue: Arc<Queue>) -> CanvasUpload {
+        CanvasUpload {
+            image: None,
+            dimensions: [0, 0],
+            staging: SubbufferAllocator::new(allocator.clone(), SubbufferAllocatorCreateInfo {
+                buffer_usage: BufferUsage::TRANSFER_SRC,
+                ..Default::default()
+            }),
+            command_buffer_allocator: StandardCommandBufferAllocator::new(
+                queue.device().clone(),
+                Default::default(),
+            ),
+            queue,
+            allocator,
+        }
+    }
+
+    /// Record a copy of `R8G8B8A8_UNORM` bytes to the image, recreated if the canvas size changed.
+    /// Returns the image & the copy to execute before drawing it.
+    pub fn upload(
+        &mut self,
+        rgba: &[u8],
+        dimensions: [u32; 2],
+    ) -> Result<(DeviceImageView, PrimaryAutoCommandBuffer)> {
+        let image = match &self.image {
+            Some(image) if self.dimensions == dimensions => image.clone(),
+            _ => {
+                let image = StorageImage::general_purpose_image_view(
+                    &self.allocator,
+                    self.queue.clone(),
+                    dimensions,
+                    Format::R8G8B8A8_UNORM,
+                    ImageUsage::SAMPLED | ImageUsage::STORAGE | ImageUsage::TRANSFER_DST,
+                )?;
+                self.dimensions = dimensions;
+                self.image.insert(image).clone()
+            }
+        };
+
+        let staging = self.staging.allocate_slice::<u8>(rgba.len() as u64)?;
+        staging.write()?.copy_from_slice(rgba);
+        let mut builder = AutoCommandBufferBuilder::primary(
+            &self.command_buffer_allocator,
+            self.queue.queue_family_index(),
+            CommandBufferUsage::OneTimeSubmit,
+        )?;
+        builder.copy_buffer_to_image(CopyBufferToImageInfo::buffer_image(
+            staging,
+            image.image().clone(),
+        ))?;
+
+        Ok((image, builder.build()?))
+    }
+
+    pub fn queue(&self) -> Arc<Queue> {
+        self.queue.clone()
+    }
+}
@@ -1,9 +1,23 @@
+use std::path::Path;
+
 use bevy::prelude::*;
 use bevy_fn_plugin::bevy_plugin;
 use bevy_vulkano::BevyVulkanoContext;
+use serde::{Deserialize, Serialize};
+use strum::IntoEnumIterator;
+use strum_macros::EnumIter;
 use vulkano::device::physical::PhysicalDeviceType;
 
-use crate::{utils::AppExt, GameState};
+use crate::{
+    fs_interaction::{
+        config::GameConfig,
+        file_utils::{FileResult, FileUtils},
+    },
+    matter::matter_definition::MatterDefinitions,
+    simulator::boundary::Boundaries,
+    utils::AppExt,
+    GameState, SIM_CANVAS_SIZE,
+};
 
 #[bevy_plugin]
 //noinspection RsFunctionNaming
@@ -14,6 +28,8 @@ pub fn SandSettingsPlugin(app: &mut App) {
 
 pub const INIT_MOVEMENT_STEPS: u32 = 3;
 pub const INIT_DISPERSION_STEPS: u32 = 10;
+/// Settings saved from the settings window, they override the defaults picked for the device
+pub const USER_SETTINGS_FILE: &str = "user_settings.toml";
 
 #[derive(Debug, Clone, Copy, Resource)]
 pub struct AppSettings {
@@ -21,6 +37,16 @@ pub struct AppSettings {
     pub movement_steps: u32,
     pub dispersion_steps: u32,
     pub print_performance: bool,
+    /// Seed the per step simulation seeds are derived from
+    pub seed: u32,
+    /// Canvas width & height, the simulation is resized when this changes
+    pub canvas_size: UVec2,
+    /// Debug overlay, tint the tiles the movement kernels run on
+    pub show_awake_tiles: bool,
+    /// What lies beyond each canvas edge
+    pub boundaries: Boundaries,
+    /// Simulation steps per `TIME_STEP` of real time, between `MIN_SIM_SPEED` and `MAX_SIM_SPEED`
+    pub sim_speed: f32,
 }
 
 impl FromWorld for AppSettings {
@@ -28,6 +54,20 @@ impl FromWorld for AppSettings {
         let mut settings = Self::new();
         let properties = world.get_resource::<DeviceProperties>().unwrap();
         settings.update_based_on_device_info_and_env(properties);
+        if let Some(user_settings) = UserSettings::load(USER_SETTINGS_FILE) {
+            log::info!("Using user settings from {}", USER_SETTINGS_FILE);
+            user_settings.apply(&mut settings);
+        }
+        if let Some(config) = world.get_resource::<GameConfig>() {
+            if let Some(seed) = config.seed {
+                settings.seed = seed;
+            }
+            settings.canvas_size = config.canvas_size();
+            if let Some(matter_definitions) = world.get_resource::<MatterDefinitions>() {
+                settings.boundaries = config.boundaries.validated(matter_definitions);
+            }
+        }
+        log::info!("Simulation seed: {}", settings.seed);
         settings
     }
 }
@@ -41,9 +81,23 @@ impl AppSettings {
             is_paused: false,
             dispersion_steps,
             print_performance: false,
+            seed: rand::random(),
+            canvas_size: UVec2::splat(SIM_CANVAS_SIZE),
+            show_awake_tiles: false,
+            boundaries: Boundaries::default(),
+            sim_speed: 1.0,
         }
     }
 
+    /// Back to the settings picked for the device, as if there were no user settings
+    pub fn use_device_defaults(&mut self, properties: &DeviceProperties) {
+        let defaults = AppSettings::new();
+        self.movement_steps = defaults.movement_steps;
+        self.dispersion_steps = defaults.dispersion_steps;
+        self.print_performance = defaults.print_performance;
+        self.update_based_on_device_info_and_env(properties);
+    }
+
     pub fn update_based_on_device_info_and_env(&mut self, properties: &DeviceProperties) {
         let max_mem_gb = properties.max_mem_gb();
         let device_type = properties.device_type();
@@ -63,15 +117,102 @@ impl AppSettings {
     }
 }
 
+/// Movement & dispersion steps per simulation step, more looks smoother but costs more
+#[derive(Debug, Copy, Clone, PartialEq, Eq, EnumIter)]
+pub enum QualityPreset {
+    Low,
+    Medium,
+    High,
+}
+
+impl QualityPreset {
+    /// (movement steps, dispersion steps)
+    pub fn steps(self) -> (u32, u32) {
+        match self {
+            QualityPreset::Low => (1, 4),
+            QualityPreset::Medium => (2, 4),
+            QualityPreset::High => (INIT_MOVEMENT_STEPS, INIT_DISPERSION_STEPS),
+        }
+    }
+
+    pub fn apply(self, settings: &mut AppSettings) {
+        (settings.movement_steps, settings.dispersion_steps) = self.steps();
+    }
+
+    /// The preset the settings are at, `None` if they were tuned by hand
+    pub fn of(settings: &AppSettings) -> Option<QualityPreset> {
+        QualityPreset::iter()
+            .find(|preset| preset.steps() == (settings.movement_steps, settings.dispersion_steps))
+    }
+}
+
+impl std::fmt::Display for QualityPreset {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+/// The part of `AppSettings` users persist from the settings window
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+pub struct UserSettings {
+    pub movement_steps: u32,
+    pub dispersion_steps: u32,
+    #[serde(default)]
+    pub print_performance: bool,
+}
+
+impl UserSettings {
+    pub fn new(settings: &AppSettings) -> UserSettings {
+        UserSettings {
+            movement_steps: settings.movement_steps,
+            dispersion_steps: settings.dispersion_steps,
+            print_performance: settings.print_performance,
+        }
+    }
+
+    /// `None` if there is no user settings file, or it can't be read
+    pub fn load(path: impl AsRef<Path>) -> Option<UserSettings> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return None;
+        }
+        FileUtils::read_toml::<UserSettings>(path)
+            .map_err(|e| log::warn!("Ignoring user settings {:?}: {}", path, e))
+            .ok()
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> FileResult<()> {
+        FileUtils::write_toml(path, self)
+    }
+
+    /// Forget the saved settings, the next launch uses the device defaults again
+    pub fn remove(path: impl AsRef<Path>) -> FileResult<()> {
+        let path = path.as_ref();
+        if path.exists() {
+            std::fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+
+    pub fn apply(&self, settings: &mut AppSettings) {
+        settings.movement_steps = self.movement_steps;
+        settings.dispersion_steps = self.dispersion_steps;
+        settings.print_performance = self.print_performance;
+    }
+}
+
 #[derive(Debug, Resource)]
 pub struct DeviceProperties {
     max_mem_gb: f32,
-    #[allow(unused)]
     device_name: String,
     device_type: PhysicalDeviceType,
 }
 
 impl DeviceProperties {
+    pub fn device_name(&self) -> &str {
+        &self.device_name
+    }
+
     pub fn device_type(&self) -> PhysicalDeviceType {
         self.device_type
     }
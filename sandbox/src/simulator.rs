@@ -1,56 +1,240 @@
+pub mod backend;
+pub mod boundary;
 pub mod ca_simulator;
+pub mod census;
+pub mod chunks;
+pub mod cpu_simulator;
+pub mod emitters;
 pub mod gpu_utils;
+pub mod grid_readback;
+pub mod push_constants;
 pub mod simulation;
 
-use std::time::Duration;
-
-use bevy::{prelude::*, time::common_conditions::on_timer, window::PrimaryWindow};
+use anyhow::Result;
+use bevy::prelude::*;
 use bevy_fn_plugin::bevy_plugin;
-use bevy_vulkano::{BevyVulkanoContext, BevyVulkanoWindows};
+use bevy_mod_sysfail::macros::*;
+use bevy_vulkano::BevyVulkanoContext;
 
-use self::simulation::Simulation;
+use self::{
+    census::{take_census, MatterCensus},
+    chunks::CHUNK_DIR,
+    grid_readback::{read_back_matter_grid, MatterGridReadback},
+    simulation::Simulation,
+};
 use crate::{
-    matter::matter_definition::MatterDefinitions, settings::AppSettings, time::SimulationTimer,
-    GameState,
+    fs_interaction::{asset_loading::MatterDefinitionsChanged, config::GameConfig},
+    matter::matter_definition::MatterDefinitions,
+    render::camera::OrthographicCamera,
+    settings::AppSettings,
+    time::SimulationTimer,
+    GameState, SIM_FPS,
 };
 
-pub const TIME_STEP: f32 = 1.0 / 60.0;
+pub const TIME_STEP: f32 = 1.0 / SIM_FPS;
+/// Most steps run in one frame to catch up after slow frames, lag beyond it is dropped
+pub const MAX_CATCH_UP_STEPS: u32 = 16;
+pub const MIN_SIM_SPEED: f32 = 0.25;
+pub const MAX_SIM_SPEED: f32 = 8.0;
 
 #[bevy_plugin]
 pub fn SimulatorPlugin(app: &mut App) {
-    app.add_system(setup_simulation.in_schedule(OnEnter(GameState::Simulating)))
+    app.init_resource::<SimulationClock>()
+        .init_resource::<MatterGridReadback>()
+        .init_resource::<MatterCensus>()
+        .add_system(setup_simulation.in_schedule(OnEnter(GameState::Simulating)))
+        .add_system(run_simulation.run_if(in_state(GameState::Simulating)))
+        .add_system(
+            read_back_matter_grid
+                .after(run_simulation)
+                .run_if(in_state(GameState::Simulating)),
+        )
+        .add_system(
+            take_census
+                .after(run_simulation)
+                .run_if(in_state(GameState::Simulating)),
+        )
+        .add_system(
+            resize_simulation
+                .before(run_simulation)
+                .run_if(in_state(GameState::Simulating)),
+        )
         .add_system(
-            run_simulation
-                .run_if(in_state(GameState::Simulating))
-                .run_if(on_timer(Duration::from_secs_f32(TIME_STEP))),
+            stream_simulation
+                .after(resize_simulation)
+                .before(run_simulation)
+                .run_if(in_state(GameState::Simulating)),
+        )
+        .add_system(
+            update_matter_definitions
+                .before(resize_simulation)
+                .run_if(in_state(GameState::Simulating)),
         );
 }
 
 fn setup_simulation(
     mut commands: Commands,
+    config: Res<GameConfig>,
     context: Res<BevyVulkanoContext>,
-    windows: NonSend<BevyVulkanoWindows>,
     matter_definitions: Res<MatterDefinitions>,
-    window_query: Query<Entity, With<PrimaryWindow>>,
 ) {
-    let Some(primary_window) = crate::utils::get_primary_window(&window_query, &windows) else{return};
-
-    let sim = Simulation::new(
+    // The canvas is drawn on the graphics queue, the gpu backend simulates on it too
+    let mut sim = Simulation::new(
+        config.simulation_backend,
         context.context.memory_allocator(),
-        primary_window.renderer.graphics_queue(),
+        context.context.graphics_queue().clone(),
         &matter_definitions,
+        config.canvas_size(),
     )
     .unwrap();
+    if config.streaming {
+        sim.enable_streaming(CHUNK_DIR, &matter_definitions)
+            .unwrap();
+    }
 
     commands.insert_resource(sim);
 }
 
+/// Fixed timestep accumulator, the simulation runs a step per `TIME_STEP` of frame time scaled by
+/// `AppSettings::sim_speed`
+#[derive(Resource, Default)]
+pub struct SimulationClock {
+    accumulator: f32,
+    step_once: bool,
+}
+
+impl SimulationClock {
+    /// Advance exactly one step on the next frame, while paused
+    pub fn request_step(&mut self) {
+        self.step_once = true;
+    }
+
+    /// Number of steps due after a frame of `delta` seconds
+    fn advance(&mut self, delta: f32, speed: f32) -> u32 {
+        self.accumulator += delta * speed.clamp(MIN_SIM_SPEED, MAX_SIM_SPEED);
+        let steps = (self.accumulator / TIME_STEP) as u32;
+        if steps > MAX_CATCH_UP_STEPS {
+            // Don't spiral into ever slower frames, simulation time is lost instead
+            self.accumulator = 0.0;
+            return MAX_CATCH_UP_STEPS;
+        }
+        self.accumulator -= steps as f32 * TIME_STEP;
+        steps
+    }
+}
+
+#[sysfail(log(level = "error"))]
 fn run_simulation(
+    time: Res<Time>,
     settings: Res<AppSettings>,
+    mut clock: ResMut<SimulationClock>,
     mut simulation: ResMut<Simulation>,
     mut sim_timer: ResMut<SimulationTimer>,
-) {
-    sim_timer.0.start();
-    simulation.step(&settings);
-    sim_timer.0.time_it();
+) -> Result<()> {
+    let mut steps = clock.advance(time.delta_seconds(), settings.sim_speed);
+    let mut step_settings = *settings;
+    if settings.is_paused {
+        // Paused steps only color the canvas, once per frame is enough
+        steps = steps.min(1);
+        if std::mem::take(&mut clock.step_once) {
+            step_settings.is_paused = false;
+            steps = 1;
+        }
+    } else {
+        clock.step_once = false;
+    }
+
+    for _ in 0..steps {
+        sim_timer.0.start();
+        simulation.step(&step_settings)?;
+        sim_timer.0.time_it();
+    }
+    Ok(())
+}
+
+/// Hand changed matter definitions to the simulation, the world's matter follows changed ids
+#[sysfail(log(level = "error"))]
+fn update_matter_definitions(
+    mut settings: ResMut<AppSettings>,
+    mut simulation: ResMut<Simulation>,
+    mut census: ResMut<MatterCensus>,
+    matter_definitions: Res<MatterDefinitions>,
+    mut changed_events: EventReader<MatterDefinitionsChanged>,
+) -> Result<()> {
+    for event in changed_events.iter() {
+        match &event.id_map {
+            Some(id_map) => {
+                simulation.remap_matters(id_map, &matter_definitions)?;
+                settings.boundaries = settings.boundaries.remapped(id_map);
+                // Earlier samples count matter under its old id
+                census.clear();
+            }
+            None => simulation.update_matter_data(&matter_definitions)?,
+        }
+    }
+    Ok(())
+}
+
+/// Recreate the simulation canvas when the canvas size setting changes
+#[sysfail(log(level = "error"))]
+fn resize_simulation(
+    mut settings: ResMut<AppSettings>,
+    mut simulation: ResMut<Simulation>,
+    matter_definitions: Res<MatterDefinitions>,
+) -> Result<()> {
+    if settings.canvas_size != simulation.canvas_size() {
+        let result = simulation.resize(settings.canvas_size, &matter_definitions);
+        // Settings show the (rounded) size in use, also if resizing failed
+        settings.canvas_size = simulation.canvas_size();
+        result?;
+        info!(
+            "Resized canvas to {}x{}",
+            settings.canvas_size.x, settings.canvas_size.y
+        );
+    }
+    Ok(())
+}
+
+/// Move a streaming canvas along with the camera
+#[sysfail(log(level = "error"))]
+fn stream_simulation(
+    camera: Res<OrthographicCamera>,
+    mut simulation: ResMut<Simulation>,
+    matter_definitions: Res<MatterDefinitions>,
+) -> Result<()> {
+    // The camera looks at the negated camera position
+    simulation.stream_to(-camera.pos, &matter_definitions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clock_carries_partial_steps() {
+        let mut clock = SimulationClock::default();
+        assert_eq!(clock.advance(TIME_STEP * 2.25, 1.0), 2);
+        assert_eq!(clock.advance(TIME_STEP * 0.5, 1.0), 0);
+        assert_eq!(clock.advance(TIME_STEP * 0.5, 1.0), 1);
+    }
+
+    #[test]
+    fn clock_scales_by_speed() {
+        let mut clock = SimulationClock::default();
+        assert_eq!(clock.advance(TIME_STEP * 1.25, 2.0), 2);
+
+        // Speeds are clamped
+        let mut clock = SimulationClock::default();
+        assert_eq!(clock.advance(TIME_STEP * 2.5, 0.0), 0);
+        assert_eq!(clock.advance(TIME_STEP * 2.5, 0.0), 1);
+        let mut clock = SimulationClock::default();
+        assert_eq!(clock.advance(TIME_STEP * 1.3, 100.0), 10);
+    }
+
+    #[test]
+    fn clock_drops_lag() {
+        let mut clock = SimulationClock::default();
+        assert_eq!(clock.advance(1.0, 1.0), MAX_CATCH_UP_STEPS);
+        assert_eq!(clock.advance(TIME_STEP * 0.5, 1.0), 0);
+    }
 }
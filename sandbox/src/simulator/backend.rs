@@ -0,0 +1,188 @@
+use anyhow::Result;
+use bevy::{
+    math::{IVec2, UVec2},
+    prelude::{FromReflect, Reflect, Vec2},
+};
+use serde::{Deserialize, Serialize};
+use vulkano_util::renderer::DeviceImageView;
+
+use super::{cpu_simulator::drawn_matter, grid_readback::GridCopyInfo};
+use crate::{
+    matter::{matter_definition::MatterDefinitions, MatterCell},
+    settings::AppSettings,
+};
+
+/// Which simulation backend steps the cellular automata
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Reflect, FromReflect, Serialize, Deserialize)]
+pub enum SimulationBackendKind {
+    /// Vulkano compute shaders (`CASimulator`)
+    Gpu,
+    /// Cpu reference kernels (`CPUSimulator`), no vulkan device needed for the simulation itself
+    Cpu,
+}
+
+impl Default for SimulationBackendKind {
+    fn default() -> Self {
+        if cfg!(feature = "wasm") {
+            SimulationBackendKind::Cpu
+        } else {
+            SimulationBackendKind::Gpu
+        }
+    }
+}
+
+/// Matter, temperature & cell data of a canvas rectangle, each row by row starting from its
+/// bottom left cell
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CellGrids {
+    pub matter: Vec<u32>,
+    pub temperature: Vec<f32>,
+    /// `CellData` values
+    pub cell_data: Vec<u32>,
+}
+
+impl CellGrids {
+    /// Empty matter for the canvas rectangle from `min` with `size`, at its temperature & cell
+    /// data
+    pub fn empty(min: UVec2, size: UVec2, matter_definitions: &MatterDefinitions) -> CellGrids {
+        let matter = (min.y..min.y + size.y)
+            .flat_map(|y| (min.x..min.x + size.x).map(move |x| UVec2::new(x, y)))
+            .map(|pos| drawn_matter(pos.as_ivec2(), matter_definitions.empty))
+            .collect();
+        CellGrids::at_defaults(matter, matter_definitions)
+    }
+
+    /// Matter cells at their matter's temperature & cell data
+    pub fn at_defaults(matter: Vec<u32>, matter_definitions: &MatterDefinitions) -> CellGrids {
+        let ids = || {
+            matter
+                .iter()
+                .map(|&cell| MatterCell::from(cell).matter_id())
+        };
+        CellGrids {
+            temperature: ids().map(|id| matter_definitions.temperature(id)).collect(),
+            cell_data: ids()
+                .map(|id| matter_definitions.cell_data(id).value)
+                .collect(),
+            matter,
+        }
+    }
+
+    /// Number of cells, `None` unless every grid has as many
+    pub fn num_cells(&self) -> Option<usize> {
+        let num_cells = self.matter.len();
+        (self.temperature.len() == num_cells && self.cell_data.len() == num_cells)
+            .then_some(num_cells)
+    }
+
+    /// Move matter to its id in `id_map`, matter without one becomes empty and restarts at the
+    /// empty matter's temperature & cell data
+    pub fn remap_matters(
+        &mut self,
+        id_map: &[Option<u32>],
+        matter_definitions: &MatterDefinitions,
+    ) {
+        let empty = matter_definitions.empty;
+        let cells = self
+            .matter
+            .iter_mut()
+            .zip(self.temperature.iter_mut())
+            .zip(self.cell_data.iter_mut());
+        for ((matter, temperature), cell_data) in cells {
+            let cell = MatterCell::from(*matter);
+            if !matches!(id_map.get(cell.matter_id() as usize), Some(Some(_))) {
+                *temperature = matter_definitions.temperature(empty);
+                *cell_data = matter_definitions.cell_data(empty).value;
+            }
+            *matter = cell.remapped(id_map, empty).value;
+        }
+    }
+}
+
+/// Everything `Simulation` needs from a cellular automata implementation
+pub trait SimulationBackend: Send + Sync {
+    /// Step simulation. Paused steps only color the canvas and don't count towards `sim_steps`.
+    fn step(&mut self, settings: &AppSettings);
+
+    /// Draw matter line with given radius
+    fn draw_matter(&mut self, start: Vec2, end: Vec2, matter: u32, radius: f32, is_square: bool);
+
+    /// Start querying the matter at pos without waiting, the result arrives through `poll_query`.
+    /// Positions outside the canvas are ignored, as are queries while all earlier ones are in
+    /// flight.
+    fn request_query(&mut self, pos: IVec2) -> Result<()>;
+
+    /// Position & matter id of the latest finished query, `None` if none finished since the last
+    /// poll
+    fn poll_query(&mut self) -> Result<Option<(IVec2, u32)>>;
+
+    /// Read back the whole matter grid (`matter_in`), row by row starting from y = 0. Waits for
+    /// the simulation and for copies in flight.
+    fn read_matter_grid(&mut self) -> Result<Vec<u32>> {
+        self.read_matter_region(UVec2::ZERO, self.canvas_size())
+    }
+
+    /// Read back the matter of the canvas rectangle from `min` with `size`, row by row starting
+    /// from `min`. Waits like `read_matter_grid`.
+    fn read_matter_region(&mut self, min: UVec2, size: UVec2) -> Result<Vec<u32>>;
+
+    /// Start copying the matter grid without waiting, it arrives through `poll_matter_grid`.
+    /// `canvas_origin` is handed back with the copy. Ignored while an earlier copy is in flight.
+    fn request_matter_grid(&mut self, canvas_origin: IVec2) -> Result<()>;
+
+    /// Where & when the copy was requested & matter grid of the latest finished
+    /// `request_matter_grid`, same layout as `read_matter_grid`
+    fn poll_matter_grid(&mut self) -> Result<Option<(GridCopyInfo, Vec<u32>)>>;
+
+    /// Start counting the cells of each matter without waiting, the counts arrive through
+    /// `poll_census`. Ignored while all earlier counts are in flight.
+    fn request_census(&mut self) -> Result<()>;
+
+    /// Step count at the census & cells per matter id (`MAX_NUM_MATTERS` of them) of the latest
+    /// finished `request_census`
+    fn poll_census(&mut self) -> Result<Option<(u32, Vec<u32>)>>;
+
+    /// Overwrite the whole matter grid (`matter_in`), same layout as `read_matter_grid`. Cells
+    /// that change matter start at the temperature & cell data of their new matter.
+    fn write_matter_grid(&mut self, grid: &[u32]) -> Result<()> {
+        self.write_matter_region(UVec2::ZERO, self.canvas_size(), grid)
+    }
+
+    /// Overwrite the matter of a canvas rectangle like `write_matter_grid`, same layout as
+    /// `read_matter_region`
+    fn write_matter_region(&mut self, min: UVec2, size: UVec2, cells: &[u32]) -> Result<()>;
+
+    /// Read back matter, temperature & cell data of a canvas rectangle, same layout as
+    /// `read_matter_region`. Waits like `read_matter_grid`.
+    fn read_cells(&mut self, min: UVec2, size: UVec2) -> Result<CellGrids>;
+
+    /// Overwrite matter, temperature & cell data of a canvas rectangle as they are, e.g. to
+    /// restore cells read with `read_cells`
+    fn write_cells(&mut self, min: UVec2, size: UVec2, cells: &CellGrids) -> Result<()>;
+
+    /// Canvas image on the device, if the backend renders into one
+    fn color_image(&self) -> Option<DeviceImageView>;
+
+    /// Read back canvas colors as `R8G8B8A8_UNORM` bytes, row by row starting from y = 0
+    fn read_color_image(&self) -> Result<Vec<u8>>;
+
+    /// Upload matter definition tables used by the kernels
+    fn update_matter_data(&mut self, matter_definitions: &MatterDefinitions) -> Result<()>;
+
+    /// Sources & sinks applied at the start of every step, `EMITTER_STRIDE` values each (see
+    /// `Emitter::to_kernel_data`). Replaces the previous ones.
+    fn set_emitters(&mut self, emitters: &[u32]) -> Result<()>;
+
+    /// Number of steps simulated so far
+    fn sim_steps(&self) -> u32;
+
+    /// Continue step counting from `sim_steps`. Per step seeds depend on it.
+    fn set_sim_steps(&mut self, sim_steps: u32);
+
+    /// Width & height of the matter grid and canvas image
+    fn canvas_size(&self) -> UVec2;
+
+    /// Recreate grid buffers & canvas image for a new canvas size (a multiple of `KERNEL_SIZE`).
+    /// The grid is cleared, `Simulation::resize` keeps its contents.
+    fn resize(&mut self, canvas_size: UVec2) -> Result<()>;
+}
@@ -0,0 +1,253 @@
+use bevy::prelude::{FromReflect, Reflect};
+use serde::{Deserialize, Serialize};
+use strum::IntoEnumIterator;
+use strum_macros::EnumIter;
+
+use crate::matter::matter_definition::MatterDefinitions;
+
+/// Canvas edges, in the order of `PushConstants::boundaries` (`EDGE_*` in `helpers/dirs.glsl`)
+#[derive(EnumIter, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Edge {
+    Top,
+    Right,
+    Bottom,
+    Left,
+}
+
+impl Edge {
+    /// The edge across the canvas, wrapping edges come in such pairs
+    pub fn opposite(self) -> Edge {
+        match self {
+            Edge::Top => Edge::Bottom,
+            Edge::Right => Edge::Left,
+            Edge::Bottom => Edge::Top,
+            Edge::Left => Edge::Right,
+        }
+    }
+}
+
+impl std::fmt::Display for Edge {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+/// What lies beyond a canvas edge, see `BOUNDARIES` in `helpers/query.glsl`
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Reflect, FromReflect)]
+pub enum Boundary {
+    /// Matter can't move across, off-canvas cells read as empty
+    #[default]
+    Wall,
+    /// Off-canvas cells are the cells across the canvas (toroidal). Set on both edges of an axis.
+    Wrap,
+    /// Off-canvas cells read as empty, matter moving out is deleted
+    Void,
+    /// Off-canvas cells are this matter, which keeps moving in. Matter moving out is absorbed.
+    Source(u32),
+}
+
+impl Boundary {
+    /// As pushed to the kernels: bits 0-7 mode, bits 16-31 matter of source edges
+    pub fn to_uint(self) -> u32 {
+        match self {
+            Boundary::Wall => 0,
+            Boundary::Wrap => 1,
+            Boundary::Void => 2,
+            Boundary::Source(matter) => 3 | (matter << 16),
+        }
+    }
+
+    pub fn is_wrap(self) -> bool {
+        self == Boundary::Wrap
+    }
+}
+
+impl std::fmt::Display for Boundary {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Boundary::Source(_) => write!(f, "Source"),
+            _ => write!(f, "{:?}", self),
+        }
+    }
+}
+
+/// Boundary of each canvas edge, from `BoundariesConfig::validated` or the gui
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Reflect, FromReflect)]
+pub struct Boundaries {
+    pub top: Boundary,
+    pub right: Boundary,
+    pub bottom: Boundary,
+    pub left: Boundary,
+}
+
+impl Boundaries {
+    pub fn get(&self, edge: Edge) -> Boundary {
+        match edge {
+            Edge::Top => self.top,
+            Edge::Right => self.right,
+            Edge::Bottom => self.bottom,
+            Edge::Left => self.left,
+        }
+    }
+
+    fn get_mut(&mut self, edge: Edge) -> &mut Boundary {
+        match edge {
+            Edge::Top => &mut self.top,
+            Edge::Right => &mut self.right,
+            Edge::Bottom => &mut self.bottom,
+            Edge::Left => &mut self.left,
+        }
+    }
+
+    /// Set an edge's boundary. Wrapping starts & stops on both edges of the axis at once.
+    pub fn set(&mut self, edge: Edge, boundary: Boundary) {
+        let opposite = self.get_mut(edge.opposite());
+        if boundary.is_wrap() {
+            *opposite = Boundary::Wrap;
+        } else if opposite.is_wrap() {
+            *opposite = Boundary::Wall;
+        }
+        *self.get_mut(edge) = boundary;
+    }
+
+    /// Source matter moved to its id in `id_map` (e.g. after a matter definition was removed),
+    /// sources of matter without one become walls
+    pub fn remapped(mut self, id_map: &[Option<u32>]) -> Boundaries {
+        for edge in Edge::iter() {
+            if let Boundary::Source(matter) = self.get(edge) {
+                let matter = id_map.get(matter as usize).copied().flatten();
+                *self.get_mut(edge) = matter.map_or(Boundary::Wall, Boundary::Source);
+            }
+        }
+        self
+    }
+
+    /// As pushed to the kernels, indexed by `Edge`
+    pub fn to_push_constants(self) -> [u32; 4] {
+        [
+            self.top.to_uint(),
+            self.right.to_uint(),
+            self.bottom.to_uint(),
+            self.left.to_uint(),
+        ]
+    }
+}
+
+/// What lies beyond a canvas edge as written in the game config, source matter by name
+#[derive(Debug, Default, Clone, PartialEq, Eq, Reflect, FromReflect, Serialize, Deserialize)]
+pub enum BoundaryConfig {
+    #[default]
+    Wall,
+    Wrap,
+    Void,
+    Source(String),
+}
+
+/// Boundary of each canvas edge in the game config, e.g.
+/// `boundaries = { left = "Wrap", right = "Wrap", top = { Source = "Water" } }`. Source matter is
+/// named, its id changes with the order of the matter definitions.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Reflect, FromReflect, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BoundariesConfig {
+    pub top: BoundaryConfig,
+    pub right: BoundaryConfig,
+    pub bottom: BoundaryConfig,
+    pub left: BoundaryConfig,
+}
+
+impl BoundariesConfig {
+    pub fn get(&self, edge: Edge) -> &BoundaryConfig {
+        match edge {
+            Edge::Top => &self.top,
+            Edge::Right => &self.right,
+            Edge::Bottom => &self.bottom,
+            Edge::Left => &self.left,
+        }
+    }
+
+    /// Boundaries with source matter resolved by name, a source of matter that doesn't exist
+    /// becomes a wall. So does a wrapping edge whose opposite edge doesn't wrap, matter would
+    /// otherwise leave on one side without arriving on the other.
+    pub fn validated(&self, matter_definitions: &MatterDefinitions) -> Boundaries {
+        let mut boundaries = Boundaries::default();
+        for edge in Edge::iter() {
+            *boundaries.get_mut(edge) = match self.get(edge) {
+                BoundaryConfig::Wall => Boundary::Wall,
+                BoundaryConfig::Wrap => Boundary::Wrap,
+                BoundaryConfig::Void => Boundary::Void,
+                BoundaryConfig::Source(name) => {
+                    match matter_definitions
+                        .definitions
+                        .iter()
+                        .find(|matter| &matter.name == name)
+                    {
+                        Some(matter) => Boundary::Source(matter.id),
+                        None => {
+                            log::warn!(
+                                "{} edge source matter {} doesn't exist, using a wall",
+                                edge,
+                                name
+                            );
+                            Boundary::Wall
+                        }
+                    }
+                }
+            };
+        }
+
+        for edge in Edge::iter() {
+            if boundaries.get(edge).is_wrap() && !boundaries.get(edge.opposite()).is_wrap() {
+                log::warn!(
+                    "{} edge wraps but the {} edge doesn't, using a wall",
+                    edge,
+                    edge.opposite()
+                );
+                *boundaries.get_mut(edge) = Boundary::Wall;
+            }
+        }
+        boundaries
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matter::{default_matter_definitions, MATTER_WATER};
+
+    #[test]
+    fn validated_resolves_source_names() {
+        let config = BoundariesConfig {
+            top: BoundaryConfig::Source("Water".to_string()),
+            bottom: BoundaryConfig::Source("Mud".to_string()),
+            left: BoundaryConfig::Wrap,
+            right: BoundaryConfig::Void,
+        };
+        let boundaries = config.validated(&default_matter_definitions());
+        assert_eq!(boundaries, Boundaries {
+            top: Boundary::Source(MATTER_WATER),
+            right: Boundary::Void,
+            // Unknown matter & wrapping without the opposite edge become walls
+            bottom: Boundary::Wall,
+            left: Boundary::Wall,
+        });
+    }
+
+    #[test]
+    fn validated_follows_matter_names() {
+        let config = BoundariesConfig {
+            top: BoundaryConfig::Source("Water".to_string()),
+            ..BoundariesConfig::default()
+        };
+        let mut matter_definitions = default_matter_definitions();
+        matter_definitions
+            .definitions
+            .swap(1, MATTER_WATER as usize);
+        for (id, matter) in matter_definitions.definitions.iter_mut().enumerate() {
+            matter.id = id as u32;
+        }
+        assert_eq!(
+            config.validated(&matter_definitions).top,
+            Boundary::Source(1)
+        );
+    }
+}
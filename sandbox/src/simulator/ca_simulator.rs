@@ -1,11 +1,15 @@
 use std::sync::Arc;
 
-use anyhow::Result;
-use bevy::{math::IVec2, prelude::Vec2, utils::Instant};
+use anyhow::{ensure, Result};
+use bevy::{
+    math::{IVec2, UVec2},
+    prelude::Vec2,
+};
 use vulkano::{
     buffer::Subbuffer,
     command_buffer::{
         allocator::StandardCommandBufferAllocator, AutoCommandBufferBuilder, CommandBufferUsage,
+        CopyBufferInfo, CopyImageToBufferInfo, DispatchIndirectCommand, FillBufferInfo,
         PrimaryAutoCommandBuffer, PrimaryCommandBufferAbstract,
     },
     descriptor_set::{
@@ -22,18 +26,64 @@ use vulkano_util::renderer::DeviceImageView;
 
 use crate::{
     matter::{
-        matter_definition::MatterDefinitions, matter_state::MatterState, MatterWithColor,
-        MAX_NUM_MATTERS,
+        matter_definition::{
+            MatterDefinitions, MAX_LIFETIME, MAX_TRANSITIONS, NUM_TEMPERATURE_TRANSITIONS,
+        },
+        matter_state::MatterState,
+        CellData, MatterCell, MAX_NUM_MATTERS,
     },
     render::utils::{create_compute_pipeline, storage_buffer_desc, storage_image_desc},
     settings::AppSettings,
-    simulator::gpu_utils::{empty_f32, empty_u32, empty_with},
-    utils::is_inside_sim_canvas,
-    KERNEL_SIZE, NUM_WORK_GROUPS, SIM_CANVAS_SIZE,
+    simulator::{
+        backend::{CellGrids, SimulationBackend},
+        boundary::Boundaries,
+        emitters::{EMITTER_STRIDE, MAX_EMITTERS},
+        gpu_utils::{
+            empty_download_u8, empty_f32, empty_u32, empty_with, indirect_dispatch, ReadbackRing,
+            SubmitFence,
+        },
+        grid_readback::GridCopyInfo,
+        push_constants::{step_seed, PushConstants},
+    },
+    utils::{is_inside_sim_canvas, read_region, region_rows, tile_index, write_region},
+    KERNEL_SIZE,
+};
+
+/// Queries in flight at once, more are ignored until one finishes
+const NUM_QUERY_READBACKS: usize = 3;
+
+/// Censuses in flight at once, more are ignored until one finishes
+const NUM_CENSUS_READBACKS: usize = 2;
+
+/// No awake tiles, `tiles.glsl` counts them up from here
+const NO_AWAKE_TILES: DispatchIndirectCommand = DispatchIndirectCommand {
+    x: 0,
+    y: 1,
+    z: 1,
 };
 
+/// Per cell & per tile buffers and the color image, sized by the canvas
+struct Canvas {
+    image: DeviceImageView,
+    matter_in: Subbuffer<[u32]>,
+    matter_out: Subbuffer<[u32]>,
+    temperature_in: Subbuffer<[f32]>,
+    temperature_out: Subbuffer<[f32]>,
+    cell_data_in: Subbuffer<[u32]>,
+    cell_data_out: Subbuffer<[u32]>,
+    tile_activity: Subbuffer<[u32]>,
+    tile_awake: Subbuffer<[u32]>,
+    awake_tiles: Subbuffer<[u32]>,
+    awake_tile_dispatch: Subbuffer<[DispatchIndirectCommand]>,
+}
+
 struct Pipelines {
+    emit_pipeline: Arc<ComputePipeline>,
+    heat_pipeline: Arc<ComputePipeline>,
+    census_pipeline: Arc<ComputePipeline>,
+    tiles_pipeline: Arc<ComputePipeline>,
     color_pipeline: Arc<ComputePipeline>,
+    react_pipeline: Arc<ComputePipeline>,
     rise_swap_pipeline: Arc<ComputePipeline>,
     fall_swap_pipeline: Arc<ComputePipeline>,
     fall_empty_pipeline: Arc<ComputePipeline>,
@@ -59,19 +109,57 @@ pub struct CASimulator {
     dispersion_dir: u32,
     dispersion_step: u32,
     draw_pos_start: Vec2,
-    draw_matter: MatterWithColor,
+    draw_matter: MatterCell,
+    show_awake_tiles: bool,
+    boundaries: Boundaries,
+    num_emitters: u32,
 
     // Shader matter inputs
     image: DeviceImageView,
     matter_out: Subbuffer<[u32]>,
     query_matter: Subbuffer<[u32]>,
     pub matter_in: Subbuffer<[u32]>,
+    temperature_in: Subbuffer<[f32]>,
+    temperature_out: Subbuffer<[f32]>,
+    cell_data_in: Subbuffer<[u32]>,
+    cell_data_out: Subbuffer<[u32]>,
     matter_state_input: Subbuffer<[u32]>,
     matter_weight_input: Subbuffer<[f32]>,
     matter_dispersion_input: Subbuffer<[u32]>,
+    matter_characteristics_input: Subbuffer<[u32]>,
+    matter_color_input: Subbuffer<[u32]>,
+    matter_reaction_reacts_input: Subbuffer<[u32]>,
+    matter_reaction_direction_input: Subbuffer<[u32]>,
+    matter_reaction_probability_input: Subbuffer<[f32]>,
+    matter_reaction_becomes_input: Subbuffer<[u32]>,
+    matter_conductivity_input: Subbuffer<[f32]>,
+    matter_heat_capacity_input: Subbuffer<[f32]>,
+    matter_temperature_input: Subbuffer<[f32]>,
+    matter_transition_point_input: Subbuffer<[f32]>,
+    matter_transition_becomes_input: Subbuffer<[u32]>,
+    matter_lifetime_input: Subbuffer<[u32]>,
+    emitters: Subbuffer<[u32]>,
+    census: Subbuffer<[u32]>,
+
+    // Tiles, movement kernels only run on awake tiles
+    tile_activity: Subbuffer<[u32]>,
+    tile_awake: Subbuffer<[u32]>,
+    awake_tiles: Subbuffer<[u32]>,
+    awake_tile_dispatch: Subbuffer<[DispatchIndirectCommand]>,
+    no_awake_tiles: Subbuffer<[DispatchIndirectCommand]>,
+
+    // Readbacks that don't wait on the device, tagged with the queried position & step count
+    query_readback: ReadbackRing<IVec2>,
+    grid_readback: ReadbackRing<GridCopyInfo>,
+    census_readback: ReadbackRing<u32>,
 
     // Pipelines
+    emit_pipeline: Arc<ComputePipeline>,
+    heat_pipeline: Arc<ComputePipeline>,
+    census_pipeline: Arc<ComputePipeline>,
+    tiles_pipeline: Arc<ComputePipeline>,
     color_pipeline: Arc<ComputePipeline>,
+    react_pipeline: Arc<ComputePipeline>,
     draw_matter_pipeline: Arc<ComputePipeline>,
     query_matter_pipeline: Arc<ComputePipeline>,
 
@@ -85,43 +173,76 @@ pub struct CASimulator {
     slide_down_empty_pipeline: Arc<ComputePipeline>,
 
     // Misc
-    start: Instant,
+    canvas_size: UVec2,
     compute_queue: Arc<Queue>,
+    memory_allocator: Arc<StandardMemoryAllocator>,
     matter_definitions: MatterDefinitions,
     command_buffer_allocator: StandardCommandBufferAllocator,
     descriptor_set_allocator: StandardDescriptorSetAllocator,
 }
 
 impl CASimulator {
-    /// Create new simulator pipeline for a compute queue. Ensure that canvas sizes are divisible by
-    /// kernel sizes so no pixel remains unsimulated.
+    /// Create new simulator pipeline for a compute queue. Canvas sizes must be divisible by
+    /// kernel sizes so no pixel remains unsimulated, see `round_canvas_size`.
     pub fn new(
         allocator: &Arc<StandardMemoryAllocator>,
         compute_queue: Arc<Queue>,
         matter_definitions: &MatterDefinitions,
+        canvas_size: UVec2,
     ) -> Result<CASimulator> {
-        // In order to not miss any pixels, the following must be true
-        assert_eq!(SIM_CANVAS_SIZE % KERNEL_SIZE, 0);
-
-        let matter_in = empty_u32(allocator, (SIM_CANVAS_SIZE * SIM_CANVAS_SIZE) as usize)?;
-        let matter_out = empty_u32(allocator, (SIM_CANVAS_SIZE * SIM_CANVAS_SIZE) as usize)?;
-        let query_matter = empty_with(allocator, vec![MatterWithColor::from(0).value])?;
+        let Canvas {
+            image,
+            matter_in,
+            matter_out,
+            temperature_in,
+            temperature_out,
+            cell_data_in,
+            cell_data_out,
+            tile_activity,
+            tile_awake,
+            awake_tiles,
+            awake_tile_dispatch,
+        } = CASimulator::create_canvas(
+            allocator,
+            &compute_queue,
+            canvas_size,
+            matter_definitions.temperature(matter_definitions.empty),
+            matter_definitions.cell_data(matter_definitions.empty),
+        )?;
+        let query_matter = empty_with(allocator, vec![MatterCell::from(0).value])?;
         let matter_state_input = empty_u32(allocator, MAX_NUM_MATTERS as usize)?;
         let matter_weight_input = empty_f32(allocator, MAX_NUM_MATTERS as usize)?;
         let matter_dispersion_input = empty_u32(allocator, MAX_NUM_MATTERS as usize)?;
-
-        // Create color image
-        let image = StorageImage::general_purpose_image_view(
-            allocator,
-            compute_queue.clone(),
-            [SIM_CANVAS_SIZE, SIM_CANVAS_SIZE],
-            Format::R8G8B8A8_UNORM,
-            ImageUsage::SAMPLED | ImageUsage::STORAGE | ImageUsage::TRANSFER_DST,
-        )?;
+        let matter_characteristics_input = empty_u32(allocator, MAX_NUM_MATTERS as usize)?;
+        let matter_color_input = empty_u32(allocator, MAX_NUM_MATTERS as usize)?;
+        let num_reactions = (MAX_NUM_MATTERS * MAX_TRANSITIONS) as usize;
+        let matter_reaction_reacts_input = empty_u32(allocator, num_reactions)?;
+        let matter_reaction_direction_input = empty_u32(allocator, num_reactions)?;
+        let matter_reaction_probability_input = empty_f32(allocator, num_reactions)?;
+        let matter_reaction_becomes_input = empty_u32(allocator, num_reactions)?;
+        let matter_conductivity_input = empty_f32(allocator, MAX_NUM_MATTERS as usize)?;
+        let matter_heat_capacity_input = empty_f32(allocator, MAX_NUM_MATTERS as usize)?;
+        let matter_temperature_input = empty_f32(allocator, MAX_NUM_MATTERS as usize)?;
+        let num_transitions = (MAX_NUM_MATTERS * NUM_TEMPERATURE_TRANSITIONS) as usize;
+        let matter_transition_point_input = empty_f32(allocator, num_transitions)?;
+        let matter_transition_becomes_input = empty_u32(allocator, num_transitions)?;
+        let matter_lifetime_input = empty_u32(allocator, MAX_NUM_MATTERS as usize)?;
+        let emitters = empty_u32(allocator, MAX_EMITTERS * EMITTER_STRIDE)?;
+        let census = empty_u32(allocator, MAX_NUM_MATTERS as usize)?;
+        let no_awake_tiles = indirect_dispatch(allocator, NO_AWAKE_TILES)?;
+        let query_readback = ReadbackRing::new(allocator, NUM_QUERY_READBACKS, 1)?;
+        let grid_readback = ReadbackRing::new(allocator, 1, matter_in.len() as usize)?;
+        let census_readback =
+            ReadbackRing::new(allocator, NUM_CENSUS_READBACKS, MAX_NUM_MATTERS as usize)?;
 
         // Create pipelines
         let Pipelines {
+            emit_pipeline,
+            heat_pipeline,
+            census_pipeline,
+            tiles_pipeline,
             color_pipeline,
+            react_pipeline,
             rise_swap_pipeline,
             fall_swap_pipeline,
             rise_empty_pipeline,
@@ -132,7 +253,7 @@ impl CASimulator {
             slide_down_swap_pipeline,
             horizontal_empty_pipeline,
             slide_down_empty_pipeline,
-        } = CASimulator::create_pipelines(&compute_queue, matter_definitions.empty)?;
+        } = CASimulator::create_pipelines(&compute_queue, matter_definitions.empty, canvas_size)?;
 
         Ok(CASimulator {
             // Push constants
@@ -146,19 +267,57 @@ impl CASimulator {
             query_pos: IVec2::new(0, 0),
             draw_pos_end: Vec2::new(0.0, 0.0),
             draw_pos_start: Vec2::new(0.0, 0.0),
-            draw_matter: MatterWithColor::from(0),
+            draw_matter: MatterCell::from(0),
+            show_awake_tiles: false,
+            boundaries: Boundaries::default(),
+            num_emitters: 0,
             empty_matter: matter_definitions.empty,
 
             // Shader matter inputs
             matter_in,
             matter_out,
+            temperature_in,
+            temperature_out,
+            cell_data_in,
+            cell_data_out,
             query_matter,
             matter_state_input,
             matter_weight_input,
             matter_dispersion_input,
+            matter_characteristics_input,
+            matter_color_input,
+            matter_reaction_reacts_input,
+            matter_reaction_direction_input,
+            matter_reaction_probability_input,
+            matter_reaction_becomes_input,
+            matter_conductivity_input,
+            matter_heat_capacity_input,
+            matter_temperature_input,
+            matter_transition_point_input,
+            matter_transition_becomes_input,
+            matter_lifetime_input,
+            emitters,
+            census,
+
+            // Tiles
+            tile_activity,
+            tile_awake,
+            awake_tiles,
+            awake_tile_dispatch,
+            no_awake_tiles,
+
+            // Readbacks
+            query_readback,
+            grid_readback,
+            census_readback,
 
             // Pipelines
+            emit_pipeline,
+            heat_pipeline,
+            census_pipeline,
+            tiles_pipeline,
             color_pipeline,
+            react_pipeline,
             fall_swap_pipeline,
             rise_swap_pipeline,
             rise_empty_pipeline,
@@ -171,8 +330,9 @@ impl CASimulator {
             slide_down_empty_pipeline,
 
             // Misc
+            canvas_size,
             compute_queue,
-            start: Instant::now(),
+            memory_allocator: allocator.clone(),
             matter_definitions: matter_definitions.clone(),
             command_buffer_allocator: StandardCommandBufferAllocator::new(
                 allocator.device().clone(),
@@ -186,7 +346,67 @@ impl CASimulator {
 }
 
 impl CASimulator {
-    fn create_pipelines(compute_queue: &Arc<Queue>, empty_matter: u32) -> Result<Pipelines> {
+    /// Matter, temperature & cell data in and out grids, tiles and the color image for a canvas
+    /// size. All cells start at `temperature` & `cell_data`, all tiles are active so the first
+    /// step moves everything.
+    fn create_canvas(
+        allocator: &Arc<StandardMemoryAllocator>,
+        compute_queue: &Arc<Queue>,
+        canvas_size: UVec2,
+        temperature: f32,
+        cell_data: CellData,
+    ) -> Result<Canvas> {
+        // In order to not miss any pixels, the following must be true
+        ensure!(
+            canvas_size.x % KERNEL_SIZE == 0 && canvas_size.y % KERNEL_SIZE == 0,
+            "Canvas size {} is not a multiple of kernel size {}",
+            canvas_size,
+            KERNEL_SIZE
+        );
+
+        let num_cells = (canvas_size.x * canvas_size.y) as usize;
+        let matter_in = empty_u32(allocator, num_cells)?;
+        let matter_out = empty_u32(allocator, num_cells)?;
+        let temperature_in = empty_with(allocator, vec![temperature; num_cells])?;
+        let temperature_out = empty_with(allocator, vec![temperature; num_cells])?;
+        let cell_data_in = empty_with(allocator, vec![cell_data.value; num_cells])?;
+        let cell_data_out = empty_with(allocator, vec![cell_data.value; num_cells])?;
+
+        let num_tiles = (canvas_size.x / KERNEL_SIZE * canvas_size.y / KERNEL_SIZE) as usize;
+        let tile_activity = empty_with(allocator, vec![1; num_tiles])?;
+        let tile_awake = empty_u32(allocator, num_tiles)?;
+        let awake_tiles = empty_u32(allocator, num_tiles)?;
+        let awake_tile_dispatch = indirect_dispatch(allocator, NO_AWAKE_TILES)?;
+
+        // Create color image
+        let image = StorageImage::general_purpose_image_view(
+            allocator,
+            compute_queue.clone(),
+            canvas_size.to_array(),
+            Format::R8G8B8A8_UNORM,
+            ImageUsage::SAMPLED | ImageUsage::STORAGE | ImageUsage::TRANSFER_DST,
+        )?;
+
+        Ok(Canvas {
+            image,
+            matter_in,
+            matter_out,
+            temperature_in,
+            temperature_out,
+            cell_data_in,
+            cell_data_out,
+            tile_activity,
+            tile_awake,
+            awake_tiles,
+            awake_tile_dispatch,
+        })
+    }
+
+    fn create_pipelines(
+        compute_queue: &Arc<Queue>,
+        empty_matter: u32,
+        canvas_size: UVec2,
+    ) -> Result<Pipelines> {
         let spec_const = color_cs::SpecializationConstants {
             empty_matter,
             constant_8: KERNEL_SIZE,
@@ -197,7 +417,8 @@ impl CASimulator {
             state_powder: MatterState::Powder as u32,
             state_liquid: MatterState::Liquid as u32,
             state_solid_gravity: MatterState::SolidGravity as u32,
-            sim_canvas_size: SIM_CANVAS_SIZE as i32,
+            sim_canvas_width: canvas_size.x as i32,
+            sim_canvas_height: canvas_size.y as i32,
         };
 
         // This must match the shader & inputs in dispatch
@@ -209,6 +430,28 @@ impl CASimulator {
             (4, storage_buffer_desc()),
             (5, storage_buffer_desc()),
             (6, storage_image_desc()),
+            (7, storage_buffer_desc()),
+            (8, storage_buffer_desc()),
+            (9, storage_buffer_desc()),
+            (10, storage_buffer_desc()),
+            (11, storage_buffer_desc()),
+            (12, storage_buffer_desc()),
+            (13, storage_buffer_desc()),
+            (14, storage_buffer_desc()),
+            (15, storage_buffer_desc()),
+            (16, storage_buffer_desc()),
+            (17, storage_buffer_desc()),
+            (18, storage_buffer_desc()),
+            (19, storage_buffer_desc()),
+            (20, storage_buffer_desc()),
+            (21, storage_buffer_desc()),
+            (22, storage_buffer_desc()),
+            (23, storage_buffer_desc()),
+            (24, storage_buffer_desc()),
+            (25, storage_buffer_desc()),
+            (26, storage_buffer_desc()),
+            (27, storage_buffer_desc()),
+            (28, storage_buffer_desc()),
         ];
 
         let fall_empty_pipeline = {
@@ -304,6 +547,56 @@ impl CASimulator {
             )
         };
 
+        let react_pipeline = {
+            let shader = react_cs::load(compute_queue.device().clone())?;
+            create_compute_pipeline(
+                compute_queue.clone(),
+                shader.entry_point("main").unwrap(),
+                descriptor_layout.to_vec(),
+                &spec_const,
+            )
+        };
+
+        let emit_pipeline = {
+            let shader = emit_cs::load(compute_queue.device().clone())?;
+            create_compute_pipeline(
+                compute_queue.clone(),
+                shader.entry_point("main").unwrap(),
+                descriptor_layout.to_vec(),
+                &spec_const,
+            )
+        };
+
+        let census_pipeline = {
+            let shader = census_cs::load(compute_queue.device().clone())?;
+            create_compute_pipeline(
+                compute_queue.clone(),
+                shader.entry_point("main").unwrap(),
+                descriptor_layout.to_vec(),
+                &spec_const,
+            )
+        };
+
+        let heat_pipeline = {
+            let shader = heat_cs::load(compute_queue.device().clone())?;
+            create_compute_pipeline(
+                compute_queue.clone(),
+                shader.entry_point("main").unwrap(),
+                descriptor_layout.to_vec(),
+                &spec_const,
+            )
+        };
+
+        let tiles_pipeline = {
+            let shader = tiles_cs::load(compute_queue.device().clone())?;
+            create_compute_pipeline(
+                compute_queue.clone(),
+                shader.entry_point("main").unwrap(),
+                descriptor_layout.to_vec(),
+                &spec_const,
+            )
+        };
+
         let color_pipeline = {
             let color_shader = color_cs::load(compute_queue.device().clone())?;
             create_compute_pipeline(
@@ -315,7 +608,12 @@ impl CASimulator {
         };
 
         Ok(Pipelines {
+            emit_pipeline,
+            heat_pipeline,
+            census_pipeline,
+            tiles_pipeline,
             color_pipeline,
+            react_pipeline,
             rise_swap_pipeline,
             fall_swap_pipeline,
             rise_empty_pipeline,
@@ -328,34 +626,108 @@ impl CASimulator {
             slide_down_empty_pipeline,
         })
     }
+
+    /// Empty matter & canvas size are specialization constants
+    fn recreate_pipelines(&mut self, empty_matter: u32, canvas_size: UVec2) -> Result<()> {
+        Pipelines {
+            emit_pipeline: self.emit_pipeline,
+            heat_pipeline: self.heat_pipeline,
+            census_pipeline: self.census_pipeline,
+            tiles_pipeline: self.tiles_pipeline,
+            color_pipeline: self.color_pipeline,
+            react_pipeline: self.react_pipeline,
+            rise_swap_pipeline: self.rise_swap_pipeline,
+            fall_swap_pipeline: self.fall_swap_pipeline,
+            rise_empty_pipeline: self.rise_empty_pipeline,
+            fall_empty_pipeline: self.fall_empty_pipeline,
+            draw_matter_pipeline: self.draw_matter_pipeline,
+            query_matter_pipeline: self.query_matter_pipeline,
+            horizontal_swap_pipeline: self.horizontal_swap_pipeline,
+            slide_down_swap_pipeline: self.slide_down_swap_pipeline,
+            horizontal_empty_pipeline: self.horizontal_empty_pipeline,
+            slide_down_empty_pipeline: self.slide_down_empty_pipeline,
+        } = CASimulator::create_pipelines(&self.compute_queue, empty_matter, canvas_size)?;
+        Ok(())
+    }
 }
 
-// Query
-impl CASimulator {
-    /// Get canvas image for rendering
-    pub fn color_image(&self) -> DeviceImageView {
-        self.image.clone()
+impl SimulationBackend for CASimulator {
+    /// Step simulation
+    fn step(&mut self, settings: &AppSettings) {
+        self.seed = step_seed(settings.seed, self.sim_steps);
+        self.show_awake_tiles = settings.show_awake_tiles;
+
+        let mut builder = self.command_buffer_builder();
+
+        if settings.boundaries != self.boundaries {
+            // Matter resting against an edge may move now, wake everything
+            self.boundaries = settings.boundaries;
+            builder
+                .fill_buffer(FillBufferInfo {
+                    data: 1,
+                    ..FillBufferInfo::dst_buffer(self.tile_activity.clone())
+                })
+                .unwrap();
+        }
+
+        if !settings.is_paused {
+            // Sources & sinks, before waking tiles so the matter they change moves this step
+            if self.num_emitters > 0 {
+                self.dispatch(&mut builder, self.emit_pipeline.clone(), false, false);
+            }
+
+            // Tiles where matter changed since the last step & their neighbors wake up
+            self.wake_tiles(&mut builder);
+
+            // Movement
+            // ------
+            self.move_once(&mut builder, 0);
+            self.disperse(
+                &mut builder,
+                (self.sim_steps % 2 == 0) as u32,
+                settings.dispersion_steps,
+            );
+            if settings.movement_steps > 1 {
+                self.move_once(&mut builder, 1);
+            }
+            if settings.movement_steps > 2 {
+                self.move_once(&mut builder, 2);
+            }
+            self.disperse(
+                &mut builder,
+                (self.sim_steps % 2 != 0) as u32,
+                settings.dispersion_steps,
+            );
+            // ------
+
+            // React
+            self.dispatch(&mut builder, self.react_pipeline.clone(), false, true);
+
+            // Heat flow & melting, boiling, freezing
+            self.dispatch(&mut builder, self.heat_pipeline.clone(), false, true);
+
+            // Movement kernels skip sleeping tiles, whose output must equal their input
+            self.copy_input_to_output(&mut builder);
+
+            // Paused steps don't count, seeds follow the steps actually simulated
+            self.sim_steps += 1;
+        }
+
+        // Finally color the image
+        self.dispatch(&mut builder, self.color_pipeline.clone(), false, false);
+
+        // Execute & finish (no need to wait)
+        self.execute(builder, false);
     }
 
     /// Draw matter line with given radius
-    pub fn draw_matter(
-        &mut self,
-        start: Vec2,
-        end: Vec2,
-        matter: u32,
-        radius: f32,
-        is_square: bool,
-    ) {
+    fn draw_matter(&mut self, start: Vec2, end: Vec2, matter: u32, radius: f32, is_square: bool) {
         // Update our variables to be used as push constants
         self.draw_pos_start = start;
         self.draw_pos_end = end;
         self.draw_radius = radius;
-        self.draw_matter = MatterWithColor::new(
-            matter,
-            crate::utils::u32_rgba_to_u8_rgba(
-                self.matter_definitions.definitions[matter as usize].color,
-            ),
-        );
+        // Each cell gets its own color variation when drawn
+        self.draw_matter = MatterCell::new(matter, 0);
 
         // Build command buffer
         let mut command_buffer_builder = self.command_buffer_builder();
@@ -372,51 +744,282 @@ impl CASimulator {
         self.execute(command_buffer_builder, false);
     }
 
-    /// Query matter at pos
-    pub fn query_matter(&mut self, pos: IVec2) -> Option<u32> {
-        if is_inside_sim_canvas(pos) {
-            self.query_pos = pos;
-            // Build command buffer
-            let mut command_buffer_builder = self.command_buffer_builder();
+    fn request_query(&mut self, pos: IVec2) -> Result<()> {
+        if !is_inside_sim_canvas(pos, self.canvas_size) {
+            return Ok(());
+        }
+        let Some(readback) = self.query_readback.free_buffer() else { return Ok(()) };
+        self.query_pos = pos;
+        // Build command buffer
+        let mut command_buffer_builder = self.command_buffer_builder();
+
+        // Dispatch & copy the result to the host
+        self.dispatch(
+            &mut command_buffer_builder,
+            self.query_matter_pipeline.clone(),
+            false,
+            false,
+        );
+        command_buffer_builder
+            .copy_buffer(CopyBufferInfo::buffers(self.query_matter.clone(), readback))?;
 
-            // Dispatch
-            self.dispatch(
-                &mut command_buffer_builder,
-                self.query_matter_pipeline.clone(),
-                false,
-                false,
-            );
+        // Execute (no need to wait), the result is polled later
+        let fence = self.submit(command_buffer_builder);
+        self.query_readback.submitted(fence, pos);
+        Ok(())
+    }
 
-            // Execute & finish (wait)
-            self.execute(command_buffer_builder, true);
+    fn poll_query(&mut self) -> Result<Option<(IVec2, u32)>> {
+        Ok(self
+            .query_readback
+            .poll_latest()?
+            .map(|(pos, query_matter)| (pos, MatterCell::from(query_matter[0]).matter_id())))
+    }
+
+    fn read_matter_region(&mut self, min: UVec2, size: UVec2) -> Result<Vec<u32>> {
+        self.finish_readbacks()?;
+        let matter_in = self.matter_in.read()?;
+        Ok(read_region(&matter_in, self.canvas_size, min, size))
+    }
+
+    fn request_matter_grid(&mut self, canvas_origin: IVec2) -> Result<()> {
+        let Some(readback) = self.grid_readback.free_buffer() else { return Ok(()) };
+        let mut command_buffer_builder = self.command_buffer_builder();
+        command_buffer_builder
+            .copy_buffer(CopyBufferInfo::buffers(self.matter_in.clone(), readback))?;
+
+        // Execute (no need to wait), the grid is polled later
+        let fence = self.submit(command_buffer_builder);
+        self.grid_readback.submitted(fence, GridCopyInfo {
+            sim_steps: self.sim_steps,
+            canvas_size: self.canvas_size,
+            canvas_origin,
+        });
+        Ok(())
+    }
+
+    fn poll_matter_grid(&mut self) -> Result<Option<(GridCopyInfo, Vec<u32>)>> {
+        self.grid_readback.poll_latest()
+    }
+
+    fn request_census(&mut self) -> Result<()> {
+        let Some(readback) = self.census_readback.free_buffer() else { return Ok(()) };
+        let mut command_buffer_builder = self.command_buffer_builder();
+        command_buffer_builder.fill_buffer(FillBufferInfo::dst_buffer(self.census.clone()))?;
+        self.dispatch(
+            &mut command_buffer_builder,
+            self.census_pipeline.clone(),
+            false,
+            false,
+        );
+        command_buffer_builder
+            .copy_buffer(CopyBufferInfo::buffers(self.census.clone(), readback))?;
+
+        // Execute (no need to wait), the counts are polled later
+        let fence = self.submit(command_buffer_builder);
+        self.census_readback.submitted(fence, self.sim_steps);
+        Ok(())
+    }
 
-            // Read result
-            let query_matter = self.query_matter.read().unwrap();
-            Some(MatterWithColor::from(query_matter[0]).matter_id())
-        } else {
-            None
+    fn poll_census(&mut self) -> Result<Option<(u32, Vec<u32>)>> {
+        self.census_readback.poll_latest()
+    }
+
+    fn write_matter_region(&mut self, min: UVec2, size: UVec2, cells: &[u32]) -> Result<()> {
+        self.finish_readbacks()?;
+        let mut matter_in = self.matter_in.write()?;
+        let mut temperature_in = self.temperature_in.write()?;
+        let mut cell_data_in = self.cell_data_in.write()?;
+        let mut tile_activity = self.tile_activity.write()?;
+        let indices = region_rows(self.canvas_size, min, size).flatten();
+        for (index, &matter) in indices.zip(cells) {
+            let matter_id = MatterCell::from(matter).matter_id();
+            if MatterCell::from(matter_in[index]).matter_id() != matter_id {
+                temperature_in[index] = self.matter_definitions.temperature(matter_id);
+                cell_data_in[index] = self.matter_definitions.cell_data(matter_id).value;
+            }
+            if matter_in[index] != matter {
+                tile_activity[tile_index(index, self.canvas_size)] = 1;
+            }
+            matter_in[index] = matter;
         }
+        Ok(())
     }
 
-    pub(crate) fn update_matter_data(
-        &mut self,
-        matter_definitions: &MatterDefinitions,
-    ) -> Result<()> {
+    fn read_cells(&mut self, min: UVec2, size: UVec2) -> Result<CellGrids> {
+        self.finish_readbacks()?;
+        Ok(CellGrids {
+            matter: read_region(&self.matter_in.read()?, self.canvas_size, min, size),
+            temperature: read_region(&self.temperature_in.read()?, self.canvas_size, min, size),
+            cell_data: read_region(&self.cell_data_in.read()?, self.canvas_size, min, size),
+        })
+    }
+
+    fn write_cells(&mut self, min: UVec2, size: UVec2, cells: &CellGrids) -> Result<()> {
+        self.finish_readbacks()?;
+        let canvas_size = self.canvas_size;
+        write_region(
+            &mut self.matter_in.write()?,
+            canvas_size,
+            min,
+            size,
+            &cells.matter,
+        );
+        write_region(
+            &mut self.temperature_in.write()?,
+            canvas_size,
+            min,
+            size,
+            &cells.temperature,
+        );
+        write_region(
+            &mut self.cell_data_in.write()?,
+            canvas_size,
+            min,
+            size,
+            &cells.cell_data,
+        );
+        let mut tile_activity = self.tile_activity.write()?;
+        for index in region_rows(canvas_size, min, size).flatten() {
+            tile_activity[tile_index(index, canvas_size)] = 1;
+        }
+        Ok(())
+    }
+
+    /// Get canvas image for rendering
+    fn color_image(&self) -> Option<DeviceImageView> {
+        Some(self.image.clone())
+    }
+
+    fn read_color_image(&self) -> Result<Vec<u8>> {
+        let buffer = empty_download_u8(
+            &self.memory_allocator,
+            (self.canvas_size.x * self.canvas_size.y * 4) as usize,
+        )?;
+
+        let mut command_buffer_builder = self.command_buffer_builder();
+        command_buffer_builder.copy_image_to_buffer(CopyImageToBufferInfo::image_buffer(
+            self.image.image().clone(),
+            buffer.clone(),
+        ))?;
+
+        // Execute & finish (wait)
+        self.execute(command_buffer_builder, true);
+
+        let colors = buffer.read()?.to_vec();
+        Ok(colors)
+    }
+
+    fn update_matter_data(&mut self, matter_definitions: &MatterDefinitions) -> Result<()> {
+        self.finish_readbacks()?;
+        if matter_definitions.empty != self.empty_matter {
+            self.recreate_pipelines(matter_definitions.empty, self.canvas_size)?;
+        }
         let mut write_matter_state_input = self.matter_state_input.write()?;
         let mut write_matter_weight_input = self.matter_weight_input.write()?;
         let mut write_matter_dispersion_input = self.matter_dispersion_input.write()?;
+        let mut write_matter_characteristics_input = self.matter_characteristics_input.write()?;
+        let mut write_matter_color_input = self.matter_color_input.write()?;
+        let mut write_reacts_input = self.matter_reaction_reacts_input.write()?;
+        let mut write_direction_input = self.matter_reaction_direction_input.write()?;
+        let mut write_probability_input = self.matter_reaction_probability_input.write()?;
+        let mut write_becomes_input = self.matter_reaction_becomes_input.write()?;
+        let mut write_conductivity_input = self.matter_conductivity_input.write()?;
+        let mut write_heat_capacity_input = self.matter_heat_capacity_input.write()?;
+        let mut write_temperature_input = self.matter_temperature_input.write()?;
+        let mut write_transition_point_input = self.matter_transition_point_input.write()?;
+        let mut write_transition_becomes_input = self.matter_transition_becomes_input.write()?;
+        let mut write_lifetime_input = self.matter_lifetime_input.write()?;
 
         matter_definitions.definitions.iter().for_each(|def| {
             write_matter_state_input[def.id as usize] = def.state as u32;
             write_matter_weight_input[def.id as usize] = def.weight;
             write_matter_dispersion_input[def.id as usize] = def.dispersion;
+            write_matter_characteristics_input[def.id as usize] = def.characteristics.bits();
+            // Without alpha, varied per cell when colored
+            write_matter_color_input[def.id as usize] = def.color >> 8;
+
+            for (i, reaction) in def.reactions.iter().enumerate() {
+                let index = (def.id * MAX_TRANSITIONS) as usize + i;
+                write_reacts_input[index] = reaction.reacts.bits();
+                write_direction_input[index] = reaction.direction.bits();
+                write_probability_input[index] = reaction.probability;
+                write_becomes_input[index] = reaction.becomes;
+            }
+
+            // Clamped to where heat diffusion stays stable
+            write_conductivity_input[def.id as usize] = def.conductivity.clamp(0.0, 1.0);
+            write_heat_capacity_input[def.id as usize] = def.heat_capacity.max(1.0);
+            write_temperature_input[def.id as usize] = def.temperature;
+            for (i, (temperature, becomes)) in def.temperature_transitions().into_iter().enumerate()
+            {
+                let index = (def.id * NUM_TEMPERATURE_TRANSITIONS) as usize + i;
+                write_transition_point_input[index] = temperature;
+                write_transition_becomes_input[index] = becomes;
+            }
+            write_lifetime_input[def.id as usize] = def.lifetime.min(MAX_LIFETIME);
         });
 
+        // Settled matter may move with the new definitions
+        self.tile_activity.write()?.fill(1);
+
         self.empty_matter = matter_definitions.empty;
         self.matter_definitions = matter_definitions.clone();
 
         Ok(())
     }
+
+    fn set_emitters(&mut self, emitters: &[u32]) -> Result<()> {
+        ensure!(
+            emitters.len() <= MAX_EMITTERS * EMITTER_STRIDE,
+            "More than {} emitters",
+            MAX_EMITTERS
+        );
+        self.finish_readbacks()?;
+        self.emitters.write()?[..emitters.len()].copy_from_slice(emitters);
+        self.num_emitters = (emitters.len() / EMITTER_STRIDE) as u32;
+        Ok(())
+    }
+
+    fn sim_steps(&self) -> u32 {
+        self.sim_steps
+    }
+
+    fn set_sim_steps(&mut self, sim_steps: u32) {
+        self.sim_steps = sim_steps;
+    }
+
+    fn canvas_size(&self) -> UVec2 {
+        self.canvas_size
+    }
+
+    /// Size is a specialization constant, so pipelines are recreated along with the buffers
+    fn resize(&mut self, canvas_size: UVec2) -> Result<()> {
+        Canvas {
+            image: self.image,
+            matter_in: self.matter_in,
+            matter_out: self.matter_out,
+            temperature_in: self.temperature_in,
+            temperature_out: self.temperature_out,
+            cell_data_in: self.cell_data_in,
+            cell_data_out: self.cell_data_out,
+            tile_activity: self.tile_activity,
+            tile_awake: self.tile_awake,
+            awake_tiles: self.awake_tiles,
+            awake_tile_dispatch: self.awake_tile_dispatch,
+        } = CASimulator::create_canvas(
+            &self.memory_allocator,
+            &self.compute_queue,
+            canvas_size,
+            self.matter_definitions.temperature(self.empty_matter),
+            self.matter_definitions.cell_data(self.empty_matter),
+        )?;
+        self.recreate_pipelines(self.empty_matter, canvas_size)?;
+        self.grid_readback =
+            ReadbackRing::new(&self.memory_allocator, 1, self.matter_in.len() as usize)?;
+        self.canvas_size = canvas_size;
+        Ok(())
+    }
 }
 
 // Simulation
@@ -435,52 +1038,29 @@ impl CASimulator {
         command_buffer_builder: AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
         wait: bool,
     ) {
-        let command_buffer = command_buffer_builder.build().unwrap();
-        let finished = command_buffer.execute(self.compute_queue.clone()).unwrap();
-        let future = finished.then_signal_fence_and_flush().unwrap();
+        let future = self.submit(command_buffer_builder);
         if wait {
             future.wait(None).unwrap();
         }
     }
 
-    /// Step simulation
-    pub fn step(&mut self, settings: &AppSettings) {
-        self.seed = (Instant::now() - self.start).as_secs_f32();
-
-        let mut builder = self.command_buffer_builder();
-
-        if !settings.is_paused {
-            // Movement
-            // ------
-            self.move_once(&mut builder, 0);
-            self.disperse(
-                &mut builder,
-                (self.sim_steps % 2 == 0) as u32,
-                settings.dispersion_steps,
-            );
-            if settings.movement_steps > 1 {
-                self.move_once(&mut builder, 1);
-            }
-            if settings.movement_steps > 2 {
-                self.move_once(&mut builder, 2);
-            }
-            self.disperse(
-                &mut builder,
-                (self.sim_steps % 2 != 0) as u32,
-                settings.dispersion_steps,
-            );
-            // ------
-
-            // React
-            // self.dispatch(&mut builder, self.react_pipeline.clone(), true)?;
-        }
-
-        // Finally color the image
-        self.dispatch(&mut builder, self.color_pipeline.clone(), false, false);
+    /// Execute without waiting, the device is done with the command buffer once the fence is
+    /// signaled
+    fn submit(
+        &self,
+        command_buffer_builder: AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    ) -> SubmitFence {
+        let command_buffer = command_buffer_builder.build().unwrap();
+        let finished = command_buffer.execute(self.compute_queue.clone()).unwrap();
+        finished.then_signal_fence_and_flush().unwrap()
+    }
 
-        // Execute & finish (no need to wait)
-        self.execute(builder, false);
-        self.sim_steps += 1;
+    /// Readbacks in flight keep the buffers they read from in use, finish them before the host
+    /// reads or writes those buffers
+    fn finish_readbacks(&mut self) -> Result<()> {
+        self.query_readback.finish()?;
+        self.grid_readback.finish()?;
+        self.census_readback.finish()
     }
 
     /// Step a movement pipeline. move_step affects the order of sliding direction
@@ -492,16 +1072,16 @@ impl CASimulator {
         self.move_step = step;
 
         // Anything that falls
-        self.dispatch(builder, self.fall_empty_pipeline.clone(), false, true);
-        self.dispatch(builder, self.fall_swap_pipeline.clone(), false, true);
+        self.dispatch_awake(builder, self.fall_empty_pipeline.clone());
+        self.dispatch_awake(builder, self.fall_swap_pipeline.clone());
 
         // Risers
-        self.dispatch(builder, self.rise_empty_pipeline.clone(), false, true);
-        self.dispatch(builder, self.rise_swap_pipeline.clone(), false, true);
+        self.dispatch_awake(builder, self.rise_empty_pipeline.clone());
+        self.dispatch_awake(builder, self.rise_swap_pipeline.clone());
 
         // Sliders
-        self.dispatch(builder, self.slide_down_empty_pipeline.clone(), false, true);
-        self.dispatch(builder, self.slide_down_swap_pipeline.clone(), false, true);
+        self.dispatch_awake(builder, self.slide_down_empty_pipeline.clone());
+        self.dispatch_awake(builder, self.slide_down_swap_pipeline.clone());
     }
 
     fn disperse(
@@ -513,18 +1093,127 @@ impl CASimulator {
         self.dispersion_dir = direction;
         for dispersion_step in 0..dispersion_steps {
             self.dispersion_step = dispersion_step;
-            self.dispatch(builder, self.horizontal_empty_pipeline.clone(), false, true);
-            self.dispatch(builder, self.horizontal_swap_pipeline.clone(), false, true);
+            self.dispatch_awake(builder, self.horizontal_empty_pipeline.clone());
+            self.dispatch_awake(builder, self.horizontal_swap_pipeline.clone());
         }
     }
 
-    /// Append a pipeline dispatch to our command buffer
+    /// List the awake tiles & their count for the indirect dispatch of the movement kernels, then
+    /// start collecting activity for the next step
+    fn wake_tiles(&self, builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>) {
+        builder
+            .copy_buffer(CopyBufferInfo::buffers(
+                self.no_awake_tiles.clone(),
+                self.awake_tile_dispatch.clone(),
+            ))
+            .unwrap();
+
+        // One thread per tile
+        let num_tiles = self.canvas_size / KERNEL_SIZE;
+        self.bind(builder, self.tiles_pipeline.clone(), false);
+        builder
+            .dispatch([
+                (num_tiles.x + KERNEL_SIZE - 1) / KERNEL_SIZE,
+                (num_tiles.y + KERNEL_SIZE - 1) / KERNEL_SIZE,
+                1,
+            ])
+            .unwrap();
+
+        builder
+            .fill_buffer(FillBufferInfo::dst_buffer(self.tile_activity.clone()))
+            .unwrap();
+    }
+
+    fn copy_input_to_output(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    ) {
+        builder
+            .copy_buffer(CopyBufferInfo::buffers(
+                self.matter_in.clone(),
+                self.matter_out.clone(),
+            ))
+            .unwrap()
+            .copy_buffer(CopyBufferInfo::buffers(
+                self.temperature_in.clone(),
+                self.temperature_out.clone(),
+            ))
+            .unwrap()
+            .copy_buffer(CopyBufferInfo::buffers(
+                self.cell_data_in.clone(),
+                self.cell_data_out.clone(),
+            ))
+            .unwrap();
+    }
+
+    /// Push constants for the next dispatch. The cpu simulator builds the exact same values.
+    fn push_constants(&self, is_square: bool) -> PushConstants {
+        PushConstants {
+            is_square,
+            seed: self.seed,
+            sim_steps: self.sim_steps,
+            move_step: self.move_step,
+            draw_radius: self.draw_radius,
+            query_pos: self.query_pos,
+            draw_matter: self.draw_matter.value,
+            dispersion_dir: self.dispersion_dir,
+            dispersion_step: self.dispersion_step,
+            draw_pos_end: self.draw_pos_end,
+            draw_pos_start: self.draw_pos_start,
+            show_awake_tiles: self.show_awake_tiles,
+            boundaries: self.boundaries.to_push_constants(),
+            num_emitters: self.num_emitters,
+        }
+    }
+
+    /// Append a pipeline dispatch over the whole canvas to our command buffer
     fn dispatch(
         &mut self,
         builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
         pipeline: Arc<ComputePipeline>,
         is_square: bool,
         swap: bool,
+    ) {
+        self.bind(builder, pipeline, is_square);
+        builder
+            .dispatch([
+                self.canvas_size.x / KERNEL_SIZE,
+                self.canvas_size.y / KERNEL_SIZE,
+                1,
+            ])
+            .unwrap();
+
+        if swap {
+            self.swap_buffers();
+        }
+    }
+
+    /// Append a movement pipeline dispatch over the awake tiles to our command buffer
+    fn dispatch_awake(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        pipeline: Arc<ComputePipeline>,
+    ) {
+        self.bind(builder, pipeline, false);
+        builder
+            .dispatch_indirect(self.awake_tile_dispatch.clone())
+            .unwrap();
+        self.swap_buffers();
+    }
+
+    /// Double buffering: Swap input and output so the output becomes the input for next frame
+    fn swap_buffers(&mut self) {
+        std::mem::swap(&mut self.matter_in, &mut self.matter_out);
+        std::mem::swap(&mut self.temperature_in, &mut self.temperature_out);
+        std::mem::swap(&mut self.cell_data_in, &mut self.cell_data_out);
+    }
+
+    /// Bind a pipeline with our buffers & push constants for the next dispatch
+    fn bind(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        pipeline: Arc<ComputePipeline>,
+        is_square: bool,
     ) {
         let pipeline_layout = pipeline.layout();
         let desc_layout = pipeline_layout.set_layouts().get(0).unwrap();
@@ -539,34 +1228,58 @@ impl CASimulator {
                 WriteDescriptorSet::buffer(4, self.matter_out.clone()),
                 WriteDescriptorSet::buffer(5, self.query_matter.clone()),
                 WriteDescriptorSet::image_view(6, self.image.clone()),
+                WriteDescriptorSet::buffer(7, self.matter_characteristics_input.clone()),
+                WriteDescriptorSet::buffer(8, self.matter_reaction_reacts_input.clone()),
+                WriteDescriptorSet::buffer(9, self.matter_reaction_direction_input.clone()),
+                WriteDescriptorSet::buffer(10, self.matter_reaction_probability_input.clone()),
+                WriteDescriptorSet::buffer(11, self.matter_reaction_becomes_input.clone()),
+                WriteDescriptorSet::buffer(12, self.matter_color_input.clone()),
+                WriteDescriptorSet::buffer(13, self.temperature_in.clone()),
+                WriteDescriptorSet::buffer(14, self.temperature_out.clone()),
+                WriteDescriptorSet::buffer(15, self.matter_conductivity_input.clone()),
+                WriteDescriptorSet::buffer(16, self.matter_heat_capacity_input.clone()),
+                WriteDescriptorSet::buffer(17, self.matter_temperature_input.clone()),
+                WriteDescriptorSet::buffer(18, self.matter_transition_point_input.clone()),
+                WriteDescriptorSet::buffer(19, self.matter_transition_becomes_input.clone()),
+                WriteDescriptorSet::buffer(20, self.cell_data_in.clone()),
+                WriteDescriptorSet::buffer(21, self.cell_data_out.clone()),
+                WriteDescriptorSet::buffer(22, self.matter_lifetime_input.clone()),
+                WriteDescriptorSet::buffer(23, self.tile_activity.clone()),
+                WriteDescriptorSet::buffer(24, self.tile_awake.clone()),
+                WriteDescriptorSet::buffer(25, self.awake_tiles.clone()),
+                WriteDescriptorSet::buffer(26, self.awake_tile_dispatch.clone()),
+                WriteDescriptorSet::buffer(27, self.emitters.clone()),
+                WriteDescriptorSet::buffer(28, self.census.clone()),
             ],
         )
         .unwrap();
 
-        let push_constants = fall_empty_cs::PushConstants {
-            is_square: is_square as u32,
-            seed: self.seed,
-            sim_steps: self.sim_steps,
-            move_step: self.move_step,
-            draw_radius: self.draw_radius,
-            query_pos: self.query_pos.into(),
-            draw_matter: self.draw_matter.value,
-            dispersion_dir: self.dispersion_dir,
-            dispersion_step: self.dispersion_step,
-            draw_pos_end: self.draw_pos_end.into(),
-            draw_pos_start: self.draw_pos_start.into(),
-        };
+        let push_constants: fall_empty_cs::PushConstants = self.push_constants(is_square).into();
 
         builder
             .bind_pipeline_compute(pipeline.clone())
             .bind_descriptor_sets(PipelineBindPoint::Compute, pipeline_layout.clone(), 0, set)
-            .push_constants(pipeline_layout.clone(), 0, push_constants)
-            .dispatch([NUM_WORK_GROUPS, NUM_WORK_GROUPS, 1])
-            .unwrap();
+            .push_constants(pipeline_layout.clone(), 0, push_constants);
+    }
+}
 
-        // Double buffering: Swap input and output so the output becomes the input for next frame
-        if swap {
-            std::mem::swap(&mut self.matter_in, &mut self.matter_out);
+impl From<PushConstants> for fall_empty_cs::PushConstants {
+    fn from(push_constants: PushConstants) -> Self {
+        fall_empty_cs::PushConstants {
+            is_square: push_constants.is_square as u32,
+            seed: push_constants.seed,
+            sim_steps: push_constants.sim_steps,
+            move_step: push_constants.move_step,
+            draw_radius: push_constants.draw_radius,
+            query_pos: push_constants.query_pos.into(),
+            draw_matter: push_constants.draw_matter,
+            dispersion_dir: push_constants.dispersion_dir,
+            dispersion_step: push_constants.dispersion_step,
+            draw_pos_end: push_constants.draw_pos_end.into(),
+            draw_pos_start: push_constants.draw_pos_start.into(),
+            show_awake_tiles: push_constants.show_awake_tiles as u32,
+            boundaries: push_constants.boundaries,
+            num_emitters: push_constants.num_emitters,
         }
     }
 }
@@ -631,6 +1344,44 @@ mod rise_swap_cs {
 }
 
 // React
+mod react_cs {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        path: "compute_shaders/react.glsl"
+    }
+}
+
+// Heat
+mod heat_cs {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        path: "compute_shaders/heat.glsl"
+    }
+}
+
+// Tiles
+mod tiles_cs {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        path: "compute_shaders/tiles.glsl"
+    }
+}
+
+mod emit_cs {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        path: "compute_shaders/emit.glsl"
+    }
+}
+
+// Statistics
+mod census_cs {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        path: "compute_shaders/census.glsl"
+    }
+}
+
 // Render
 mod color_cs {
     vulkano_shaders::shader! {
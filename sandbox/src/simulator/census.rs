@@ -0,0 +1,123 @@
+use std::{collections::VecDeque, io::Write};
+
+use anyhow::Result;
+use bevy::prelude::*;
+use bevy_mod_sysfail::macros::*;
+
+use super::simulation::Simulation;
+use crate::matter::matter_definition::MatterDefinitions;
+
+/// Count the cells of each matter every this many steps by default
+pub const CENSUS_EVERY_STEPS: u32 = 10;
+/// Samples kept, older ones are dropped
+const MAX_CENSUS_SAMPLES: usize = 3600;
+
+/// Cells of each matter at a step
+pub struct CensusSample {
+    pub sim_steps: u32,
+    /// Indexed by matter id
+    pub counts: Vec<u32>,
+}
+
+/// Time series of matter counts, taken on the device every `every_steps` steps. Used to check
+/// mass conservation & balance reaction rates.
+#[derive(Resource)]
+pub struct MatterCensus {
+    /// Steps between counts
+    pub every_steps: u32,
+    samples: VecDeque<CensusSample>,
+    /// Step count of the last requested census
+    last_request: Option<u32>,
+}
+
+impl Default for MatterCensus {
+    fn default() -> Self {
+        MatterCensus {
+            every_steps: CENSUS_EVERY_STEPS,
+            samples: VecDeque::new(),
+            last_request: None,
+        }
+    }
+}
+
+impl MatterCensus {
+    /// Oldest first
+    pub fn samples(&self) -> &VecDeque<CensusSample> {
+        &self.samples
+    }
+
+    pub fn latest(&self) -> Option<&CensusSample> {
+        self.samples.back()
+    }
+
+    pub fn clear(&mut self) {
+        self.samples.clear();
+        self.last_request = None;
+    }
+
+    /// Is a census due at `sim_steps`
+    fn is_due(&self, sim_steps: u32) -> bool {
+        self.last_request.map_or(true, |last| {
+            // The step count restarted, e.g. after loading a snapshot
+            sim_steps < last || sim_steps - last >= self.every_steps.max(1)
+        })
+    }
+
+    fn push(&mut self, sample: CensusSample) {
+        if let Some(latest) = self.samples.back() {
+            if sample.sim_steps < latest.sim_steps {
+                self.samples.clear();
+            } else if sample.sim_steps == latest.sim_steps {
+                self.samples.pop_back();
+            }
+        }
+        if self.samples.len() >= MAX_CENSUS_SAMPLES {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(sample);
+    }
+
+    /// One row per sample, the step count followed by the count of each matter
+    pub fn write_csv(
+        &self,
+        writer: &mut impl Write,
+        matter_definitions: &MatterDefinitions,
+    ) -> Result<()> {
+        write!(writer, "step")?;
+        for definition in &matter_definitions.definitions {
+            write!(writer, ",{}", definition.name)?;
+        }
+        writeln!(writer)?;
+        for sample in &self.samples {
+            write!(writer, "{}", sample.sim_steps)?;
+            for id in 0..matter_definitions.definitions.len() {
+                write!(writer, ",{}", sample.counts.get(id).copied().unwrap_or(0))?;
+            }
+            writeln!(writer)?;
+        }
+        Ok(())
+    }
+}
+
+/// Take the latest finished census & request the next one when due
+#[sysfail(log(level = "error"))]
+pub(super) fn take_census(
+    mut census: ResMut<MatterCensus>,
+    mut simulation: ResMut<Simulation>,
+    matter_definitions: Res<MatterDefinitions>,
+) -> Result<()> {
+    if let Some((sim_steps, mut counts)) = simulation.poll_census()? {
+        counts.truncate(matter_definitions.definitions.len());
+        census.push(CensusSample {
+            sim_steps,
+            counts,
+        });
+    }
+
+    let sim_steps = simulation.sim_steps();
+    if census.is_due(sim_steps) {
+        simulation.request_census()?;
+        census.last_request = Some(sim_steps);
+    }
+    Ok(())
+}
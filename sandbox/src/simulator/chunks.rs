@@ -0,0 +1,207 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    path::PathBuf,
+};
+
+use anyhow::{ensure, Result};
+use bevy::prelude::*;
+
+use super::backend::CellGrids;
+use crate::{
+    fs_interaction::chunk_file::ChunkFile,
+    matter::{matter_definition::MatterDefinitions, MatterCell},
+};
+
+/// Chunk width & height in cells, a multiple of `KERNEL_SIZE`
+pub const CHUNK_SIZE: u32 = 64;
+/// Where chunks that don't fit in memory are paged out to
+pub const CHUNK_DIR: &str = "saves/chunks";
+/// Marks a directory as one a chunk store pages out to, it's only ever cleared if it has one
+const CHUNK_DIR_MARKER: &str = ".chunk_store";
+/// Paged out chunks kept in memory before the oldest go to disk, 48 kb each
+pub const MAX_CHUNKS_IN_MEMORY: usize = 1024;
+
+/// Chunk a world cell falls in
+pub fn chunk_pos(world_cell: IVec2) -> IVec2 {
+    let chunk_size = CHUNK_SIZE as i32;
+    IVec2::new(
+        world_cell.x.div_euclid(chunk_size),
+        world_cell.y.div_euclid(chunk_size),
+    )
+}
+
+/// Round a canvas size up to whole chunks
+pub fn round_to_chunks(canvas_size: UVec2) -> UVec2 {
+    let round = |size: u32| (size.max(1) + CHUNK_SIZE - 1) / CHUNK_SIZE * CHUNK_SIZE;
+    UVec2::new(round(canvas_size.x), round(canvas_size.y))
+}
+
+/// Chunks of the world outside the simulated canvas, with the matter, temperature & cell data of
+/// their cells. Chunks of only empty matter aren't stored at all, they come back at the empty
+/// matter's temperature. The most recently paged out chunks stay in memory, older ones go to `dir`.
+pub struct ChunkStore {
+    dir: PathBuf,
+    in_memory: HashMap<IVec2, CellGrids>,
+    /// Chunks in memory, oldest first
+    memory_order: VecDeque<IVec2>,
+    on_disk: HashSet<IVec2>,
+}
+
+impl ChunkStore {
+    /// Chunk files left in `dir` belong to an earlier session (and its matter ids), so they're
+    /// removed. Only a new or empty directory is taken, or one marked by an earlier chunk store,
+    /// and nothing but its chunk files is ever removed.
+    pub fn new(dir: impl Into<PathBuf>) -> Result<ChunkStore> {
+        let dir = dir.into();
+        let marker = dir.join(CHUNK_DIR_MARKER);
+        if dir.exists() {
+            ensure!(
+                marker.exists() || std::fs::read_dir(&dir)?.next().is_none(),
+                "{:?} is not a chunk directory, refusing to page chunks out to it",
+                dir
+            );
+            for entry in std::fs::read_dir(&dir)? {
+                let path = entry?.path();
+                if path.extension().map_or(false, |ext| ext == "chunk") && path.is_file() {
+                    std::fs::remove_file(path)?;
+                }
+            }
+        } else {
+            std::fs::create_dir_all(&dir)?;
+        }
+        std::fs::write(marker, "")?;
+        Ok(ChunkStore {
+            dir,
+            in_memory: HashMap::new(),
+            memory_order: VecDeque::new(),
+            on_disk: HashSet::new(),
+        })
+    }
+
+    pub fn num_in_memory(&self) -> usize {
+        self.in_memory.len()
+    }
+
+    pub fn num_on_disk(&self) -> usize {
+        self.on_disk.len()
+    }
+
+    /// Store a chunk leaving the canvas, paging the oldest chunks in memory out to disk
+    pub fn page_out(&mut self, chunk: IVec2, cells: CellGrids, empty_matter: u32) -> Result<()> {
+        self.remove(chunk)?;
+        if cells
+            .matter
+            .iter()
+            .all(|&cell| MatterCell::from(cell).matter_id() == empty_matter)
+        {
+            return Ok(());
+        }
+
+        self.in_memory.insert(chunk, cells);
+        self.memory_order.push_back(chunk);
+        while self.memory_order.len() > MAX_CHUNKS_IN_MEMORY {
+            let oldest = self.memory_order.pop_front().unwrap();
+            let cells = self.in_memory.remove(&oldest).unwrap();
+            ChunkFile {
+                chunk_pos: oldest.to_array(),
+                chunk_size: CHUNK_SIZE,
+                cells,
+            }
+            .save(self.chunk_path(oldest))?;
+            self.on_disk.insert(oldest);
+        }
+        Ok(())
+    }
+
+    /// Take a chunk entering the canvas, `None` if it's empty
+    pub fn page_in(&mut self, chunk: IVec2) -> Result<Option<CellGrids>> {
+        if let Some(cells) = self.in_memory.remove(&chunk) {
+            self.memory_order.retain(|&c| c != chunk);
+            return Ok(Some(cells));
+        }
+        if self.on_disk.remove(&chunk) {
+            let path = self.chunk_path(chunk);
+            let file = ChunkFile::load(&path)?;
+            std::fs::remove_file(path)?;
+            return Ok(Some(file.cells));
+        }
+        Ok(None)
+    }
+
+    /// Move the matter of every stored cell to its id in `id_map`, e.g. after matter definitions
+    /// were removed. See `CellGrids::remap_matters`.
+    pub fn remap_matters(
+        &mut self,
+        id_map: &[Option<u32>],
+        matter_definitions: &MatterDefinitions,
+    ) -> Result<()> {
+        for cells in self.in_memory.values_mut() {
+            cells.remap_matters(id_map, matter_definitions);
+        }
+        for &chunk in self.on_disk.iter() {
+            let path = self.chunk_path(chunk);
+            let mut file = ChunkFile::load(&path)?;
+            file.cells.remap_matters(id_map, matter_definitions);
+            file.save(path)?;
+        }
+        Ok(())
+    }
+
+    fn remove(&mut self, chunk: IVec2) -> Result<()> {
+        self.page_in(chunk)?;
+        Ok(())
+    }
+
+    fn chunk_path(&self, chunk: IVec2) -> PathBuf {
+        self.dir.join(chunk_file_name(chunk))
+    }
+}
+
+fn chunk_file_name(chunk: IVec2) -> String {
+    format!("{}_{}.chunk", chunk.x, chunk.y)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("{}_{}", name, std::process::id()));
+        if dir.exists() {
+            std::fs::remove_dir_all(&dir).unwrap();
+        }
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn touch(path: impl AsRef<Path>) {
+        std::fs::write(path, "").unwrap();
+    }
+
+    #[test]
+    fn new_refuses_foreign_directories() {
+        let dir = test_dir("chunk_store_foreign");
+        touch(dir.join("notes.txt"));
+        touch(dir.join("0_0.chunk"));
+
+        assert!(ChunkStore::new(&dir).is_err());
+        assert!(dir.join("notes.txt").exists());
+        assert!(dir.join("0_0.chunk").exists());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn new_removes_only_chunk_files() {
+        let dir = test_dir("chunk_store_owned");
+        ChunkStore::new(&dir).unwrap();
+        touch(dir.join("0_0.chunk"));
+        touch(dir.join("notes.txt"));
+
+        ChunkStore::new(&dir).unwrap();
+        assert!(!dir.join("0_0.chunk").exists());
+        assert!(dir.join("notes.txt").exists());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,1653 @@
+use anyhow::{ensure, Result};
+use bevy::{
+    math::{IVec2, UVec2},
+    prelude::Vec2,
+};
+use vulkano_util::renderer::DeviceImageView;
+
+use crate::{
+    matter::{
+        matter_definition::{
+            MatterDefinitions, MAX_LIFETIME, MAX_TRANSITIONS, NUM_TEMPERATURE_TRANSITIONS,
+        },
+        matter_state::MatterState,
+        CellData, MatterCell, MAX_NUM_MATTERS,
+    },
+    settings::AppSettings,
+    simulator::{
+        backend::{CellGrids, SimulationBackend},
+        boundary::Boundaries,
+        emitters::{EMITTER_STRIDE, MAX_EMITTERS},
+        grid_readback::GridCopyInfo,
+        push_constants::{step_seed, PushConstants},
+    },
+    utils::{idx, is_inside_sim_canvas, read_region, region_rows, tile_index, write_region},
+    KERNEL_SIZE,
+};
+
+/// A kernel that reads `matter_in`, `temperature_in` & `cell_data_in` and returns the matter
+/// written to `matter_out`, `temperature_out` & `cell_data_out` at `pos`
+type Kernel = fn(&KernelInput, IVec2) -> Matter;
+
+/// Cellular automata simulation on the cpu. Every kernel mirrors its glsl counterpart in
+/// `compute_shaders/` cell for cell, so this can be used to run & inspect the simulation without
+/// a gpu.
+pub struct CPUSimulator {
+    // Push constants
+    seed: f32,
+    sim_steps: u32,
+    move_step: u32,
+    draw_radius: f32,
+    query_pos: IVec2,
+    draw_pos_end: Vec2,
+    dispersion_dir: u32,
+    dispersion_step: u32,
+    draw_pos_start: Vec2,
+    draw_matter: MatterCell,
+    show_awake_tiles: bool,
+    boundaries: Boundaries,
+    num_emitters: u32,
+
+    // Specialization constants
+    empty_matter: u32,
+    canvas_size: UVec2,
+
+    // Shader matter inputs
+    image: Vec<u8>,
+    matter_out: Vec<u32>,
+    query_matter: u32,
+    pub matter_in: Vec<u32>,
+    temperature_in: Vec<f32>,
+    temperature_out: Vec<f32>,
+    cell_data_in: Vec<u32>,
+    cell_data_out: Vec<u32>,
+    matter_state_input: Vec<u32>,
+    matter_weight_input: Vec<f32>,
+    matter_dispersion_input: Vec<u32>,
+    matter_characteristics_input: Vec<u32>,
+    matter_color_input: Vec<u32>,
+    matter_reaction_reacts_input: Vec<u32>,
+    matter_reaction_direction_input: Vec<u32>,
+    matter_reaction_probability_input: Vec<f32>,
+    matter_reaction_becomes_input: Vec<u32>,
+    matter_conductivity_input: Vec<f32>,
+    matter_heat_capacity_input: Vec<f32>,
+    matter_temperature_input: Vec<f32>,
+    matter_transition_point_input: Vec<f32>,
+    matter_transition_becomes_input: Vec<u32>,
+    matter_lifetime_input: Vec<u32>,
+    emitters: Vec<u32>,
+
+    // Tiles, movement kernels only run on awake tiles
+    tile_activity: Vec<u32>,
+    tile_awake: Vec<u32>,
+    awake_tiles: Vec<u32>,
+
+    // Readbacks, ready right away on the cpu
+    query_readback: Option<(IVec2, u32)>,
+    grid_readback: Option<(GridCopyInfo, Vec<u32>)>,
+    census_readback: Option<(u32, Vec<u32>)>,
+
+    // Misc
+    matter_definitions: MatterDefinitions,
+}
+
+impl CPUSimulator {
+    pub fn new(matter_definitions: &MatterDefinitions, canvas_size: UVec2) -> CPUSimulator {
+        let num_cells = (canvas_size.x * canvas_size.y) as usize;
+        let num_reactions = (MAX_NUM_MATTERS * MAX_TRANSITIONS) as usize;
+        let num_transitions = (MAX_NUM_MATTERS * NUM_TEMPERATURE_TRANSITIONS) as usize;
+        let temperature = matter_definitions.temperature(matter_definitions.empty);
+        let cell_data = matter_definitions.cell_data(matter_definitions.empty);
+        let num_tiles = num_tiles(canvas_size);
+
+        CPUSimulator {
+            // Push constants
+            seed: 0.0,
+            sim_steps: 0,
+            move_step: 0,
+            draw_radius: 0.0,
+            dispersion_dir: 0,
+            dispersion_step: 0,
+            query_pos: IVec2::new(0, 0),
+            draw_pos_end: Vec2::new(0.0, 0.0),
+            draw_pos_start: Vec2::new(0.0, 0.0),
+            draw_matter: MatterCell::from(0),
+            show_awake_tiles: false,
+            boundaries: Boundaries::default(),
+            num_emitters: 0,
+
+            // Specialization constants
+            empty_matter: matter_definitions.empty,
+            canvas_size,
+
+            // Shader matter inputs
+            image: vec![0; num_cells * 4],
+            matter_in: vec![0; num_cells],
+            matter_out: vec![0; num_cells],
+            temperature_in: vec![temperature; num_cells],
+            temperature_out: vec![temperature; num_cells],
+            cell_data_in: vec![cell_data.value; num_cells],
+            cell_data_out: vec![cell_data.value; num_cells],
+            query_matter: MatterCell::from(0).value,
+            matter_state_input: vec![0; MAX_NUM_MATTERS as usize],
+            matter_weight_input: vec![0.0; MAX_NUM_MATTERS as usize],
+            matter_dispersion_input: vec![0; MAX_NUM_MATTERS as usize],
+            matter_characteristics_input: vec![0; MAX_NUM_MATTERS as usize],
+            matter_color_input: vec![0; MAX_NUM_MATTERS as usize],
+            matter_reaction_reacts_input: vec![0; num_reactions],
+            matter_reaction_direction_input: vec![0; num_reactions],
+            matter_reaction_probability_input: vec![0.0; num_reactions],
+            matter_reaction_becomes_input: vec![0; num_reactions],
+            matter_conductivity_input: vec![0.0; MAX_NUM_MATTERS as usize],
+            matter_heat_capacity_input: vec![0.0; MAX_NUM_MATTERS as usize],
+            matter_temperature_input: vec![0.0; MAX_NUM_MATTERS as usize],
+            matter_transition_point_input: vec![0.0; num_transitions],
+            matter_transition_becomes_input: vec![0; num_transitions],
+            matter_lifetime_input: vec![0; MAX_NUM_MATTERS as usize],
+            emitters: vec![],
+
+            // Tiles, all active so the first step moves everything
+            tile_activity: vec![1; num_tiles],
+            tile_awake: vec![0; num_tiles],
+            awake_tiles: vec![],
+
+            // Readbacks
+            query_readback: None,
+            grid_readback: None,
+            census_readback: None,
+
+            // Misc
+            matter_definitions: matter_definitions.clone(),
+        }
+    }
+}
+
+impl SimulationBackend for CPUSimulator {
+    /// Step simulation
+    fn step(&mut self, settings: &AppSettings) {
+        self.seed = step_seed(settings.seed, self.sim_steps);
+        self.show_awake_tiles = settings.show_awake_tiles;
+
+        if settings.boundaries != self.boundaries {
+            // Matter resting against an edge may move now, wake everything
+            self.boundaries = settings.boundaries;
+            self.tile_activity.fill(1);
+        }
+
+        if !settings.is_paused {
+            // Sources & sinks, before waking tiles so the matter they change moves this step
+            if self.num_emitters > 0 {
+                self.write_input(emit_kernel, false);
+            }
+
+            // Tiles where matter changed since the last step & their neighbors wake up
+            self.wake_tiles();
+
+            // Movement
+            // ------
+            self.move_once(0);
+            self.disperse((self.sim_steps % 2 == 0) as u32, settings.dispersion_steps);
+            if settings.movement_steps > 1 {
+                self.move_once(1);
+            }
+            if settings.movement_steps > 2 {
+                self.move_once(2);
+            }
+            self.disperse((self.sim_steps % 2 != 0) as u32, settings.dispersion_steps);
+            // ------
+
+            // React
+            self.dispatch(react_kernel);
+
+            // Heat flow & melting, boiling, freezing
+            self.dispatch(heat_kernel);
+
+            // Movement kernels skip sleeping tiles, whose output must equal their input
+            self.matter_out.copy_from_slice(&self.matter_in);
+            self.temperature_out.copy_from_slice(&self.temperature_in);
+            self.cell_data_out.copy_from_slice(&self.cell_data_in);
+
+            // Paused steps don't count, seeds follow the steps actually simulated
+            self.sim_steps += 1;
+        }
+
+        // Finally color the image
+        self.color();
+    }
+
+    /// Draw matter line with given radius
+    fn draw_matter(&mut self, start: Vec2, end: Vec2, matter: u32, radius: f32, is_square: bool) {
+        // Update our variables to be used as push constants
+        self.draw_pos_start = start;
+        self.draw_pos_end = end;
+        self.draw_radius = radius;
+        // Each cell gets its own color variation when drawn
+        self.draw_matter = MatterCell::new(matter, 0);
+
+        self.write_input(draw_matter_kernel, is_square);
+    }
+
+    fn request_query(&mut self, pos: IVec2) -> Result<()> {
+        if is_inside_sim_canvas(pos, self.canvas_size) {
+            self.query_pos = pos;
+
+            let push_constants = self.push_constants(false);
+            let input = self.kernel_input(&push_constants);
+            self.query_matter = query_matter_kernel(&input);
+
+            self.query_readback = Some((pos, MatterCell::from(self.query_matter).matter_id()));
+        }
+        Ok(())
+    }
+
+    fn poll_query(&mut self) -> Result<Option<(IVec2, u32)>> {
+        Ok(self.query_readback.take())
+    }
+
+    fn read_matter_region(&mut self, min: UVec2, size: UVec2) -> Result<Vec<u32>> {
+        Ok(read_region(&self.matter_in, self.canvas_size, min, size))
+    }
+
+    fn request_matter_grid(&mut self, canvas_origin: IVec2) -> Result<()> {
+        if self.grid_readback.is_none() {
+            let info = GridCopyInfo {
+                sim_steps: self.sim_steps,
+                canvas_size: self.canvas_size,
+                canvas_origin,
+            };
+            self.grid_readback = Some((info, self.matter_in.clone()));
+        }
+        Ok(())
+    }
+
+    fn poll_matter_grid(&mut self) -> Result<Option<(GridCopyInfo, Vec<u32>)>> {
+        Ok(self.grid_readback.take())
+    }
+
+    fn request_census(&mut self) -> Result<()> {
+        if self.census_readback.is_none() {
+            self.census_readback = Some((self.sim_steps, self.census()));
+        }
+        Ok(())
+    }
+
+    fn poll_census(&mut self) -> Result<Option<(u32, Vec<u32>)>> {
+        Ok(self.census_readback.take())
+    }
+
+    fn write_matter_region(&mut self, min: UVec2, size: UVec2, cells: &[u32]) -> Result<()> {
+        let indices = region_rows(self.canvas_size, min, size).flatten();
+        for (index, &matter) in indices.zip(cells) {
+            let matter_id = MatterCell::from(matter).matter_id();
+            if MatterCell::from(self.matter_in[index]).matter_id() != matter_id {
+                self.temperature_in[index] = self.matter_definitions.temperature(matter_id);
+                self.cell_data_in[index] = self.matter_definitions.cell_data(matter_id).value;
+            }
+            if self.matter_in[index] != matter {
+                self.tile_activity[tile_index(index, self.canvas_size)] = 1;
+            }
+            self.matter_in[index] = matter;
+        }
+        Ok(())
+    }
+
+    fn read_cells(&mut self, min: UVec2, size: UVec2) -> Result<CellGrids> {
+        Ok(CellGrids {
+            matter: read_region(&self.matter_in, self.canvas_size, min, size),
+            temperature: read_region(&self.temperature_in, self.canvas_size, min, size),
+            cell_data: read_region(&self.cell_data_in, self.canvas_size, min, size),
+        })
+    }
+
+    fn write_cells(&mut self, min: UVec2, size: UVec2, cells: &CellGrids) -> Result<()> {
+        let canvas_size = self.canvas_size;
+        write_region(&mut self.matter_in, canvas_size, min, size, &cells.matter);
+        write_region(
+            &mut self.temperature_in,
+            canvas_size,
+            min,
+            size,
+            &cells.temperature,
+        );
+        write_region(
+            &mut self.cell_data_in,
+            canvas_size,
+            min,
+            size,
+            &cells.cell_data,
+        );
+        for index in region_rows(canvas_size, min, size).flatten() {
+            self.tile_activity[tile_index(index, canvas_size)] = 1;
+        }
+        Ok(())
+    }
+
+    /// Colors are only on the host, the renderer uploads them once per frame
+    fn color_image(&self) -> Option<DeviceImageView> {
+        None
+    }
+
+    fn read_color_image(&self) -> Result<Vec<u8>> {
+        Ok(self.image.clone())
+    }
+
+    fn update_matter_data(&mut self, matter_definitions: &MatterDefinitions) -> Result<()> {
+        matter_definitions.definitions.iter().for_each(|def| {
+            self.matter_state_input[def.id as usize] = def.state as u32;
+            self.matter_weight_input[def.id as usize] = def.weight;
+            self.matter_dispersion_input[def.id as usize] = def.dispersion;
+            self.matter_characteristics_input[def.id as usize] = def.characteristics.bits();
+            // Without alpha, varied per cell when colored
+            self.matter_color_input[def.id as usize] = def.color >> 8;
+
+            for (i, reaction) in def.reactions.iter().enumerate() {
+                let index = (def.id * MAX_TRANSITIONS) as usize + i;
+                self.matter_reaction_reacts_input[index] = reaction.reacts.bits();
+                self.matter_reaction_direction_input[index] = reaction.direction.bits();
+                self.matter_reaction_probability_input[index] = reaction.probability;
+                self.matter_reaction_becomes_input[index] = reaction.becomes;
+            }
+
+            // Clamped to where heat diffusion stays stable
+            self.matter_conductivity_input[def.id as usize] = def.conductivity.clamp(0.0, 1.0);
+            self.matter_heat_capacity_input[def.id as usize] = def.heat_capacity.max(1.0);
+            self.matter_temperature_input[def.id as usize] = def.temperature;
+            for (i, (temperature, becomes)) in def.temperature_transitions().into_iter().enumerate()
+            {
+                let index = (def.id * NUM_TEMPERATURE_TRANSITIONS) as usize + i;
+                self.matter_transition_point_input[index] = temperature;
+                self.matter_transition_becomes_input[index] = becomes;
+            }
+            self.matter_lifetime_input[def.id as usize] = def.lifetime.min(MAX_LIFETIME);
+        });
+
+        // Settled matter may move with the new definitions
+        self.tile_activity.fill(1);
+
+        self.empty_matter = matter_definitions.empty;
+        self.matter_definitions = matter_definitions.clone();
+
+        Ok(())
+    }
+
+    fn set_emitters(&mut self, emitters: &[u32]) -> Result<()> {
+        ensure!(
+            emitters.len() <= MAX_EMITTERS * EMITTER_STRIDE,
+            "More than {} emitters",
+            MAX_EMITTERS
+        );
+        self.emitters = emitters.to_vec();
+        self.num_emitters = (emitters.len() / EMITTER_STRIDE) as u32;
+        Ok(())
+    }
+
+    fn sim_steps(&self) -> u32 {
+        self.sim_steps
+    }
+
+    fn set_sim_steps(&mut self, sim_steps: u32) {
+        self.sim_steps = sim_steps;
+    }
+
+    fn canvas_size(&self) -> UVec2 {
+        self.canvas_size
+    }
+
+    fn resize(&mut self, canvas_size: UVec2) -> Result<()> {
+        let num_cells = (canvas_size.x * canvas_size.y) as usize;
+        self.image = vec![0; num_cells * 4];
+        self.matter_in = vec![0; num_cells];
+        self.matter_out = vec![0; num_cells];
+        let temperature = self.matter_definitions.temperature(self.empty_matter);
+        self.temperature_in = vec![temperature; num_cells];
+        self.temperature_out = vec![temperature; num_cells];
+        let cell_data = self.matter_definitions.cell_data(self.empty_matter);
+        self.cell_data_in = vec![cell_data.value; num_cells];
+        self.cell_data_out = vec![cell_data.value; num_cells];
+        let num_tiles = num_tiles(canvas_size);
+        self.tile_activity = vec![1; num_tiles];
+        self.tile_awake = vec![0; num_tiles];
+        self.awake_tiles = vec![];
+        self.grid_readback = None;
+        self.canvas_size = canvas_size;
+        Ok(())
+    }
+}
+
+// Simulation
+impl CPUSimulator {
+    /// Step a movement pipeline. move_step affects the order of sliding direction
+    fn move_once(&mut self, step: u32) {
+        self.move_step = step;
+
+        // Anything that falls
+        self.dispatch_awake(fall_empty_kernel);
+        self.dispatch_awake(fall_swap_kernel);
+
+        // Risers
+        self.dispatch_awake(rise_empty_kernel);
+        self.dispatch_awake(rise_swap_kernel);
+
+        // Sliders
+        self.dispatch_awake(slide_down_empty_kernel);
+        self.dispatch_awake(slide_down_swap_kernel);
+    }
+
+    fn disperse(&mut self, direction: u32, dispersion_steps: u32) {
+        self.dispersion_dir = direction;
+        for dispersion_step in 0..dispersion_steps {
+            self.dispersion_step = dispersion_step;
+            self.dispatch_awake(horizontal_empty_kernel);
+            self.dispatch_awake(horizontal_swap_kernel);
+        }
+    }
+
+    /// tiles.glsl. List the awake tiles for the movement kernels, then start collecting activity
+    /// for the next step
+    fn wake_tiles(&mut self) {
+        let num_tiles = (self.canvas_size / KERNEL_SIZE).as_ivec2();
+        let wraps_x = self.boundaries.left.is_wrap();
+        let wraps_y = self.boundaries.bottom.is_wrap();
+        self.awake_tiles.clear();
+        for index in 0..self.tile_awake.len() {
+            let tile = IVec2::new(index as i32 % num_tiles.x, index as i32 / num_tiles.x);
+            let mut awake = 0;
+            for y in -1..=1 {
+                for x in -1..=1 {
+                    let mut neighbor = tile + IVec2::new(x, y);
+                    if wraps_x {
+                        neighbor.x = (neighbor.x + num_tiles.x) % num_tiles.x;
+                    }
+                    if wraps_y {
+                        neighbor.y = (neighbor.y + num_tiles.y) % num_tiles.y;
+                    }
+                    if is_inside_sim_canvas(neighbor, num_tiles.as_uvec2()) {
+                        awake |= self.tile_activity[idx(neighbor, num_tiles.as_uvec2())];
+                    }
+                }
+            }
+            self.tile_awake[index] = awake;
+            if awake != 0 {
+                self.awake_tiles.push(index as u32);
+            }
+        }
+        self.tile_activity.fill(0);
+    }
+
+    /// Run a kernel over the whole canvas, then swap input and output
+    fn dispatch(&mut self, kernel: Kernel) {
+        self.dispatch_cells(kernel, 0..self.matter_in.len());
+    }
+
+    /// Run a movement kernel over the awake tiles, then swap input and output
+    fn dispatch_awake(&mut self, kernel: Kernel) {
+        let num_tiles_x = self.canvas_size.x / KERNEL_SIZE;
+        let canvas_size = self.canvas_size;
+        let cells = self.awake_tiles.clone().into_iter().flat_map(move |tile| {
+            let tile_pos = UVec2::new(tile % num_tiles_x, tile / num_tiles_x) * KERNEL_SIZE;
+            (0..KERNEL_SIZE * KERNEL_SIZE).map(move |i| {
+                let pos = tile_pos + UVec2::new(i % KERNEL_SIZE, i / KERNEL_SIZE);
+                idx(pos.as_ivec2(), canvas_size)
+            })
+        });
+        self.dispatch_cells(kernel, cells);
+    }
+
+    /// Run a kernel over the given cell indices, then swap input and output. Like `write_matter`
+    /// in includes.glsl, tiles where matter changes are marked active.
+    fn dispatch_cells(&mut self, kernel: Kernel, indices: impl Iterator<Item = usize>) {
+        let push_constants = self.push_constants(false);
+        let mut matter_out = std::mem::take(&mut self.matter_out);
+        let mut temperature_out = std::mem::take(&mut self.temperature_out);
+        let mut cell_data_out = std::mem::take(&mut self.cell_data_out);
+        let mut tile_activity = std::mem::take(&mut self.tile_activity);
+        let input = self.kernel_input(&push_constants);
+        for index in indices {
+            let matter = kernel(&input, pos_from_index(index, self.canvas_size));
+            let value = matter_to_uint(matter);
+            if value != self.matter_in[index] {
+                tile_activity[tile_index(index, self.canvas_size)] = 1;
+            }
+            matter_out[index] = value;
+            temperature_out[index] = matter.temperature;
+            cell_data_out[index] = matter.data;
+        }
+        self.tile_activity = tile_activity;
+        self.matter_out = matter_out;
+        self.temperature_out = temperature_out;
+        self.cell_data_out = cell_data_out;
+
+        // Double buffering: Swap input and output so the output becomes the input for next frame
+        std::mem::swap(&mut self.matter_in, &mut self.matter_out);
+        std::mem::swap(&mut self.temperature_in, &mut self.temperature_out);
+        std::mem::swap(&mut self.cell_data_in, &mut self.cell_data_out);
+    }
+
+    /// Run a kernel that writes directly to matter in (`write_matter_input`), where it returns
+    /// matter
+    fn write_input(&mut self, kernel: fn(&KernelInput, IVec2) -> Option<Matter>, is_square: bool) {
+        let push_constants = self.push_constants(is_square);
+        let input = self.kernel_input(&push_constants);
+        let written = (0..self.matter_in.len())
+            .map(|index| kernel(&input, pos_from_index(index, self.canvas_size)))
+            .collect::<Vec<_>>();
+        for (index, matter) in written.into_iter().enumerate() {
+            if let Some(matter) = matter {
+                if matter_to_uint(matter) != self.matter_in[index] {
+                    self.tile_activity[tile_index(index, self.canvas_size)] = 1;
+                }
+                self.matter_in[index] = matter_to_uint(matter);
+                self.temperature_in[index] = matter.temperature;
+                self.cell_data_in[index] = matter.data;
+            }
+        }
+    }
+
+    /// Cells per matter id, see `census_kernel`
+    fn census(&self) -> Vec<u32> {
+        let push_constants = self.push_constants(false);
+        let input = self.kernel_input(&push_constants);
+        let mut census = vec![0; MAX_NUM_MATTERS as usize];
+        for index in 0..self.matter_in.len() {
+            census_kernel(&input, pos_from_index(index, self.canvas_size), &mut census);
+        }
+        census
+    }
+
+    /// Write matter colors to the canvas image
+    fn color(&mut self) {
+        let push_constants = self.push_constants(false);
+        let mut image = std::mem::take(&mut self.image);
+        let input = self.kernel_input(&push_constants);
+        for (index, pixel) in image.chunks_exact_mut(4).enumerate() {
+            pixel.copy_from_slice(&color_kernel(
+                &input,
+                pos_from_index(index, self.canvas_size),
+            ));
+        }
+        self.image = image;
+    }
+
+    /// Push constants for the next kernel. These are the same values `CASimulator` pushes.
+    fn push_constants(&self, is_square: bool) -> PushConstants {
+        PushConstants {
+            is_square,
+            seed: self.seed,
+            sim_steps: self.sim_steps,
+            move_step: self.move_step,
+            draw_radius: self.draw_radius,
+            query_pos: self.query_pos,
+            draw_matter: self.draw_matter.value,
+            dispersion_dir: self.dispersion_dir,
+            dispersion_step: self.dispersion_step,
+            draw_pos_end: self.draw_pos_end,
+            draw_pos_start: self.draw_pos_start,
+            show_awake_tiles: self.show_awake_tiles,
+            boundaries: self.boundaries.to_push_constants(),
+            num_emitters: self.num_emitters,
+        }
+    }
+
+    fn kernel_input<'a>(&'a self, push_constants: &'a PushConstants) -> KernelInput<'a> {
+        KernelInput {
+            matter_in: &self.matter_in,
+            temperature_in: &self.temperature_in,
+            cell_data_in: &self.cell_data_in,
+            matter_state: &self.matter_state_input,
+            matter_weights: &self.matter_weight_input,
+            matter_dispersion: &self.matter_dispersion_input,
+            matter_characteristics: &self.matter_characteristics_input,
+            matter_colors: &self.matter_color_input,
+            matter_reaction_reacts: &self.matter_reaction_reacts_input,
+            matter_reaction_direction: &self.matter_reaction_direction_input,
+            matter_reaction_probability: &self.matter_reaction_probability_input,
+            matter_reaction_becomes: &self.matter_reaction_becomes_input,
+            matter_conductivity: &self.matter_conductivity_input,
+            matter_heat_capacity: &self.matter_heat_capacity_input,
+            matter_temperature: &self.matter_temperature_input,
+            matter_transition_point: &self.matter_transition_point_input,
+            matter_transition_becomes: &self.matter_transition_becomes_input,
+            matter_lifetime: &self.matter_lifetime_input,
+            emitters: &self.emitters,
+            tile_awake: &self.tile_awake,
+            empty_matter: self.empty_matter,
+            canvas_size: self.canvas_size,
+            push_constants,
+        }
+    }
+}
+
+fn num_tiles(canvas_size: UVec2) -> usize {
+    (canvas_size.x / KERNEL_SIZE * canvas_size.y / KERNEL_SIZE) as usize
+}
+
+pub(crate) fn pos_from_index(index: usize, canvas_size: UVec2) -> IVec2 {
+    IVec2::new(
+        (index % canvas_size.x as usize) as i32,
+        (index / canvas_size.x as usize) as i32,
+    )
+}
+
+/*
+Grid Directions (helpers/dirs.glsl)
+*/
+const UP_LEFT: usize = 0;
+const UP: usize = 1;
+const UP_RIGHT: usize = 2;
+const RIGHT: usize = 3;
+const DOWN_RIGHT: usize = 4;
+const DOWN: usize = 5;
+const DOWN_LEFT: usize = 6;
+const LEFT: usize = 7;
+
+/*
+Neighbor Directions
+*/
+const OFFSETS: [IVec2; 8] = [
+    IVec2::new(-1, 1),
+    IVec2::new(0, 1),
+    IVec2::new(1, 1),
+    IVec2::new(1, 0),
+    IVec2::new(1, -1),
+    IVec2::new(0, -1),
+    IVec2::new(-1, -1),
+    IVec2::new(-1, 0),
+];
+
+/*
+Canvas edges (helpers/dirs.glsl) & boundaries (helpers/query.glsl)
+*/
+const EDGE_TOP: usize = 0;
+const EDGE_RIGHT: usize = 1;
+const EDGE_BOTTOM: usize = 2;
+const EDGE_LEFT: usize = 3;
+const BOUNDARY_WALL: u32 = 0;
+const BOUNDARY_WRAP: u32 = 1;
+const BOUNDARY_SOURCE: u32 = 3;
+
+/// Index of freezing in the temperature transitions, see `helpers/definition.glsl`
+const TRANSITION_FREEZES: u32 = 2;
+
+/// Emitter flags, see `emit.glsl`
+const EMITTER_SINK: u32 = 1;
+const EMITTER_SQUARE: u32 = 2;
+
+/// See `color.glsl`
+const AWAKE_TILE_TINT: [f32; 3] = [1.0, 0.0, 1.0];
+
+/// Golden ratio, see `helpers/rand.glsl`
+const PHI: f32 = 1.618_034;
+
+/// Matter as unpacked in `helpers/matter.glsl`
+#[derive(Debug, Copy, Clone)]
+struct Matter {
+    variation: u32,
+    state: u32,
+    matter: u32,
+    weight: f32,
+    dispersion: u32,
+    characteristics: u32,
+    temperature: f32,
+    data: u32,
+}
+
+fn matter_to_uint(matter: Matter) -> u32 {
+    (matter.variation << 16) | matter.matter
+}
+
+fn rand(xy: IVec2, seed: f32) -> f32 {
+    let pos = Vec2::new(xy.x as f32 + 0.5, xy.y as f32 + 0.5);
+    fract(((pos * PHI).distance(pos) * seed).tan() * pos.x)
+}
+
+/// Glsl `fract` (which differs from `f32::fract` for negative values)
+fn fract(x: f32) -> f32 {
+    x - x.floor()
+}
+
+fn matter_color_to_vec4(color: u32) -> [f32; 4] {
+    [
+        ((color >> 16) & 255) as f32 / 255.0,
+        ((color >> 8) & 255) as f32 / 255.0,
+        (color & 255) as f32 / 255.0,
+        1.0,
+    ]
+}
+
+/// Everything a kernel invocation can read. Matches the buffers, specialization constants and
+/// push constants bound to each compute pipeline.
+struct KernelInput<'a> {
+    matter_in: &'a [u32],
+    temperature_in: &'a [f32],
+    cell_data_in: &'a [u32],
+    matter_state: &'a [u32],
+    matter_weights: &'a [f32],
+    matter_dispersion: &'a [u32],
+    matter_characteristics: &'a [u32],
+    matter_colors: &'a [u32],
+    matter_reaction_reacts: &'a [u32],
+    matter_reaction_direction: &'a [u32],
+    matter_reaction_probability: &'a [f32],
+    matter_reaction_becomes: &'a [u32],
+    matter_conductivity: &'a [f32],
+    matter_heat_capacity: &'a [f32],
+    matter_temperature: &'a [f32],
+    matter_transition_point: &'a [f32],
+    matter_transition_becomes: &'a [u32],
+    matter_lifetime: &'a [u32],
+    emitters: &'a [u32],
+    tile_awake: &'a [u32],
+    empty_matter: u32,
+    canvas_size: UVec2,
+    push_constants: &'a PushConstants,
+}
+
+// helpers/matter.glsl & helpers/query.glsl
+impl<'a> KernelInput<'a> {
+    fn new_matter(&self, matter: u32) -> Matter {
+        let cell = MatterCell::from(matter);
+        let id = cell.matter_id();
+        Matter {
+            matter: id,
+            variation: cell.variation() as u32,
+            state: self.matter_state[id as usize],
+            weight: self.matter_weights[id as usize],
+            dispersion: self.matter_dispersion[id as usize],
+            characteristics: self.matter_characteristics[id as usize],
+            // New matter, read_matter replaces these with the temperature & data of the cell
+            temperature: self.matter_temperature[id as usize],
+            data: CellData::default()
+                .with_lifetime(self.matter_lifetime[id as usize] as u8)
+                .value,
+        }
+    }
+
+    fn read_matter(&self, pos: IVec2) -> Matter {
+        let index = idx(pos, self.canvas_size);
+        Matter {
+            temperature: self.temperature_in[index],
+            data: self.cell_data_in[index],
+            ..self.new_matter(self.matter_in[index])
+        }
+    }
+
+    /// Color of the matter from its definition, randomized a bit by the cell's variation. Empty is
+    /// not varied.
+    fn matter_color(&self, matter: Matter) -> [f32; 4] {
+        let color = matter_color_to_vec4(self.matter_colors[matter.matter as usize]);
+        if is_empty(matter) {
+            return color;
+        }
+        vary_color_rgb(color, matter.variation)
+    }
+
+    fn boundary_mode(&self, edge: usize) -> u32 {
+        self.push_constants.boundaries[edge] & 255
+    }
+
+    fn boundary_source(&self, edge: usize) -> u32 {
+        self.push_constants.boundaries[edge] >> 16
+    }
+
+    /// Edge an off-canvas position lies beyond. Beyond a corner counts as beyond the top or bottom.
+    fn edge_beyond(&self, pos: IVec2) -> usize {
+        if pos.y >= self.canvas_size.y as i32 {
+            EDGE_TOP
+        } else if pos.y < 0 {
+            EDGE_BOTTOM
+        } else if pos.x >= self.canvas_size.x as i32 {
+            EDGE_RIGHT
+        } else {
+            EDGE_LEFT
+        }
+    }
+
+    /// Bring positions beyond wrapping edges back onto the canvas
+    fn wrap_pos(&self, mut pos: IVec2) -> IVec2 {
+        let size = self.canvas_size.as_ivec2();
+        if (pos.x < 0 || pos.x >= size.x) && self.boundary_mode(EDGE_LEFT) == BOUNDARY_WRAP {
+            pos.x = pos.x.rem_euclid(size.x);
+        }
+        if (pos.y < 0 || pos.y >= size.y) && self.boundary_mode(EDGE_BOTTOM) == BOUNDARY_WRAP {
+            pos.y = pos.y.rem_euclid(size.y);
+        }
+        pos
+    }
+
+    /// Position of the neighbor in the given direction, across wrapping edges
+    fn get_pos_at_dir(&self, pos: IVec2, dir: usize) -> IVec2 {
+        self.wrap_pos(pos + OFFSETS[dir])
+    }
+
+    fn is_at_border_top(&self, pos: IVec2) -> bool {
+        pos.y == self.canvas_size.y as i32 - 1 && self.boundary_mode(EDGE_TOP) == BOUNDARY_WALL
+    }
+
+    fn is_at_border_bottom(&self, pos: IVec2) -> bool {
+        pos.y == 0 && self.boundary_mode(EDGE_BOTTOM) == BOUNDARY_WALL
+    }
+
+    fn is_at_border_right(&self, pos: IVec2) -> bool {
+        pos.x == self.canvas_size.x as i32 - 1 && self.boundary_mode(EDGE_RIGHT) == BOUNDARY_WALL
+    }
+
+    fn is_at_border_left(&self, pos: IVec2) -> bool {
+        pos.x == 0 && self.boundary_mode(EDGE_LEFT) == BOUNDARY_WALL
+    }
+
+    fn get_neighbor(&self, pos: IVec2, dir: usize) -> Matter {
+        let neighbor_pos = self.get_pos_at_dir(pos, dir);
+        if is_inside_sim_canvas(neighbor_pos, self.canvas_size) {
+            return self.read_matter(neighbor_pos);
+        }
+
+        let edge = self.edge_beyond(neighbor_pos);
+        if self.boundary_mode(edge) == BOUNDARY_SOURCE {
+            return Matter {
+                variation: variation_at(neighbor_pos) as u32,
+                ..self.new_matter(self.boundary_source(edge))
+            };
+        }
+        self.new_matter(self.empty_matter)
+    }
+
+    /*
+    MATTER MOVEMENT QUERIES
+    */
+
+    fn moves_on_empty_maybe(
+        &self,
+        from: Matter,
+        to: Matter,
+        opposite: Matter,
+        down: Matter,
+        p: f32,
+    ) -> bool {
+        p < 0.5
+            && self.push_constants.dispersion_step < from.dispersion
+            && ((is_liquid(from) && !is_empty(down)) || is_gas(from))
+            && is_empty(to)
+            && is_empty(opposite)
+    }
+
+    fn moves_on_empty_certainly(
+        &self,
+        from: Matter,
+        to: Matter,
+        opposite: Matter,
+        down: Matter,
+    ) -> bool {
+        self.push_constants.dispersion_step < from.dispersion
+            && ((is_liquid(from) && !is_empty(down)) || is_gas(from))
+            && is_empty(to)
+            && !is_empty(opposite)
+    }
+
+    fn moves_on_swap_maybe(&self, from: Matter, to: Matter, opposite: Matter, p: f32) -> bool {
+        p < 0.5
+            && self.push_constants.dispersion_step < from.dispersion
+            && (is_liquid(from) || is_gas(from))
+            && (is_liquid(to) || is_gas(to))
+            && (is_liquid(opposite) || is_gas(opposite))
+            && opposite.weight < from.weight
+            && to.weight < from.weight
+    }
+
+    fn moves_on_swap_certainly(&self, from: Matter, to: Matter, opposite: Matter) -> bool {
+        self.push_constants.dispersion_step < from.dispersion
+            && (is_liquid(from) || is_gas(from))
+            && (is_liquid(to) || is_gas(to))
+            && !(is_liquid(opposite) && opposite.weight < from.weight)
+            && to.weight < from.weight
+    }
+
+    /*
+    MATTER REACTION QUERIES
+    */
+
+    /// Does a neighbor in one of the direction bits have any of the characteristics? No
+    /// characteristics means the reaction needs no neighbor at all
+    fn reacts_with_neighbor(&self, pos: IVec2, characteristics: u32, direction: u32) -> bool {
+        if characteristics == 0 {
+            return true;
+        }
+        (0..8).any(|dir| {
+            direction & (1 << dir) != 0
+                && self.get_neighbor(pos, dir).characteristics & characteristics != 0
+        })
+    }
+}
+
+/*
+MATTER STATE QUERIES
+*/
+fn is_gas(matter: Matter) -> bool {
+    matter.state == MatterState::Gas as u32
+}
+
+/// Note: Compares the matter id against the empty *state*, exactly like the shader does
+fn is_empty(matter: Matter) -> bool {
+    matter.matter == MatterState::Empty as u32
+}
+
+fn is_powder(matter: Matter) -> bool {
+    matter.state == MatterState::Powder as u32
+}
+
+fn is_liquid(matter: Matter) -> bool {
+    matter.state == MatterState::Liquid as u32
+}
+
+fn is_solid_gravity(matter: Matter) -> bool {
+    matter.state == MatterState::SolidGravity as u32
+}
+
+fn is_gravity(matter: Matter) -> bool {
+    is_powder(matter) || is_liquid(matter) || is_solid_gravity(matter)
+}
+
+/*
+================== Empty ==================
+*/
+fn falls_on_empty(from: Matter, to: Matter) -> bool {
+    is_gravity(from) && is_empty(to)
+}
+
+fn slides_on_empty(from_diagonal: Matter, to_diagonal: Matter, from_down: Matter) -> bool {
+    is_powder(from_diagonal)
+        && !is_empty(from_down)
+        && !is_liquid(from_down)
+        && is_empty(to_diagonal)
+}
+
+fn rises_on_empty(from: Matter, to: Matter) -> bool {
+    is_gas(from) && is_empty(to)
+}
+
+/*
+================== Swap ==================
+*/
+fn falls_on_swap(from: Matter, to: Matter) -> bool {
+    is_gravity(from) && (is_liquid(to) || is_gas(to)) && to.weight < from.weight
+}
+
+fn slides_on_swap(from_diagonal: Matter, to_diagonal: Matter, from_down: Matter) -> bool {
+    is_powder(from_diagonal)
+        && !is_empty(from_down)
+        && !is_liquid(from_down)
+        && is_liquid(to_diagonal)
+        && to_diagonal.weight < from_diagonal.weight
+}
+
+fn rises_on_swap(from: Matter, to: Matter) -> bool {
+    is_gas(from) && (is_liquid(to) || is_powder(to)) && to.weight > from.weight
+}
+
+/*
+Kernels
+*/
+
+/// empty/fall_empty.glsl
+fn fall_empty_kernel(input: &KernelInput, pos: IVec2) -> Matter {
+    let current = input.read_matter(pos);
+    let up = input.get_neighbor(pos, UP);
+    let down = input.get_neighbor(pos, DOWN);
+    let mut m = current;
+    if !input.is_at_border_top(pos) && falls_on_empty(up, current) {
+        m = up;
+    } else if !input.is_at_border_bottom(pos) && falls_on_empty(current, down) {
+        m = down;
+    }
+    m
+}
+
+/// swap/fall_swap.glsl
+fn fall_swap_kernel(input: &KernelInput, pos: IVec2) -> Matter {
+    let current = input.read_matter(pos);
+    let up = input.get_neighbor(pos, UP);
+    let down = input.get_neighbor(pos, DOWN);
+    let mut m = current;
+    if !input.is_at_border_top(pos) && falls_on_swap(up, current) {
+        m = up;
+    } else if !input.is_at_border_bottom(pos) && falls_on_swap(current, down) {
+        m = down;
+    }
+    m
+}
+
+/// empty/rise_empty.glsl
+fn rise_empty_kernel(input: &KernelInput, pos: IVec2) -> Matter {
+    let current = input.read_matter(pos);
+    let up = input.get_neighbor(pos, UP);
+    let down = input.get_neighbor(pos, DOWN);
+    let mut m = current;
+    if !input.is_at_border_bottom(pos) && rises_on_empty(down, current) {
+        m = down;
+    } else if !input.is_at_border_top(pos) && rises_on_empty(current, up) {
+        m = up;
+    }
+    m
+}
+
+/// swap/rise_swap.glsl
+fn rise_swap_kernel(input: &KernelInput, pos: IVec2) -> Matter {
+    let current = input.read_matter(pos);
+    let up = input.get_neighbor(pos, UP);
+    let down = input.get_neighbor(pos, DOWN);
+    let mut m = current;
+    if !input.is_at_border_bottom(pos) && rises_on_swap(down, current) {
+        m = down;
+    } else if !input.is_at_border_top(pos) && rises_on_swap(current, up) {
+        m = up;
+    }
+    m
+}
+
+/// empty/slide_down_empty.glsl
+fn slide_down_empty_kernel(input: &KernelInput, pos: IVec2) -> Matter {
+    let current = input.read_matter(pos);
+    let down = input.get_neighbor(pos, DOWN);
+    let mut m = current;
+    if (input.push_constants.sim_steps + input.push_constants.move_step) % 2 == 0 {
+        let right = input.get_neighbor(pos, RIGHT);
+        let up_right = input.get_neighbor(pos, UP_RIGHT);
+        let down_left = input.get_neighbor(pos, DOWN_LEFT);
+        if !input.is_at_border_top(pos)
+            && !input.is_at_border_right(pos)
+            && slides_on_empty(up_right, current, right)
+        {
+            m = up_right;
+        } else if !input.is_at_border_bottom(pos)
+            && !input.is_at_border_left(pos)
+            && slides_on_empty(current, down_left, down)
+        {
+            m = down_left;
+        }
+    } else {
+        let left = input.get_neighbor(pos, LEFT);
+        let up_left = input.get_neighbor(pos, UP_LEFT);
+        let down_right = input.get_neighbor(pos, DOWN_RIGHT);
+        if !input.is_at_border_top(pos)
+            && !input.is_at_border_left(pos)
+            && slides_on_empty(up_left, current, left)
+        {
+            m = up_left;
+        } else if !input.is_at_border_bottom(pos)
+            && !input.is_at_border_right(pos)
+            && slides_on_empty(current, down_right, down)
+        {
+            m = down_right;
+        }
+    }
+    m
+}
+
+/// swap/slide_down_swap.glsl
+fn slide_down_swap_kernel(input: &KernelInput, pos: IVec2) -> Matter {
+    let current = input.read_matter(pos);
+    let down = input.get_neighbor(pos, DOWN);
+    let mut m = current;
+    if (input.push_constants.sim_steps + input.push_constants.move_step) % 2 == 0 {
+        let right = input.get_neighbor(pos, RIGHT);
+        let up_right = input.get_neighbor(pos, UP_RIGHT);
+        let down_left = input.get_neighbor(pos, DOWN_LEFT);
+        if !input.is_at_border_top(pos)
+            && !input.is_at_border_right(pos)
+            && slides_on_swap(up_right, current, right)
+        {
+            m = up_right;
+        } else if !input.is_at_border_bottom(pos)
+            && !input.is_at_border_left(pos)
+            && slides_on_swap(current, down_left, down)
+        {
+            m = down_left;
+        }
+    } else {
+        let left = input.get_neighbor(pos, LEFT);
+        let up_left = input.get_neighbor(pos, UP_LEFT);
+        let down_right = input.get_neighbor(pos, DOWN_RIGHT);
+        if !input.is_at_border_top(pos)
+            && !input.is_at_border_left(pos)
+            && slides_on_swap(up_left, current, left)
+        {
+            m = up_left;
+        } else if !input.is_at_border_bottom(pos)
+            && !input.is_at_border_right(pos)
+            && slides_on_swap(current, down_right, down)
+        {
+            m = down_right;
+        }
+    }
+    m
+}
+
+/// empty/horizontal_empty.glsl
+fn horizontal_empty_kernel(input: &KernelInput, pos: IVec2) -> Matter {
+    let seed = input.push_constants.seed;
+    let current = input.read_matter(pos);
+    let down = input.get_neighbor(pos, DOWN);
+    let right = input.get_neighbor(pos, RIGHT);
+    let left = input.get_neighbor(pos, LEFT);
+
+    let mut m = current;
+    if input.push_constants.dispersion_dir == 0 {
+        // Move left
+        let down_right = input.get_neighbor(pos, DOWN_RIGHT);
+        let right_right = input.get_neighbor(input.get_pos_at_dir(pos, RIGHT), RIGHT);
+        if !input.is_at_border_right(pos)
+            && input.moves_on_empty_certainly(right, current, right_right, down_right)
+        {
+            m = right;
+        } else if !input.is_at_border_left(pos)
+            && input.moves_on_empty_certainly(current, left, right, down)
+        {
+            m = left;
+        } else if !input.is_at_border_right(pos)
+            && input.moves_on_empty_maybe(
+                right,
+                current,
+                right_right,
+                down_right,
+                rand(input.get_pos_at_dir(pos, RIGHT), seed),
+            )
+        {
+            m = right;
+        } else if !input.is_at_border_left(pos)
+            && input.moves_on_empty_maybe(current, left, right, down, rand(pos, seed))
+        {
+            m = left;
+        }
+    } else {
+        // Move right
+        let down_left = input.get_neighbor(pos, DOWN_LEFT);
+        let left_left = input.get_neighbor(input.get_pos_at_dir(pos, LEFT), LEFT);
+        if !input.is_at_border_left(pos)
+            && input.moves_on_empty_certainly(left, current, left_left, down_left)
+        {
+            m = left;
+        } else if !input.is_at_border_right(pos)
+            && input.moves_on_empty_certainly(current, right, left, down)
+        {
+            m = right;
+        } else if !input.is_at_border_left(pos)
+            && input.moves_on_empty_maybe(
+                left,
+                current,
+                left_left,
+                down_left,
+                rand(input.get_pos_at_dir(pos, LEFT), seed),
+            )
+        {
+            m = left;
+        } else if !input.is_at_border_right(pos)
+            && input.moves_on_empty_maybe(current, right, left, down, rand(pos, seed))
+        {
+            m = right;
+        }
+    }
+    m
+}
+
+/// swap/horizontal_swap.glsl
+fn horizontal_swap_kernel(input: &KernelInput, pos: IVec2) -> Matter {
+    let seed = input.push_constants.seed;
+    let current = input.read_matter(pos);
+    let right = input.get_neighbor(pos, RIGHT);
+    let left = input.get_neighbor(pos, LEFT);
+
+    let mut m = current;
+    if input.push_constants.dispersion_dir == 0 {
+        // Move left
+        let right_right = input.get_neighbor(input.get_pos_at_dir(pos, RIGHT), RIGHT);
+        if !input.is_at_border_right(pos)
+            && input.moves_on_swap_certainly(right, current, right_right)
+        {
+            m = right;
+        } else if !input.is_at_border_left(pos)
+            && input.moves_on_swap_certainly(current, left, right)
+        {
+            m = left;
+        } else if !input.is_at_border_right(pos)
+            && input.moves_on_swap_maybe(
+                right,
+                current,
+                right_right,
+                rand(input.get_pos_at_dir(pos, RIGHT), seed),
+            )
+        {
+            m = right;
+        } else if !input.is_at_border_left(pos)
+            && input.moves_on_swap_maybe(current, left, right, rand(pos, seed))
+        {
+            m = left;
+        }
+    } else {
+        // Move right
+        let left_left = input.get_neighbor(input.get_pos_at_dir(pos, LEFT), LEFT);
+        if !input.is_at_border_left(pos) && input.moves_on_swap_certainly(left, current, left_left)
+        {
+            m = left;
+        } else if !input.is_at_border_right(pos)
+            && input.moves_on_swap_certainly(current, right, left)
+        {
+            m = right;
+        } else if !input.is_at_border_left(pos)
+            && input.moves_on_swap_maybe(
+                left,
+                current,
+                left_left,
+                rand(input.get_pos_at_dir(pos, LEFT), seed),
+            )
+        {
+            m = left;
+        } else if !input.is_at_border_right(pos)
+            && input.moves_on_swap_maybe(current, right, left, rand(pos, seed))
+        {
+            m = right;
+        }
+    }
+    m
+}
+
+/// query_matter.glsl. Only the invocation at `query_pos` writes a result.
+fn query_matter_kernel(input: &KernelInput) -> u32 {
+    matter_to_uint(input.read_matter(input.push_constants.query_pos))
+}
+
+/// census.glsl
+fn census_kernel(input: &KernelInput, pos: IVec2, census: &mut [u32]) {
+    census[input.read_matter(pos).matter as usize] += 1;
+}
+
+/// Count down lifetime, matter that runs out of it becomes empty
+fn age(input: &KernelInput, mut current: Matter) -> Matter {
+    let data = CellData::from(current.data);
+    match data.lifetime() {
+        0 => current,
+        1 => {
+            let mut m = input.new_matter(input.empty_matter);
+            // Keeps its heat
+            m.temperature = current.temperature;
+            m
+        }
+        lifetime => {
+            current.data = data.with_lifetime(lifetime - 1).value;
+            current
+        }
+    }
+}
+
+/// react.glsl
+fn react_kernel(input: &KernelInput, pos: IVec2) -> Matter {
+    let current = input.read_matter(pos);
+    let mut m = age(input, current);
+    for i in 0..MAX_TRANSITIONS {
+        let index = (current.matter * MAX_TRANSITIONS + i) as usize;
+        let probability = input.matter_reaction_probability[index];
+        if probability <= 0.0 {
+            continue;
+        }
+
+        let p = rand(pos, input.push_constants.seed + i as f32);
+        if p < probability
+            && input.reacts_with_neighbor(
+                pos,
+                input.matter_reaction_reacts[index],
+                input.matter_reaction_direction[index],
+            )
+        {
+            m = input.new_matter(input.matter_reaction_becomes[index]);
+            m.variation = variation_at(pos) as u32;
+            // Keeps its heat
+            m.temperature = current.temperature;
+            break;
+        }
+    }
+    m
+}
+
+/// heat.glsl
+fn heat_kernel(input: &KernelInput, pos: IVec2) -> Matter {
+    let mut current = input.read_matter(pos);
+    current.temperature = diffuse_heat(input, pos, current);
+    transition(input, pos, current)
+}
+
+/// Heat flows from & to the direct neighbors. Between two cells the lower conductivity limits the
+/// flow, heat capacity slows down how fast the temperature changes.
+fn diffuse_heat(input: &KernelInput, pos: IVec2, current: Matter) -> f32 {
+    let conductivity = input.matter_conductivity[current.matter as usize];
+    let flow: f32 = [UP, RIGHT, DOWN, LEFT]
+        .into_iter()
+        .map(|dir| {
+            let neighbor = input.get_neighbor(pos, dir);
+            conductivity.min(input.matter_conductivity[neighbor.matter as usize])
+                * (neighbor.temperature - current.temperature)
+        })
+        .sum();
+    current.temperature + 0.25 * flow / input.matter_heat_capacity[current.matter as usize]
+}
+
+/// Matter melts & boils at or above, freezes at or below its transition temperatures. The first
+/// transition that happens wins.
+fn transition(input: &KernelInput, pos: IVec2, current: Matter) -> Matter {
+    for i in 0..NUM_TEMPERATURE_TRANSITIONS {
+        let index = (current.matter * NUM_TEMPERATURE_TRANSITIONS + i) as usize;
+        let temperature = input.matter_transition_point[index];
+        let crossed = if i == TRANSITION_FREEZES {
+            current.temperature <= temperature
+        } else {
+            current.temperature >= temperature
+        };
+        if crossed {
+            let mut m = input.new_matter(input.matter_transition_becomes[index]);
+            m.variation = variation_at(pos) as u32;
+            // Keeps its heat
+            m.temperature = current.temperature;
+            return m;
+        }
+    }
+    current
+}
+
+/// color.glsl. Returns the `R8G8B8A8_UNORM` pixel written to the canvas image.
+fn color_kernel(input: &KernelInput, pos: IVec2) -> [u8; 4] {
+    let mut color = input.matter_color(input.read_matter(pos));
+    // Debug overlay, tint tiles the movement kernels ran on
+    let tile = tile_index(idx(pos, input.canvas_size), input.canvas_size);
+    if input.push_constants.show_awake_tiles && input.tile_awake[tile] != 0 {
+        for (c, tint) in color.iter_mut().zip(AWAKE_TILE_TINT) {
+            *c = *c * 0.7 + tint * 0.3;
+        }
+    }
+    let linear = [
+        linear_from_srgb(color[0] * 255.0),
+        linear_from_srgb(color[1] * 255.0),
+        linear_from_srgb(color[2] * 255.0),
+        color[3],
+    ];
+    linear.map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8)
+}
+
+/// 0-1 linear from 0-255 sRGB
+fn linear_from_srgb(srgb: f32) -> f32 {
+    if srgb < 10.31475 {
+        srgb / 3294.6
+    } else {
+        ((srgb + 14.025) / 269.025).powf(2.4)
+    }
+}
+
+/// draw_matter.glsl. Returns the matter written to `matter_in` & `temperature_in` at `pos`, if any.
+fn draw_matter_kernel(input: &KernelInput, pos: IVec2) -> Option<Matter> {
+    let push_constants = input.push_constants;
+
+    // 1. Get closest point on the line defined by start and end from push constants
+    let point_on_line = closest_point_on_line(
+        push_constants.draw_pos_start,
+        push_constants.draw_pos_end,
+        pos.as_vec2(),
+    );
+
+    let matter_at = input.read_matter(pos).matter;
+    let draw = input.new_matter(push_constants.draw_matter);
+
+    // Make sure we don't draw on top of other matter, unless we are erasing
+    if matter_at != input.empty_matter && draw.matter != input.empty_matter {
+        return None;
+    }
+
+    // 2. Draw matter at the closest point on line
+    let draw_pos = point_on_line.as_ivec2();
+    let inside = if push_constants.is_square {
+        let size = (push_constants.draw_radius * 2.0) as i32;
+        pos.x >= draw_pos.x - size / 2
+            && pos.x <= draw_pos.x + size / 2
+            && pos.y >= draw_pos.y - size / 2
+            && pos.y <= draw_pos.y + size / 2
+    } else {
+        let radius = push_constants.draw_radius;
+        pos.x >= draw_pos.x - radius as i32
+            && pos.x <= draw_pos.x + radius as i32
+            && pos.y >= draw_pos.y - radius as i32
+            && pos.y <= draw_pos.y + radius as i32
+            && (pos.as_vec2() - draw_pos.as_vec2()).length().round() <= radius
+    };
+    if !inside {
+        return None;
+    }
+
+    // 3. Vary color per cell
+    let mut matter = draw;
+    matter.variation = variation_at(pos) as u32;
+
+    // 4. write matter to input buffer
+    Some(matter)
+}
+
+/// emit.glsl. Sources fill empty cells, sinks delete their matter (any matter if theirs is empty),
+/// each cell with a chance of the emitter's rate per step.
+fn emit_kernel(input: &KernelInput, pos: IVec2) -> Option<Matter> {
+    let mut current = input.read_matter(pos);
+    let mut written = None;
+    for (i, emitter) in input
+        .emitters
+        .chunks_exact(EMITTER_STRIDE)
+        .take(input.push_constants.num_emitters as usize)
+        .enumerate()
+    {
+        let center = IVec2::new(emitter[0] as i32, emitter[1] as i32);
+        let radius = f32::from_bits(emitter[2]);
+        let rate = f32::from_bits(emitter[3]);
+        let matter = emitter[4];
+        let flags = emitter[5];
+        if !is_inside_emitter(pos, center, radius, flags & EMITTER_SQUARE != 0) {
+            continue;
+        }
+        // Offset from the seeds of react.glsl
+        if rand(pos, input.push_constants.seed + i as f32 + 0.5) >= rate {
+            continue;
+        }
+
+        let is_sink = flags & EMITTER_SINK != 0;
+        if is_sink
+            && !is_empty(current)
+            && (matter == input.empty_matter || current.matter == matter)
+        {
+            current = input.new_matter(input.empty_matter);
+        } else if !is_sink && is_empty(current) {
+            current = input.new_matter(matter);
+        } else {
+            continue;
+        }
+        current.variation = variation_at(pos) as u32;
+        written = Some(current);
+    }
+    written
+}
+
+/// Same shapes as `draw_matter_kernel`
+fn is_inside_emitter(pos: IVec2, center: IVec2, radius: f32, is_square: bool) -> bool {
+    let diff = (pos - center).abs();
+    if is_square {
+        let half_size = (radius * 2.0) as i32 / 2;
+        return diff.x <= half_size && diff.y <= half_size;
+    }
+    diff.as_vec2().length().round() <= radius
+}
+
+/// Matter cell as `draw_matter.glsl` writes it
+pub(crate) fn drawn_matter(pos: IVec2, matter: u32) -> u32 {
+    MatterCell::new(matter, variation_at(pos)).value
+}
+
+/// Color variation of a cell. Just use the same seed (means same variation for individual xy
+/// position)
+pub(crate) fn variation_at(pos: IVec2) -> u8 {
+    (rand(pos, 0.1) * 255.0) as u8
+}
+
+/// Randomize rgb a bit by a cell's color variation
+fn vary_color_rgb(color: [f32; 4], variation: u32) -> [f32; 4] {
+    let p = variation as f32 / 255.0;
+    let variation = -0.1 + 0.15 * p;
+    [
+        color[0] + variation,
+        color[1] + variation,
+        color[2] + variation,
+        color[3],
+    ]
+}
+
+/// sRGB color of a matter cell as `color.glsl` colors it, before converting to linear
+pub(crate) fn cell_color(matter: u32, matter_definitions: &MatterDefinitions) -> [u8; 4] {
+    let cell = MatterCell::from(matter);
+    let definition = &matter_definitions.definitions[cell.matter_id() as usize];
+    let mut color = matter_color_to_vec4(definition.color >> 8);
+    // Vary color only if not empty
+    if cell.matter_id() != matter_definitions.empty {
+        color = vary_color_rgb(color, cell.variation() as u32);
+    }
+    color.map(|c| (c.clamp(0.0, 1.0) * 255.0) as u8)
+}
+
+/// Line v->w, point p
+/// https://stackoverflow.com/questions/849211/shortest-distance-between-a-point-and-a-line-segment
+fn closest_point_on_line(v: Vec2, w: Vec2, p: Vec2) -> Vec2 {
+    let c = v - w;
+    // length squared
+    let l2 = c.dot(c);
+    if l2 == 0.0 {
+        return v;
+    }
+    let t = ((p - v).dot(w - v) / l2).clamp(0.0, 1.0);
+    v + t * (w - v)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matter::{default_matter_definitions, MATTER_EMPTY, MATTER_SAND, MATTER_WATER};
+
+    const CANVAS_SIZE: UVec2 = UVec2::new(KERNEL_SIZE, KERNEL_SIZE);
+
+    fn simulator() -> CPUSimulator {
+        let matter_definitions = default_matter_definitions();
+        let mut simulator = CPUSimulator::new(&matter_definitions, CANVAS_SIZE);
+        simulator.update_matter_data(&matter_definitions).unwrap();
+        simulator
+    }
+
+    fn run(simulator: &mut CPUSimulator, steps: u32) {
+        let settings = AppSettings {
+            seed: 1,
+            ..AppSettings::new()
+        };
+        for _ in 0..steps {
+            simulator.step(&settings);
+        }
+    }
+
+    fn place(simulator: &mut CPUSimulator, pos: UVec2, matter: u32) {
+        let cell = drawn_matter(pos.as_ivec2(), matter);
+        simulator
+            .write_matter_region(pos, UVec2::ONE, &[cell])
+            .unwrap();
+    }
+
+    fn matter_at(simulator: &mut CPUSimulator, pos: UVec2) -> u32 {
+        let cells = simulator.read_matter_region(pos, UVec2::ONE).unwrap();
+        MatterCell::from(cells[0]).matter_id()
+    }
+
+    /// Canvas positions of a matter, row by row
+    fn positions_of(simulator: &mut CPUSimulator, matter: u32) -> Vec<UVec2> {
+        let grid = simulator.read_matter_grid().unwrap();
+        grid.iter()
+            .enumerate()
+            .filter(|(_, &cell)| MatterCell::from(cell).matter_id() == matter)
+            .map(|(index, _)| pos_from_index(index, CANVAS_SIZE).as_uvec2())
+            .collect()
+    }
+
+    #[test]
+    fn sand_falls_to_the_floor() {
+        let mut simulator = simulator();
+        place(&mut simulator, UVec2::new(10, 20), MATTER_SAND);
+        run(&mut simulator, 40);
+        assert_eq!(positions_of(&mut simulator, MATTER_SAND), vec![UVec2::new(
+            10, 0
+        )]);
+    }
+
+    #[test]
+    fn sand_slides_off_sand() {
+        let mut simulator = simulator();
+        place(&mut simulator, UVec2::new(10, 0), MATTER_SAND);
+        place(&mut simulator, UVec2::new(10, 1), MATTER_SAND);
+        run(&mut simulator, 10);
+        let sand = positions_of(&mut simulator, MATTER_SAND);
+        assert_eq!(sand.len(), 2);
+        assert!(sand.contains(&UVec2::new(10, 0)));
+        assert!(sand.iter().all(|pos| pos.y == 0), "{:?}", sand);
+    }
+
+    #[test]
+    fn sand_sinks_through_water() {
+        let mut simulator = simulator();
+        place(&mut simulator, UVec2::new(10, 0), MATTER_WATER);
+        place(&mut simulator, UVec2::new(10, 1), MATTER_SAND);
+        run(&mut simulator, 20);
+        assert_eq!(matter_at(&mut simulator, UVec2::new(10, 0)), MATTER_SAND);
+        assert_eq!(positions_of(&mut simulator, MATTER_WATER).len(), 1);
+    }
+
+    #[test]
+    fn draw_matter_fills_empty_cells_in_radius() {
+        let mut simulator = simulator();
+        place(&mut simulator, UVec2::new(17, 16), MATTER_WATER);
+        let center = Vec2::new(16.0, 16.0);
+        simulator.draw_matter(center, center, MATTER_SAND, 2.0, false);
+
+        for pos in [UVec2::new(16, 16), UVec2::new(14, 16), UVec2::new(16, 18)] {
+            let cells = simulator.read_matter_region(pos, UVec2::ONE).unwrap();
+            assert_eq!(cells[0], drawn_matter(pos.as_ivec2(), MATTER_SAND));
+        }
+        // Outside the radius, or not empty
+        assert_eq!(matter_at(&mut simulator, UVec2::new(19, 16)), MATTER_EMPTY);
+        assert_eq!(matter_at(&mut simulator, UVec2::new(18, 18)), MATTER_EMPTY);
+        assert_eq!(matter_at(&mut simulator, UVec2::new(17, 16)), MATTER_WATER);
+
+        // Erasing draws over any matter, a square covers the corners
+        simulator.draw_matter(center, center, MATTER_EMPTY, 2.0, true);
+        assert!(positions_of(&mut simulator, MATTER_SAND).is_empty());
+        assert!(positions_of(&mut simulator, MATTER_WATER).is_empty());
+        simulator.draw_matter(center, center, MATTER_SAND, 1.0, true);
+        assert_eq!(matter_at(&mut simulator, UVec2::new(17, 17)), MATTER_SAND);
+        assert_eq!(positions_of(&mut simulator, MATTER_SAND).len(), 9);
+    }
+
+    #[test]
+    fn query_reads_matter_at_position() {
+        let mut simulator = simulator();
+        place(&mut simulator, UVec2::new(5, 5), MATTER_WATER);
+
+        simulator.request_query(IVec2::new(5, 5)).unwrap();
+        assert_eq!(
+            simulator.poll_query().unwrap(),
+            Some((IVec2::new(5, 5), MATTER_WATER))
+        );
+        assert_eq!(simulator.poll_query().unwrap(), None);
+
+        simulator.request_query(IVec2::new(6, 5)).unwrap();
+        assert_eq!(
+            simulator.poll_query().unwrap(),
+            Some((IVec2::new(6, 5), MATTER_EMPTY))
+        );
+
+        // Outside the canvas there's nothing to query
+        simulator.request_query(IVec2::new(-1, 5)).unwrap();
+        assert_eq!(simulator.poll_query().unwrap(), None);
+    }
+}
@@ -0,0 +1,69 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Most emitters in the world, the size of the kernels' emitter buffer
+pub const MAX_EMITTERS: usize = 256;
+/// Values per emitter in the emitter buffer, see `emit.glsl`
+pub const EMITTER_STRIDE: usize = 6;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EmitterKind {
+    /// Fills empty cells with its matter, e.g. a faucet
+    Source,
+    /// Deletes its matter, e.g. a drain. A sink of empty matter deletes any matter.
+    Sink,
+}
+
+/// A source or sink that stays in the world, applied at the start of every step
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Emitter {
+    pub kind: EmitterKind,
+    /// World cell at the center
+    pub pos: [i32; 2],
+    pub matter: u32,
+    /// Same shape as a brush of this radius
+    pub radius: f32,
+    pub is_square: bool,
+    /// Chance of each cell in the shape to be filled or emptied per step, 0 to 1
+    pub rate: f32,
+}
+
+impl Emitter {
+    pub fn world_pos(&self) -> IVec2 {
+        IVec2::from(self.pos)
+    }
+
+    /// Is a world cell inside the emitter's shape
+    pub fn contains(&self, world_cell: IVec2) -> bool {
+        let diff = world_cell - self.world_pos();
+        if self.is_square {
+            let half = (self.radius * 2.0) as i32 / 2;
+            diff.x.abs() <= half && diff.y.abs() <= half
+        } else {
+            diff.as_vec2().length().round() <= self.radius
+        }
+    }
+
+    /// Does any of the shape fall on a canvas at `canvas_origin` of `canvas_size`
+    pub fn overlaps_canvas(&self, canvas_origin: IVec2, canvas_size: UVec2) -> bool {
+        let reach = self.radius.ceil() as i32;
+        let min = self.world_pos() - reach - canvas_origin;
+        let max = self.world_pos() + reach - canvas_origin;
+        max.x >= 0 && max.y >= 0 && min.x < canvas_size.x as i32 && min.y < canvas_size.y as i32
+    }
+
+    /// As `emit.glsl` reads it: canvas position, radius, rate, matter and flags (bit 0 sink, bit 1
+    /// square)
+    pub fn to_kernel_data(&self, canvas_origin: IVec2) -> [u32; EMITTER_STRIDE] {
+        let canvas_pos = self.world_pos() - canvas_origin;
+        let flags = (self.kind == EmitterKind::Sink) as u32 | (self.is_square as u32) << 1;
+        [
+            canvas_pos.x as u32,
+            canvas_pos.y as u32,
+            self.radius.to_bits(),
+            self.rate.clamp(0.0, 1.0).to_bits(),
+            self.matter,
+            flags,
+        ]
+    }
+}
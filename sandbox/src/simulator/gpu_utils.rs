@@ -3,10 +3,11 @@ use std::sync::Arc;
 use anyhow::*;
 use vulkano::{
     buffer::{Buffer, BufferContents, BufferCreateInfo, BufferUsage, Subbuffer},
+    command_buffer::{CommandBufferExecFuture, DispatchIndirectCommand},
     memory::allocator::{AllocationCreateInfo, MemoryUsage, StandardMemoryAllocator},
+    sync::future::{FenceSignalFuture, NowFuture},
 };
 
-#[allow(unused)]
 pub fn empty_f32(
     allocator: &Arc<StandardMemoryAllocator>,
     size: usize,
@@ -14,7 +15,6 @@ pub fn empty_f32(
     empty_with(allocator, vec![0.0; size])
 }
 
-#[allow(unused)]
 pub fn empty_u32(
     allocator: &Arc<StandardMemoryAllocator>,
     size: usize,
@@ -31,7 +31,10 @@ where
     Ok(Buffer::from_iter(
         allocator,
         BufferCreateInfo {
-            usage: BufferUsage::STORAGE_BUFFER | BufferUsage::TRANSFER_DST,
+            // Transfers to copy & clear buffers on the device
+            usage: BufferUsage::STORAGE_BUFFER
+                | BufferUsage::TRANSFER_SRC
+                | BufferUsage::TRANSFER_DST,
             ..Default::default()
         },
         AllocationCreateInfo {
@@ -41,3 +44,147 @@ where
         iter,
     )?)
 }
+
+/// Dispatch size for `dispatch_indirect`, kernels can write it as a storage buffer
+pub fn indirect_dispatch(
+    allocator: &Arc<StandardMemoryAllocator>,
+    command: DispatchIndirectCommand,
+) -> Result<Subbuffer<[DispatchIndirectCommand]>> {
+    Ok(Buffer::from_iter(
+        allocator,
+        BufferCreateInfo {
+            usage: BufferUsage::STORAGE_BUFFER
+                | BufferUsage::INDIRECT_BUFFER
+                | BufferUsage::TRANSFER_SRC
+                | BufferUsage::TRANSFER_DST,
+            ..Default::default()
+        },
+        AllocationCreateInfo {
+            usage: MemoryUsage::Upload,
+            ..Default::default()
+        },
+        [command],
+    )?)
+}
+
+/// Host readable buffer to copy device data into
+pub fn empty_download_u8(
+    allocator: &Arc<StandardMemoryAllocator>,
+    size: usize,
+) -> Result<Subbuffer<[u8]>> {
+    Ok(Buffer::from_iter(
+        allocator,
+        BufferCreateInfo {
+            usage: BufferUsage::TRANSFER_DST,
+            ..Default::default()
+        },
+        AllocationCreateInfo {
+            usage: MemoryUsage::Download,
+            ..Default::default()
+        },
+        vec![0; size],
+    )?)
+}
+
+/// Host readable buffer to copy device data into
+pub fn empty_download_u32(
+    allocator: &Arc<StandardMemoryAllocator>,
+    size: usize,
+) -> Result<Subbuffer<[u32]>> {
+    Ok(Buffer::from_iter(
+        allocator,
+        BufferCreateInfo {
+            usage: BufferUsage::TRANSFER_DST,
+            ..Default::default()
+        },
+        AllocationCreateInfo {
+            usage: MemoryUsage::Download,
+            ..Default::default()
+        },
+        vec![0; size],
+    )?)
+}
+
+/// A submitted command buffer, signaled once the device finished it
+pub type SubmitFence = FenceSignalFuture<CommandBufferExecFuture<NowFuture>>;
+
+struct ReadbackSlot<T> {
+    buffer: Subbuffer<[u32]>,
+    /// What the buffer is being filled with, and the submission filling it until it finished
+    pending: Option<(Option<SubmitFence>, T)>,
+}
+
+/// Ring of download buffers that device data is copied into without waiting. Results are picked
+/// up a frame or two later, once the device finished the copy. `T` tags each readback, e.g. with
+/// the queried position.
+pub struct ReadbackRing<T> {
+    slots: Vec<ReadbackSlot<T>>,
+    next: usize,
+}
+
+impl<T> ReadbackRing<T> {
+    pub fn new(
+        allocator: &Arc<StandardMemoryAllocator>,
+        num_slots: usize,
+        len: usize,
+    ) -> Result<ReadbackRing<T>> {
+        let slots = (0..num_slots.max(1))
+            .map(|_| {
+                Ok(ReadbackSlot {
+                    buffer: empty_download_u32(allocator, len)?,
+                    pending: None,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(ReadbackRing {
+            slots,
+            next: 0,
+        })
+    }
+
+    /// Buffer to record the next copy into, `None` while all are in use
+    pub fn free_buffer(&self) -> Option<Subbuffer<[u32]>> {
+        let slot = &self.slots[self.next];
+        slot.pending.is_none().then(|| slot.buffer.clone())
+    }
+
+    /// The copy into `free_buffer` was submitted
+    pub fn submitted(&mut self, fence: SubmitFence, tag: T) {
+        self.slots[self.next].pending = Some((Some(fence), tag));
+        self.next = (self.next + 1) % self.slots.len();
+    }
+
+    /// Latest finished readback, older finished ones are dropped. Unfinished ones stay in flight.
+    pub fn poll_latest(&mut self) -> Result<Option<(T, Vec<u32>)>> {
+        let num_slots = self.slots.len();
+        let mut latest = None;
+        // Oldest first, starting from the slot that is written next
+        for i in 0..num_slots {
+            let slot = &mut self.slots[(self.next + i) % num_slots];
+            let Some((fence, _)) = &mut slot.pending else { continue };
+            if let Some(submitted) = fence {
+                if !submitted.is_signaled()? {
+                    break;
+                }
+                // Dropping the finished submission releases the buffers it used
+                *fence = None;
+            }
+            let (_, tag) = slot.pending.take().unwrap();
+            latest = Some((tag, slot.buffer.read()?.to_vec()));
+        }
+        Ok(latest)
+    }
+
+    /// Wait for the copies in flight, e.g. before the host writes a buffer they read from. Their
+    /// results can still be polled.
+    pub fn finish(&mut self) -> Result<()> {
+        for slot in &mut self.slots {
+            if let Some((fence, _)) = &mut slot.pending {
+                if let Some(submitted) = fence.take() {
+                    submitted.wait(None)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
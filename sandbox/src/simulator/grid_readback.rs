@@ -0,0 +1,67 @@
+use anyhow::Result;
+use bevy::prelude::*;
+use bevy_mod_sysfail::macros::*;
+
+use super::simulation::Simulation;
+
+/// When & where a matter grid copy was requested. Stored with the request, the canvas may be
+/// resized or moved before the copy arrives.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct GridCopyInfo {
+    /// Step count at the copy
+    pub sim_steps: u32,
+    /// Width & height of the copied grid
+    pub canvas_size: UVec2,
+    /// World cell of the grid's canvas position (0, 0)
+    pub canvas_origin: IVec2,
+}
+
+/// Cpu copy of the matter grid that systems can read without waiting on the simulation. It is
+/// copied back asynchronously while requested, lagging the simulation by a frame or two.
+#[derive(Resource, Default)]
+pub struct MatterGridReadback {
+    requested: bool,
+    grid: Vec<u32>,
+    info: GridCopyInfo,
+    num_copies: u64,
+}
+
+impl MatterGridReadback {
+    /// Keep the copy updated, request it every frame the grid is needed
+    pub fn request(&mut self) {
+        self.requested = true;
+    }
+
+    /// Matter cells row by row starting from y = 0, empty until the first copy arrived
+    pub fn grid(&self) -> &[u32] {
+        &self.grid
+    }
+
+    /// Step count, size & origin of the canvas the grid was copied from
+    pub fn info(&self) -> GridCopyInfo {
+        self.info
+    }
+
+    /// Number of copies that arrived so far. A higher count than before a request means a copy
+    /// arrived since.
+    pub fn num_copies(&self) -> u64 {
+        self.num_copies
+    }
+}
+
+/// Take the latest finished copy & start the next one while requested
+#[sysfail(log(level = "error"))]
+pub(super) fn read_back_matter_grid(
+    mut simulation: ResMut<Simulation>,
+    mut readback: ResMut<MatterGridReadback>,
+) -> Result<()> {
+    if let Some((info, grid)) = simulation.poll_matter_grid()? {
+        readback.grid = grid;
+        readback.info = info;
+        readback.num_copies += 1;
+    }
+    if std::mem::take(&mut readback.requested) {
+        simulation.request_matter_grid()?;
+    }
+    Ok(())
+}
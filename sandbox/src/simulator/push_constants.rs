@@ -0,0 +1,36 @@
+use bevy::{math::IVec2, prelude::Vec2};
+
+/// Values pushed to every compute kernel. Field for field the same as the `PushConstants` block in
+/// `compute_shaders/helpers/definition.glsl`, so both the gpu and cpu simulators can build one
+/// and feed it to their kernels.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct PushConstants {
+    pub seed: f32,
+    pub sim_steps: u32,
+    pub draw_pos_start: Vec2,
+    pub draw_pos_end: Vec2,
+    pub draw_radius: f32,
+    pub draw_matter: u32,
+    pub query_pos: IVec2,
+    pub dispersion_dir: u32,
+    pub move_step: u32,
+    pub dispersion_step: u32,
+    pub is_square: bool,
+    pub show_awake_tiles: bool,
+    /// See `Boundaries::to_push_constants`
+    pub boundaries: [u32; 4],
+    /// Emitters in the emitter buffer, see `SimulationBackend::set_emitters`
+    pub num_emitters: u32,
+}
+
+/// Seed pushed for a simulation step. Derived only from the user seed and the step count, so the
+/// same seed and the same inputs always produce the same grid. Lands in `[1, 1001)`: a seed of 0
+/// would make `rand` in `helpers/rand.glsl` constant.
+pub fn step_seed(seed: u32, sim_steps: u32) -> f32 {
+    // Murmur3 finalizer over the seed mixed with the step
+    let mut hash = seed ^ sim_steps.wrapping_mul(0x9e37_79b9);
+    hash = (hash ^ (hash >> 16)).wrapping_mul(0x85eb_ca6b);
+    hash = (hash ^ (hash >> 13)).wrapping_mul(0xc2b2_ae35);
+    hash ^= hash >> 16;
+    1.0 + (hash >> 8) as f32 / (1 << 24) as f32 * 1000.0
+}
@@ -1,45 +1,104 @@
-use std::sync::Arc;
+use std::{path::PathBuf, sync::Arc};
 
-use anyhow::Result;
+use anyhow::{ensure, Result};
 use bevy::prelude::*;
 use vulkano::{device::Queue, memory::allocator::StandardMemoryAllocator};
 use vulkano_util::renderer::DeviceImageView;
 
-use super::ca_simulator::CASimulator;
+use super::{
+    backend::{CellGrids, SimulationBackend, SimulationBackendKind},
+    ca_simulator::CASimulator,
+    chunks::{chunk_pos, round_to_chunks, ChunkStore, CHUNK_SIZE},
+    cpu_simulator::CPUSimulator,
+    emitters::{Emitter, MAX_EMITTERS},
+    grid_readback::GridCopyInfo,
+};
 use crate::{
-    matter::matter_definition::MatterDefinitions, settings::AppSettings,
+    fs_interaction::snapshot::WorldSnapshot,
+    matter::matter_definition::MatterDefinitions,
+    settings::AppSettings,
     time::performance_timer::PerformanceTimer,
+    utils::{is_inside_sim_canvas, round_canvas_size},
 };
 
 #[derive(Resource)]
 pub struct Simulation {
-    ca_simulator: CASimulator,
+    backend: Box<dyn SimulationBackend>,
+    /// World cell of canvas position (0, 0). The canvas is centered at the world origin unless
+    /// streaming moves it.
+    canvas_origin: IVec2,
+    /// The world outside the canvas, if streaming
+    chunks: Option<ChunkStore>,
+    emitters: Vec<Emitter>,
+    /// Emitters or the canvas changed since they were last handed to the backend
+    emitters_changed: bool,
+    /// Canvas position & matter of the latest finished query
+    last_query: Option<(IVec2, u32)>,
     pub ca_timer: PerformanceTimer,
 }
 
 impl Simulation {
     pub fn new(
+        backend_kind: SimulationBackendKind,
         allocator: &Arc<StandardMemoryAllocator>,
         compute_queue: Arc<Queue>,
         matter_definitions: &MatterDefinitions,
+        canvas_size: UVec2,
+    ) -> Result<Simulation> {
+        let canvas_size = round_canvas_size(canvas_size);
+        let backend: Box<dyn SimulationBackend> = match backend_kind {
+            SimulationBackendKind::Gpu => Box::new(CASimulator::new(
+                allocator,
+                compute_queue,
+                matter_definitions,
+                canvas_size,
+            )?),
+            SimulationBackendKind::Cpu => {
+                Box::new(CPUSimulator::new(matter_definitions, canvas_size))
+            }
+        };
+
+        Simulation::from_backend(backend, matter_definitions)
+    }
+
+    /// Simulation on a backend created elsewhere, e.g. by a headless run
+    pub fn from_backend(
+        mut backend: Box<dyn SimulationBackend>,
+        matter_definitions: &MatterDefinitions,
     ) -> Result<Simulation> {
-        let mut ca_simulator = CASimulator::new(allocator, compute_queue, matter_definitions)?;
-        ca_simulator.update_matter_data(matter_definitions)?;
+        backend.update_matter_data(matter_definitions)?;
 
         Ok(Simulation {
-            ca_simulator,
+            canvas_origin: centered_origin(backend.canvas_size()),
+            backend,
+            chunks: None,
+            emitters: vec![],
+            emitters_changed: false,
+            last_query: None,
             ca_timer: PerformanceTimer::default(),
         })
     }
 
-    pub fn canvas_image(&self) -> DeviceImageView {
-        self.ca_simulator.color_image()
+    /// Device image of the canvas colors, `None` if the backend only has them on the host
+    pub fn canvas_image(&self) -> Option<DeviceImageView> {
+        self.backend.color_image()
+    }
+
+    /// Canvas colors as `R8G8B8A8_UNORM` bytes
+    pub fn read_color_image(&self) -> Result<Vec<u8>> {
+        self.backend.read_color_image()
     }
 
-    pub fn step(&mut self, settings: &AppSettings) {
+    pub fn step(&mut self, settings: &AppSettings) -> Result<()> {
+        if self.emitters_changed {
+            self.upload_emitters()?;
+        }
+
         self.ca_timer.start();
-        self.ca_simulator.step(settings);
+        self.backend.step(settings);
         self.ca_timer.time_it();
+
+        Ok(())
     }
 
     pub fn paint_round(
@@ -50,12 +109,415 @@ impl Simulation {
         radius: f32,
         is_square: bool,
     ) -> Result<()> {
-        self.ca_simulator
+        self.backend
             .draw_matter(start, end, matter, radius, is_square);
         Ok(())
     }
 
-    pub fn query_matter(&mut self, pos: IVec2) -> Option<u32> {
-        self.ca_simulator.query_matter(pos)
+    /// Sources & sinks, in the order they're applied
+    pub fn emitters(&self) -> &[Emitter] {
+        &self.emitters
+    }
+
+    pub fn add_emitter(&mut self, emitter: Emitter) -> Result<()> {
+        ensure!(
+            self.emitters.len() < MAX_EMITTERS,
+            "Can't add more than {} emitters",
+            MAX_EMITTERS
+        );
+        self.emitters.push(emitter);
+        self.emitters_changed = true;
+        Ok(())
+    }
+
+    pub fn remove_emitter(&mut self, index: usize) {
+        self.emitters.remove(index);
+        self.emitters_changed = true;
+    }
+
+    /// Remove the most recently added emitter covering a world cell, if any
+    pub fn remove_emitter_at(&mut self, world_cell: IVec2) -> bool {
+        if let Some(index) = self.emitters.iter().rposition(|e| e.contains(world_cell)) {
+            self.remove_emitter(index);
+            return true;
+        }
+        false
+    }
+
+    pub fn clear_emitters(&mut self) {
+        self.emitters.clear();
+        self.emitters_changed = true;
+    }
+
+    /// Hand the emitters on the canvas to the backend, in canvas positions. All of them are
+    /// applied in one kernel dispatch per step.
+    fn upload_emitters(&mut self) -> Result<()> {
+        let canvas_size = self.canvas_size();
+        let emitters = self
+            .emitters
+            .iter()
+            .filter(|emitter| emitter.overlaps_canvas(self.canvas_origin, canvas_size))
+            .flat_map(|emitter| emitter.to_kernel_data(self.canvas_origin))
+            .collect::<Vec<_>>();
+        self.backend.set_emitters(&emitters)?;
+        self.emitters_changed = false;
+        Ok(())
+    }
+
+    /// Matter at a canvas position as of a frame or two ago, the simulation isn't waited on. Keep
+    /// calling it with the same position (e.g. the hovered cell) to follow it.
+    pub fn query_matter(&mut self, pos: IVec2) -> Result<Option<u32>> {
+        if let Some(query) = self.backend.poll_query()? {
+            self.last_query = Some(query);
+        }
+        if !is_inside_sim_canvas(pos, self.canvas_size()) {
+            return Ok(None);
+        }
+        self.backend.request_query(pos)?;
+        Ok(self.last_query.map(|(_, matter)| matter))
+    }
+
+    /// Matter grid as of now, waits for the simulation. See `MatterGridReadback` for a copy that
+    /// doesn't.
+    pub fn read_matter_grid(&mut self) -> Result<Vec<u32>> {
+        self.backend.read_matter_grid()
+    }
+
+    /// Matter of the canvas rectangle from `min` with `size` as of now, row by row starting from
+    /// `min`. Waits for the simulation like `read_matter_grid`.
+    pub fn read_matter_region(&mut self, min: UVec2, size: UVec2) -> Result<Vec<u32>> {
+        self.ensure_inside_canvas(min, size)?;
+        self.backend.read_matter_region(min, size)
+    }
+
+    /// Start copying the matter grid without waiting, see `poll_matter_grid`. Ignored while an
+    /// earlier copy is in flight.
+    pub fn request_matter_grid(&mut self) -> Result<()> {
+        self.backend.request_matter_grid(self.canvas_origin)
+    }
+
+    /// Where & when the copy was requested & matter grid of the latest finished
+    /// `request_matter_grid`
+    pub fn poll_matter_grid(&mut self) -> Result<Option<(GridCopyInfo, Vec<u32>)>> {
+        self.backend.poll_matter_grid()
+    }
+
+    /// Start counting the cells of each matter without waiting, see `poll_census`
+    pub fn request_census(&mut self) -> Result<()> {
+        self.backend.request_census()
+    }
+
+    /// Step count at the census & cells per matter id of the latest finished `request_census`
+    pub fn poll_census(&mut self) -> Result<Option<(u32, Vec<u32>)>> {
+        self.backend.poll_census()
+    }
+
+    /// Readbacks taken before the canvas moved don't match it anymore
+    fn discard_readbacks(&mut self) -> Result<()> {
+        self.backend.poll_query()?;
+        self.backend.poll_matter_grid()?;
+        self.last_query = None;
+        Ok(())
+    }
+
+    pub fn write_matter_grid(&mut self, grid: &[u32]) -> Result<()> {
+        let canvas_size = self.canvas_size();
+        let num_cells = (canvas_size.x * canvas_size.y) as usize;
+        ensure!(
+            grid.len() == num_cells,
+            "Matter grid has {} cells, expected {}",
+            grid.len(),
+            num_cells
+        );
+        self.backend.write_matter_grid(grid)
+    }
+
+    /// Overwrite the matter of a canvas rectangle, same layout as `read_matter_region`
+    pub fn write_matter_region(&mut self, min: UVec2, size: UVec2, cells: &[u32]) -> Result<()> {
+        self.ensure_inside_canvas(min, size)?;
+        let num_cells = (size.x * size.y) as usize;
+        ensure!(
+            cells.len() == num_cells,
+            "Matter region has {} cells, expected {}",
+            cells.len(),
+            num_cells
+        );
+        self.backend.write_matter_region(min, size, cells)
+    }
+
+    fn ensure_inside_canvas(&self, min: UVec2, size: UVec2) -> Result<()> {
+        ensure!(
+            (min + size).cmple(self.canvas_size()).all(),
+            "Region at {} of size {} is outside the {} canvas",
+            min,
+            size,
+            self.canvas_size()
+        );
+        Ok(())
+    }
+
+    pub fn sim_steps(&self) -> u32 {
+        self.backend.sim_steps()
+    }
+
+    pub fn set_sim_steps(&mut self, sim_steps: u32) {
+        self.backend.set_sim_steps(sim_steps)
+    }
+
+    pub fn canvas_size(&self) -> UVec2 {
+        self.backend.canvas_size()
+    }
+
+    pub fn canvas_origin(&self) -> IVec2 {
+        self.canvas_origin
+    }
+
+    /// World position the canvas is centered at
+    pub fn canvas_center(&self) -> Vec2 {
+        self.canvas_origin.as_vec2() + self.canvas_size().as_vec2() / 2.0
+    }
+
+    /// The world outside the canvas, if streaming
+    pub fn chunks(&self) -> Option<&ChunkStore> {
+        self.chunks.as_ref()
+    }
+
+    /// Make the world unbounded. The canvas is resized to whole chunks and moved onto the chunk
+    /// grid, from then on `stream_to` moves it through the world.
+    pub fn enable_streaming(
+        &mut self,
+        chunk_dir: impl Into<PathBuf>,
+        matter_definitions: &MatterDefinitions,
+    ) -> Result<()> {
+        self.chunks = Some(ChunkStore::new(chunk_dir)?);
+        let canvas_size = round_to_chunks(self.canvas_size());
+        self.relocate(
+            chunk_aligned_origin(self.canvas_center(), canvas_size),
+            canvas_size,
+            matter_definitions,
+        )
+    }
+
+    /// Move the canvas by whole chunks once `center` (e.g. the camera) is more than a chunk away
+    /// from the canvas center. Does nothing unless streaming.
+    pub fn stream_to(
+        &mut self,
+        center: Vec2,
+        matter_definitions: &MatterDefinitions,
+    ) -> Result<()> {
+        if self.chunks.is_none()
+            || (center - self.canvas_center()).abs().max_element() <= CHUNK_SIZE as f32
+        {
+            return Ok(());
+        }
+        let canvas_size = self.canvas_size();
+        self.relocate(
+            chunk_aligned_origin(center, canvas_size),
+            canvas_size,
+            matter_definitions,
+        )
+    }
+
+    /// Page the canvas' chunks out, then page in the chunks of the canvas at `origin` with
+    /// `canvas_size`. Both must be whole chunks.
+    fn relocate(
+        &mut self,
+        origin: IVec2,
+        canvas_size: UVec2,
+        matter_definitions: &MatterDefinitions,
+    ) -> Result<()> {
+        let Some(mut chunks) = self.chunks.take() else { return Ok(()) };
+        let result = self.relocate_chunks(&mut chunks, origin, canvas_size, matter_definitions);
+        self.chunks = Some(chunks);
+        result
+    }
+
+    /// Paged out chunks are frozen and don't exchange border cells with the canvas. Its edges stay
+    /// the configured boundaries, so matter reaching them never enters the stored chunk beyond,
+    /// nor does stored matter flow in, until the canvas moves over it.
+    fn relocate_chunks(
+        &mut self,
+        chunks: &mut ChunkStore,
+        origin: IVec2,
+        canvas_size: UVec2,
+        matter_definitions: &MatterDefinitions,
+    ) -> Result<()> {
+        let old_size = self.canvas_size();
+        let chunk_size = UVec2::splat(CHUNK_SIZE);
+        for chunk_min in chunk_mins(old_size) {
+            let cells = self.backend.read_cells(chunk_min, chunk_size)?;
+            let chunk = chunk_pos(self.canvas_origin + chunk_min.as_ivec2());
+            chunks.page_out(chunk, cells, matter_definitions.empty)?;
+        }
+
+        if canvas_size != old_size {
+            self.backend.resize(canvas_size)?;
+        }
+
+        for chunk_min in chunk_mins(canvas_size) {
+            let cells = match chunks.page_in(chunk_pos(origin + chunk_min.as_ivec2()))? {
+                Some(cells) => cells,
+                None => CellGrids::empty(chunk_min, chunk_size, matter_definitions),
+            };
+            self.backend.write_cells(chunk_min, chunk_size, &cells)?;
+        }
+        self.discard_readbacks()?;
+        self.canvas_origin = origin;
+        self.emitters_changed = true;
+        Ok(())
+    }
+
+    /// Change the canvas size (rounded up to whole kernels). The cells keep the part that still
+    /// fits, anchored at the bottom left, new cells are empty. When streaming, the size is
+    /// rounded up to whole chunks and the canvas keeps its center in the world instead.
+    pub fn resize(
+        &mut self,
+        canvas_size: UVec2,
+        matter_definitions: &MatterDefinitions,
+    ) -> Result<()> {
+        if self.chunks.is_some() {
+            let canvas_size = round_to_chunks(canvas_size);
+            if canvas_size == self.canvas_size() {
+                return Ok(());
+            }
+            return self.relocate(
+                chunk_aligned_origin(self.canvas_center(), canvas_size),
+                canvas_size,
+                matter_definitions,
+            );
+        }
+
+        let canvas_size = round_canvas_size(canvas_size);
+        let old_size = self.canvas_size();
+        if canvas_size == old_size {
+            return Ok(());
+        }
+
+        let kept_size = old_size.min(canvas_size);
+        let kept_cells = self.backend.read_cells(UVec2::ZERO, kept_size)?;
+        self.backend.resize(canvas_size)?;
+        self.discard_readbacks()?;
+        self.canvas_origin = centered_origin(canvas_size);
+        self.emitters_changed = true;
+
+        let empty_cells = CellGrids::empty(UVec2::ZERO, canvas_size, matter_definitions);
+        self.backend
+            .write_cells(UVec2::ZERO, canvas_size, &empty_cells)?;
+        self.backend
+            .write_cells(UVec2::ZERO, kept_size, &kept_cells)
+    }
+
+    /// Capture the cells, emitters & step counter, e.g. to save them to disk. When streaming, only
+    /// the canvas (and every emitter) is captured.
+    pub fn snapshot(
+        &mut self,
+        seed: u32,
+        matter_definitions: &MatterDefinitions,
+    ) -> Result<WorldSnapshot> {
+        Ok(WorldSnapshot {
+            canvas_size: self.canvas_size().to_array(),
+            sim_steps: self.sim_steps(),
+            seed,
+            matter_definitions: matter_definitions.clone(),
+            cells: self.backend.read_cells(UVec2::ZERO, self.canvas_size())?,
+            emitters: self.emitters.clone(),
+        })
+    }
+
+    /// Continue from a snapshot. Matter ids are remapped by name to the current definitions and
+    /// the canvas is resized to the snapshot's size. The snapshot's emitters replace ours.
+    /// Snapshots only hold the canvas, so they can't be loaded while streaming.
+    pub fn load_snapshot(
+        &mut self,
+        mut snapshot: WorldSnapshot,
+        matter_definitions: &MatterDefinitions,
+    ) -> Result<()> {
+        ensure!(
+            self.chunks.is_none(),
+            "Snapshots can't be loaded into an unbounded world, they would replace the world \
+             outside the canvas. Turn streaming off to load them."
+        );
+        let canvas_size = UVec2::from(snapshot.canvas_size);
+        ensure!(
+            round_canvas_size(canvas_size) == canvas_size,
+            "Snapshot canvas size {} is not a multiple of the kernel size",
+            canvas_size
+        );
+        ensure!(
+            snapshot.cells.num_cells() == Some((canvas_size.x * canvas_size.y) as usize),
+            "Snapshot cells don't fill its {} canvas",
+            canvas_size
+        );
+        self.resize(canvas_size, matter_definitions)?;
+
+        let missing = snapshot.remap_matters(matter_definitions);
+        if !missing.is_empty() {
+            warn!(
+                "Snapshot matters not in current definitions became empty: {:?}",
+                missing
+            );
+        }
+
+        self.backend
+            .write_cells(UVec2::ZERO, canvas_size, &snapshot.cells)?;
+        self.set_sim_steps(snapshot.sim_steps);
+        self.emitters = snapshot.emitters;
+        self.emitters_changed = true;
+        Ok(())
+    }
+
+    /// Use changed matter definitions. Ids must stay the same, see `remap_matters` otherwise.
+    pub fn update_matter_data(&mut self, matter_definitions: &MatterDefinitions) -> Result<()> {
+        self.backend.update_matter_data(matter_definitions)
     }
+
+    /// Move the matter of the world to its id in `id_map` (e.g. after a matter definition was
+    /// removed), matter without one becomes empty. Emitters of such matter are dropped.
+    pub fn remap_matters(
+        &mut self,
+        id_map: &[Option<u32>],
+        matter_definitions: &MatterDefinitions,
+    ) -> Result<()> {
+        self.update_matter_data(matter_definitions)?;
+        let canvas_size = self.canvas_size();
+        let mut cells = self.backend.read_cells(UVec2::ZERO, canvas_size)?;
+        cells.remap_matters(id_map, matter_definitions);
+        self.backend.write_cells(UVec2::ZERO, canvas_size, &cells)?;
+        if let Some(chunks) = &mut self.chunks {
+            chunks.remap_matters(id_map, matter_definitions)?;
+        }
+        self.discard_readbacks()?;
+
+        self.emitters.retain_mut(|emitter| {
+            let id = id_map.get(emitter.matter as usize).copied().flatten();
+            emitter.matter = id.unwrap_or(matter_definitions.empty);
+            id.is_some()
+        });
+        self.emitters_changed = true;
+        Ok(())
+    }
+
+    /// Restart step counting, so per step seeds repeat from the first step
+    pub fn reset_clock(&mut self) {
+        self.set_sim_steps(0)
+    }
+}
+
+/// Canvas origin that centers the canvas at the world origin
+fn centered_origin(canvas_size: UVec2) -> IVec2 {
+    -(canvas_size / 2).as_ivec2()
+}
+
+/// Canvas origin on the chunk grid closest to centering the canvas at `center`
+fn chunk_aligned_origin(center: Vec2, canvas_size: UVec2) -> IVec2 {
+    let chunk_size = CHUNK_SIZE as f32;
+    let origin = center - canvas_size.as_vec2() / 2.0;
+    ((origin / chunk_size).round() * chunk_size).as_ivec2()
+}
+
+/// Canvas positions of the bottom left cells of a canvas' chunks
+fn chunk_mins(canvas_size: UVec2) -> impl Iterator<Item = UVec2> {
+    let num_chunks = canvas_size / CHUNK_SIZE;
+    (0..num_chunks.y)
+        .flat_map(move |y| (0..num_chunks.x).map(move |x| UVec2::new(x, y) * CHUNK_SIZE))
 }
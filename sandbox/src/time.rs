@@ -1,16 +1,18 @@
 pub mod performance_timer;
 
-use bevy::prelude::Resource;
+use bevy::prelude::*;
 use bevy_fn_plugin::bevy_plugin;
 
 use self::performance_timer::PerformanceTimer;
+use crate::{settings::AppSettings, simulator::simulation::Simulation, GameState};
 
 const NUM_TIME_SAMPLES: usize = 150;
 
 #[bevy_plugin]
 pub fn TimerPlugin(app: &mut App) {
     app.init_resource::<SimulationTimer>()
-        .init_resource::<RenderTimer>();
+        .init_resource::<RenderTimer>()
+        .add_system(print_performance.run_if(in_state(GameState::Simulating)));
 }
 
 #[derive(Resource, Default)]
@@ -18,3 +20,31 @@ pub struct SimulationTimer(pub PerformanceTimer);
 
 #[derive(Resource, Default)]
 pub struct RenderTimer(pub PerformanceTimer);
+
+pub struct PrintTimer(Timer);
+
+impl Default for PrintTimer {
+    fn default() -> Self {
+        Self(Timer::from_seconds(1.0, TimerMode::Repeating))
+    }
+}
+
+/// Log the time averages every second while `AppSettings::print_performance` is on
+fn print_performance(
+    time: Res<Time>,
+    mut timer: Local<PrintTimer>,
+    settings: Res<AppSettings>,
+    simulation: Res<Simulation>,
+    render_timer: Res<RenderTimer>,
+    sim_timer: Res<SimulationTimer>,
+) {
+    if !settings.print_performance || !timer.0.tick(time.delta()).just_finished() {
+        return;
+    }
+    info!(
+        "Render: {:.3} ms, simulation: {:.3} ms, CA simulation: {:.3} ms",
+        render_timer.0.time_average_ms(),
+        sim_timer.0.time_average_ms(),
+        simulation.ca_timer.time_average_ms()
+    );
+}
@@ -1,10 +1,6 @@
 use bevy::{prelude::*, window::PrimaryWindow};
 use bevy_vulkano::{BevyVulkanoWindows, VulkanoWindow};
 
-use crate::{
-    fs_interaction::FileUtils, matter::matter_definition::MatterDefinitions, SIM_CANVAS_SIZE,
-};
-
 pub fn get_primary_window<'a>(
     window_query: &'a Query<Entity, With<PrimaryWindow>>,
     vulkan_windows: &'a NonSend<BevyVulkanoWindows>,
@@ -29,16 +25,7 @@ pub fn get_primary_window_mut<'a>(
     }
 }
 
-pub fn world_pos_to_canvas_pos(world_pos: Vec2) -> Vec2 {
-    world_pos + Vec2::new(SIM_CANVAS_SIZE as f32 / 2.0, SIM_CANVAS_SIZE as f32 / 2.0)
-}
-
-pub fn read_matter_definitions_file(path: &str) -> Option<MatterDefinitions> {
-    match FileUtils::read_ron::<MatterDefinitions>(path) {
-        Ok(matter_definitions) => Some(matter_definitions),
-        Err(e) => {
-            log::warn!("Error reading matter definitions: {}", e);
-            None
-        }
-    }
+/// `canvas_origin` is the world cell of canvas position (0, 0), see `Simulation::canvas_origin`
+pub fn world_pos_to_canvas_pos(world_pos: Vec2, canvas_origin: IVec2) -> Vec2 {
+    world_pos - canvas_origin.as_vec2()
 }
@@ -1,12 +1,60 @@
-use bevy::prelude::IVec2;
+use std::ops::Range;
 
-use crate::SIM_CANVAS_SIZE;
+use bevy::prelude::{IVec2, UVec2};
+
+use crate::KERNEL_SIZE;
 
 // /// Index to access our one dimensional grid with two dimensional position
-pub fn idx(pos: IVec2) -> usize {
-    (pos.y * SIM_CANVAS_SIZE as i32 + pos.x) as usize
+pub fn idx(pos: IVec2, canvas_size: UVec2) -> usize {
+    (pos.y * canvas_size.x as i32 + pos.x) as usize
+}
+
+pub fn is_inside_sim_canvas(pos: IVec2, canvas_size: UVec2) -> bool {
+    pos.x >= 0 && pos.x < canvas_size.x as i32 && pos.y >= 0 && pos.y < canvas_size.y as i32
+}
+
+/// Index ranges of the rows of a canvas rectangle in our one dimensional grid, from y = `min.y` up
+pub fn region_rows(
+    canvas_size: UVec2,
+    min: UVec2,
+    size: UVec2,
+) -> impl Iterator<Item = Range<usize>> {
+    (min.y..min.y + size.y).map(move |y| {
+        let start = (y * canvas_size.x + min.x) as usize;
+        start..start + size.x as usize
+    })
+}
+
+/// Cells of a canvas rectangle of a grid, row by row starting from `min`
+pub fn read_region<T: Copy>(grid: &[T], canvas_size: UVec2, min: UVec2, size: UVec2) -> Vec<T> {
+    region_rows(canvas_size, min, size)
+        .flat_map(|row| grid[row].iter().copied())
+        .collect()
+}
+
+/// Overwrite the cells of a canvas rectangle of a grid, same layout as `read_region`
+pub fn write_region<T: Copy>(
+    grid: &mut [T],
+    canvas_size: UVec2,
+    min: UVec2,
+    size: UVec2,
+    cells: &[T],
+) {
+    let rows = region_rows(canvas_size, min, size);
+    for (row, cells) in rows.zip(cells.chunks_exact(size.x as usize)) {
+        grid[row].copy_from_slice(cells);
+    }
+}
+
+/// Index of the tile (one kernel) a cell index of our one dimensional grid falls in
+pub fn tile_index(index: usize, canvas_size: UVec2) -> usize {
+    let width = canvas_size.x as usize;
+    let tile_size = KERNEL_SIZE as usize;
+    (index / width / tile_size) * (width / tile_size) + (index % width) / tile_size
 }
 
-pub fn is_inside_sim_canvas(pos: IVec2) -> bool {
-    pos.x >= 0 && pos.x < SIM_CANVAS_SIZE as i32 && pos.y >= 0 && pos.y < SIM_CANVAS_SIZE as i32
+/// Round a canvas size up to whole kernels, so no pixel remains unsimulated
+pub fn round_canvas_size(canvas_size: UVec2) -> UVec2 {
+    let round = |size: u32| (size.max(1) + KERNEL_SIZE - 1) / KERNEL_SIZE * KERNEL_SIZE;
+    UVec2::new(round(canvas_size.x), round(canvas_size.y))
 }